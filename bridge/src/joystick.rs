@@ -0,0 +1,141 @@
+//! The virtual joystick, and which evdev codes the gamepad's buttons and stick become.
+
+use std::io;
+
+use evdev::{
+    uinput::VirtualDevice, AbsInfo, AbsoluteAxisCode, AbsoluteAxisEvent, AttributeSet, BusType,
+    InputEvent, InputId, KeyCode, KeyEvent, UinputAbsSetup,
+};
+use gamepad_protocol::{value::AXIS_MAX, AxisId, ButtonId, Notification};
+
+const NAME: &str = "micro:bit BLE gamepad";
+
+/// Buttons in the kernel's gamepad layout, A and B are the face buttons under the thumb
+pub fn key(button: ButtonId) -> KeyCode {
+    match button {
+        ButtonId::A => KeyCode::BTN_SOUTH,
+        ButtonId::B => KeyCode::BTN_EAST,
+        ButtonId::C => KeyCode::BTN_WEST,
+        ButtonId::D => KeyCode::BTN_NORTH,
+        ButtonId::E => KeyCode::BTN_SELECT,
+        ButtonId::F => KeyCode::BTN_START,
+    }
+}
+
+/// Stick levels are sent as they are, negative y is up for both the gamepad and evdev
+pub fn axis(axis: AxisId) -> AbsoluteAxisCode {
+    match axis {
+        AxisId::X => AbsoluteAxisCode::ABS_X,
+        AxisId::Y => AbsoluteAxisCode::ABS_Y,
+    }
+}
+
+/// What a notification does to the joystick
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Key(KeyCode, bool),
+    Axis(AbsoluteAxisCode, i32),
+}
+
+impl Event {
+    /// `None` for the notifications a joystick has nothing for, such as gestures
+    pub fn from_notification(notification: Notification) -> Option<Self> {
+        match notification {
+            Notification::Button { button, pressed } => Some(Self::Key(key(button), pressed)),
+            Notification::Axis { axis: id, value } => Some(Self::Axis(axis(id), value.into())),
+            Notification::Heading(_)
+            | Notification::Gesture(_)
+            | Notification::Orientation(_)
+            | Notification::RecordingLength(_)
+            | Notification::RecordingSample(_) => None,
+        }
+    }
+
+    /// Every button released and the stick centred, for when the gamepad goes away
+    pub fn neutral() -> impl Iterator<Item = Self> {
+        let keys = ButtonId::ALL.into_iter().map(|b| Self::Key(key(b), false));
+        keys.chain([AxisId::X, AxisId::Y].map(|a| Self::Axis(axis(a), 0)))
+    }
+
+    fn to_input_event(self) -> InputEvent {
+        match self {
+            Self::Key(code, pressed) => *KeyEvent::new(code, pressed.into()),
+            Self::Axis(code, value) => *AbsoluteAxisEvent::new(code, value),
+        }
+    }
+}
+
+/// A uinput device with the gamepad's buttons and stick
+pub struct Joystick {
+    device: VirtualDevice,
+}
+
+impl Joystick {
+    /// Needs write access to `/dev/uinput`
+    pub fn create() -> io::Result<Self> {
+        let keys: AttributeSet<KeyCode> = ButtonId::ALL.into_iter().map(key).collect();
+        let range = i32::from(AXIS_MAX);
+        let level = AbsInfo::new(0, -range, range, 0, 0, 0);
+        let mut builder = VirtualDevice::builder()?
+            .name(NAME)
+            .input_id(InputId::new(BusType::BUS_VIRTUAL, 0, 0, 1))
+            .with_keys(&keys)?;
+        for id in [AxisId::X, AxisId::Y] {
+            builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis(id), level))?;
+        }
+        Ok(Self {
+            device: builder.build()?,
+        })
+    }
+
+    pub fn send(&mut self, event: Event) -> io::Result<()> {
+        self.device.emit(&[event.to_input_event()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buttons_and_axes_map_to_distinct_codes() {
+        let mut keys: Vec<u16> = ButtonId::ALL.into_iter().map(|b| key(b).code()).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), ButtonId::ALL.len());
+        assert_ne!(axis(AxisId::X), axis(AxisId::Y));
+    }
+
+    #[test]
+    fn notifications_become_events() {
+        assert_eq!(
+            Event::from_notification(Notification::Button {
+                button: ButtonId::F,
+                pressed: false
+            }),
+            Some(Event::Key(KeyCode::BTN_START, false))
+        );
+        assert_eq!(
+            Event::from_notification(Notification::Axis {
+                axis: AxisId::Y,
+                value: -3
+            }),
+            Some(Event::Axis(AbsoluteAxisCode::ABS_Y, -3))
+        );
+        assert_eq!(Event::from_notification(Notification::Heading(90)), None);
+    }
+
+    #[test]
+    fn input_events_carry_type_code_and_value() {
+        let event = Event::Key(KeyCode::BTN_SOUTH, true).to_input_event();
+        assert_eq!(
+            (event.event_type(), event.code(), event.value()),
+            (evdev::EventType::KEY, KeyCode::BTN_SOUTH.code(), 1)
+        );
+        let event = Event::Axis(AbsoluteAxisCode::ABS_X, -2).to_input_event();
+        assert_eq!(
+            (event.event_type(), event.code(), event.value()),
+            (evdev::EventType::ABSOLUTE, AbsoluteAxisCode::ABS_X.0, -2)
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Makes the gamepad a joystick on Linux. Its notifications, from a replay file or written to a
+//! Unix socket by whatever holds the BLE connection, are decoded with `gamepad-protocol` and
+//! played into a uinput device that games see like any other controller.
+
+mod joystick;
+mod source;
+
+use std::{cell::RefCell, io, path::PathBuf, process::ExitCode};
+
+use gamepad_protocol::Notification;
+
+use crate::joystick::{Event, Joystick};
+
+const USAGE: &str = "usage: gamepad-bridge (--socket PATH | --replay FILE) [--print]
+
+  --socket PATH  read notifications written to a Unix socket created at PATH
+  --replay FILE  read notifications from FILE, at the times it gives
+  --print        print the joystick events instead of creating a uinput device
+
+Each line is `[ms] characteristic hex-value`, the characteristic by `service.name` or UUID,
+for example `250 hid.button_a 01`.";
+
+enum Input {
+    Socket(PathBuf),
+    Replay(PathBuf),
+}
+
+/// Where joystick events go
+enum Output {
+    Joystick(Joystick),
+    Print,
+}
+
+impl Output {
+    fn send(&mut self, event: Event) -> io::Result<()> {
+        match self {
+            Self::Joystick(joystick) => joystick.send(event),
+            Self::Print => {
+                println!("{event:?}");
+                Ok(())
+            }
+        }
+    }
+
+    fn notify(&mut self, notification: Notification) -> io::Result<()> {
+        match Event::from_notification(notification) {
+            Some(event) => self.send(event),
+            None => Ok(()),
+        }
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        Event::neutral().try_for_each(|event| self.send(event))
+    }
+}
+
+fn main() -> ExitCode {
+    let Some((input, print)) = parse_args(std::env::args().skip(1)) else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    match run(input, print) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("gamepad-bridge: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<(Input, bool)> {
+    let mut input = None;
+    let mut print = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" => input = Some(Input::Socket(args.next()?.into())),
+            "--replay" => input = Some(Input::Replay(args.next()?.into())),
+            "--print" => print = true,
+            _ => return None,
+        }
+    }
+    Some((input?, print))
+}
+
+fn run(input: Input, print: bool) -> io::Result<()> {
+    let mut output = if print {
+        Output::Print
+    } else {
+        Output::Joystick(Joystick::create()?)
+    };
+    match input {
+        Input::Replay(path) => {
+            source::replay(&path, |notification| output.notify(notification))?;
+            output.reset()
+        }
+        Input::Socket(path) => {
+            let output = RefCell::new(output);
+            source::listen(
+                &path,
+                |notification| output.borrow_mut().notify(notification),
+                || output.borrow_mut().reset(),
+            )
+        }
+    }
+}
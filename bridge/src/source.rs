@@ -0,0 +1,72 @@
+//! Where notifications come from: a replay file, or clients writing lines to a Unix socket, such
+//! as a script forwarding them from a BLE connection.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    os::unix::net::UnixListener,
+    path::Path,
+    thread,
+    time::Instant,
+};
+
+use gamepad_protocol::{text, Notification};
+
+/// Send every line of a replay file to `handle`, at the times it gives
+pub fn replay(
+    path: &Path,
+    mut handle: impl FnMut(Notification) -> io::Result<()>,
+) -> io::Result<()> {
+    let reader = BufReader::new(File::open(path)?);
+    let start = Instant::now();
+    read_lines(reader, &path.display().to_string(), |line| {
+        if let Some(at) = line.at {
+            thread::sleep((start + at).saturating_duration_since(Instant::now()));
+        }
+        handle(line.notification)
+    })
+}
+
+/// Accept clients on a socket at `path` one at a time, sending each line they write to `handle`
+/// and calling `hung_up` when they go
+pub fn listen(
+    path: &Path,
+    mut handle: impl FnMut(Notification) -> io::Result<()>,
+    mut hung_up: impl FnMut() -> io::Result<()>,
+) -> io::Result<()> {
+    // left behind by an earlier run
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    eprintln!("listening on {}", path.display());
+    for client in listener.incoming() {
+        let client = client?;
+        eprintln!("client connected");
+        let result = read_lines(BufReader::new(client), "socket", |line| {
+            handle(line.notification)
+        });
+        if let Err(error) = result {
+            eprintln!("client: {error}");
+        }
+        eprintln!("client disconnected");
+        hung_up()?;
+    }
+    Ok(())
+}
+
+/// Parse each line, reporting and skipping any that are bad
+fn read_lines(
+    reader: impl BufRead,
+    name: &str,
+    mut handle: impl FnMut(text::Line) -> io::Result<()>,
+) -> io::Result<()> {
+    for (number, text) in reader.lines().enumerate() {
+        match text::parse(&text?) {
+            Ok(Some(line)) => handle(line)?,
+            Ok(None) => {}
+            Err(error) => eprintln!("{name}:{}: {error}", number + 1),
+        }
+    }
+    Ok(())
+}
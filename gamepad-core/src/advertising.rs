@@ -0,0 +1,83 @@
+//! How eagerly the gamepad advertises while waiting for a central.
+//!
+//! Advertising starts in a [`Phase::Fast`] phase after boot, a disconnect or waking up, so a
+//! host that is looking finds it straight away. If nothing connects it drops to a
+//! [`Phase::Slow`] phase that costs much less power, and when that runs out too the controller
+//! goes to sleep.
+
+use embassy_time::Duration;
+use gamepad_protocol::AdvertisingPhase;
+
+use crate::config::Settings;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Phase {
+    Fast,
+    Slow,
+}
+
+/// How often to advertise during a phase, and for how long
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PhaseTiming {
+    /// The controller picks an interval between these, in steps of 0.625 ms
+    pub interval_min: Duration,
+    pub interval_max: Duration,
+    /// How long the phase lasts before moving on
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdvertisingConfig {
+    pub fast: PhaseTiming,
+    pub slow: PhaseTiming,
+}
+
+impl AdvertisingConfig {
+    pub fn timing(&self, phase: Phase) -> &PhaseTiming {
+        match phase {
+            Phase::Fast => &self.fast,
+            Phase::Slow => &self.slow,
+        }
+    }
+}
+
+impl From<&AdvertisingPhase> for PhaseTiming {
+    fn from(phase: &AdvertisingPhase) -> Self {
+        Self {
+            interval_min: Duration::from_micros(phase.interval_min_us().into()),
+            interval_max: Duration::from_micros(phase.interval_max_us().into()),
+            timeout: Duration::from_secs(phase.timeout_s.into()),
+        }
+    }
+}
+
+impl From<&Settings> for AdvertisingConfig {
+    /// The phases a central last wrote to the config service
+    fn from(settings: &Settings) -> Self {
+        Self {
+            fast: (&settings.fast_advertising).into(),
+            slow: (&settings.slow_advertising).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_settings_advertise_fast_then_slow() {
+        let config = AdvertisingConfig::from(&Settings::default());
+        assert_eq!(
+            config.timing(Phase::Fast),
+            &PhaseTiming {
+                interval_min: Duration::from_millis(20),
+                interval_max: Duration::from_millis(30),
+                timeout: Duration::from_secs(30),
+            }
+        );
+        assert_eq!(config.slow.interval_max, Duration::from_micros(1_022_500));
+        assert_eq!(config.slow.timeout, Duration::from_secs(300));
+    }
+}
@@ -0,0 +1,69 @@
+/// Short jingles played to signal a change of state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Tune {
+    Connect,
+    Disconnect,
+    Error,
+}
+
+const C4: u32 = 262;
+const G4: u32 = 392;
+
+/// A tone at `frequency` Hz, or silence if it is zero
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Note {
+    pub frequency: u32,
+    pub duration_ms: u32,
+}
+
+impl Note {
+    pub const fn new(frequency: u32, duration_ms: u32) -> Self {
+        Self {
+            frequency,
+            duration_ms,
+        }
+    }
+}
+
+impl Tune {
+    /// The notes to play, in order
+    pub fn notes(&self) -> &'static [Note] {
+        const CONNECT: [Note; 2] = [Note::new(C4, 200), Note::new(G4, 200)];
+        const DISCONNECT: [Note; 2] = [Note::new(G4, 200), Note::new(C4, 200)];
+        const ERROR: [Note; 2] = [Note::new(C4, 200), Note::new(C4, 600)];
+        match self {
+            Tune::Connect => &CONNECT,
+            Tune::Disconnect => &DISCONNECT,
+            Tune::Error => &ERROR,
+        }
+    }
+}
+
+/// Speaker volume level, silent at [`Volume::MIN`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Volume(u8);
+
+impl Volume {
+    pub const MIN: Self = Self(0);
+    pub const MAX: Self = Self(10);
+
+    /// Clamped to [`Volume::MIN`]..=[`Volume::MAX`]
+    pub fn new(level: u8) -> Self {
+        Self(level.min(Self::MAX.0))
+    }
+
+    pub fn level(&self) -> u8 {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AudioAction {
+    PlayNote(Note),
+    PlayTune(Tune),
+    SetVolume(Volume),
+}
@@ -0,0 +1,183 @@
+//! The centrals connected at the same time.
+//!
+//! The first central to connect while the gamepad is advertising is the primary, normally the
+//! game host. Its connection is the one the [`Controller`](crate::controller::Controller)
+//! follows, so losing it goes back to advertising. While it is connected, up to `N` companions
+//! such as a teacher's dashboard can join in. They get every notification they subscribe to and
+//! can change settings, but come and go without the controller noticing, and stay connected
+//! when the primary leaves.
+
+use core::cell::RefCell;
+
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, signal::Signal};
+
+/// Which of the centrals a connection is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Role {
+    Primary,
+    /// In the given slot, from 0
+    Companion(usize),
+}
+
+struct Slot<C> {
+    conn: RefCell<Option<C>>,
+    joined: Signal<NoopRawMutex, ()>,
+}
+
+/// The primary and companion connections, `C` being the BLE host's connection handle
+pub struct Centrals<C, const N: usize> {
+    primary: RefCell<Option<C>>,
+    companions: [Slot<C>; N],
+    left: Signal<NoopRawMutex, ()>,
+}
+
+impl<C: Clone, const N: usize> Default for Centrals<C, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clone, const N: usize> Centrals<C, N> {
+    pub const fn new() -> Self {
+        Self {
+            primary: RefCell::new(None),
+            companions: [const {
+                Slot {
+                    conn: RefCell::new(None),
+                    joined: Signal::new(),
+                }
+            }; N],
+            left: Signal::new(),
+        }
+    }
+
+    pub fn primary(&self) -> Option<C> {
+        self.primary.borrow().clone()
+    }
+
+    pub fn set_primary(&self, conn: Option<C>) {
+        *self.primary.borrow_mut() = conn;
+    }
+
+    /// Take `conn` as a companion in the first free slot, or give it back if there isn't one
+    pub fn join(&self, conn: C) -> Result<usize, C> {
+        let Some(slot) = self
+            .companions
+            .iter()
+            .position(|slot| slot.conn.borrow().is_none())
+        else {
+            return Err(conn);
+        };
+        *self.companions[slot].conn.borrow_mut() = Some(conn);
+        self.companions[slot].joined.signal(());
+        Ok(slot)
+    }
+
+    /// Forget the companion in `slot`, if there is one
+    pub fn leave(&self, slot: usize) {
+        if self.companions[slot].conn.borrow_mut().take().is_some() {
+            self.left.signal(());
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.companions
+            .iter()
+            .all(|slot| slot.conn.borrow().is_some())
+    }
+
+    /// Wait until another companion can join. Only one caller may wait at a time.
+    pub async fn wait_for_room(&self) {
+        while self.is_full() {
+            self.left.wait().await;
+        }
+    }
+
+    /// The companion in `slot`, waiting for one to join if it's free. Only one caller may
+    /// wait on each slot at a time.
+    pub async fn companion(&self, slot: usize) -> C {
+        loop {
+            if let Some(conn) = self.companions[slot].conn.borrow().clone() {
+                return conn;
+            }
+            self.companions[slot].joined.wait().await;
+        }
+    }
+
+    /// Every central connected right now, primary first
+    pub fn connected(&self) -> impl Iterator<Item = (Role, C)> {
+        let companions: [Option<C>; N] =
+            core::array::from_fn(|slot| self.companions[slot].conn.borrow().clone());
+        let companions = companions
+            .into_iter()
+            .enumerate()
+            .filter_map(|(slot, conn)| Some((Role::Companion(slot), conn?)));
+        self.primary()
+            .map(|conn| (Role::Primary, conn))
+            .into_iter()
+            .chain(companions)
+    }
+
+    /// Forget every central, returning them all so they can be disconnected
+    pub fn clear(&self) -> impl Iterator<Item = C> {
+        let primary = self.primary.borrow_mut().take();
+        let companions: [Option<C>; N] =
+            core::array::from_fn(|slot| self.companions[slot].conn.borrow_mut().take());
+        self.left.signal(());
+        primary.into_iter().chain(companions.into_iter().flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::{block_on, join::join};
+
+    use super::*;
+
+    #[test]
+    fn companions_fill_free_slots_until_full() {
+        let centrals = Centrals::<u8, 2>::new();
+        centrals.set_primary(Some(1));
+        assert_eq!(centrals.join(2), Ok(0));
+        assert_eq!(centrals.join(3), Ok(1));
+        assert!(centrals.is_full());
+        assert_eq!(centrals.join(4), Err(4));
+        centrals.leave(0);
+        assert_eq!(centrals.join(5), Ok(0));
+        assert_eq!(
+            centrals.connected().collect::<Vec<_>>(),
+            [
+                (Role::Primary, 1),
+                (Role::Companion(0), 5),
+                (Role::Companion(1), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn companions_outlive_the_primary() {
+        let centrals = Centrals::<u8, 1>::new();
+        centrals.set_primary(Some(1));
+        assert_eq!(centrals.join(2), Ok(0));
+        centrals.set_primary(None);
+        assert_eq!(
+            centrals.connected().collect::<Vec<_>>(),
+            [(Role::Companion(0), 2)]
+        );
+        assert_eq!(centrals.clear().collect::<Vec<_>>(), [2]);
+        assert_eq!(centrals.connected().count(), 0);
+    }
+
+    #[test]
+    fn waiters_wake_on_join_and_leave() {
+        let centrals = Centrals::<u8, 1>::new();
+        block_on(async {
+            let (conn, slot) = join(centrals.companion(0), async { centrals.join(7) }).await;
+            assert_eq!((conn, slot), (7, Ok(0)));
+            assert!(centrals.is_full());
+            join(centrals.wait_for_room(), async { centrals.leave(0) }).await;
+            assert!(!centrals.is_full());
+        });
+    }
+}
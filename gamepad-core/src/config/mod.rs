@@ -0,0 +1,259 @@
+//! Settings kept in flash across power cycles.
+//!
+//! Every setting has its own [`Key`] in a [`Store`]. The layout of the values is versioned by
+//! [`SCHEMA_VERSION`], and [`migration`] brings values stored under an older schema up to date
+//! when the store is mounted.
+//! [`Settings`] are the tunables a central changes through the config service, stored as the
+//! bytes it wrote. Those that make up a [`Profile`](crate::profile::Profile) are the profile in
+//! use, the others wait in their own slots.
+
+pub mod store;
+
+use gamepad_protocol::{
+    gatt,
+    hid::{HidMode, KeyMap},
+    AdvertisingPhase, ButtonMapping, Curve, DeviceName, IdleTimeouts, ProfileName, Setting, Turbo,
+    Uuid,
+};
+pub use store::{Entry, Store, StoreError, MAX_VALUE};
+
+use crate::{
+    audio::Volume,
+    display::Brightness,
+    hal::Flash,
+    input::{BUTTON_DEBOUNCE, STICK_INTERVAL},
+    profile,
+};
+
+/// Bump whenever the meaning of a stored value changes, and teach [`migration`] to convert the
+/// values stored under the one before
+///
+/// 2. The slot of the profile in use only holds it while a switch to it is unfinished. Under 1
+///    it kept the copy taken up when switching to it.
+pub const SCHEMA_VERSION: u16 = 2;
+
+/// What is stored, each under its own key. Keys are never reused once retired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Key {
+    /// The `u8` the central last wrote to the player characteristic
+    PlayerIndex = 1,
+    /// Magnetometer hard and soft iron correction
+    CompassCalibration = 2,
+    // the tunables, each holding what was written to its characteristic
+    DeviceName = 3,
+    Deadzone = 4,
+    Curve = 5,
+    Debounce = 6,
+    ReportRate = 7,
+    Brightness = 8,
+    Volume = 9,
+    ButtonMapping = 10,
+    HidMode = 11,
+    KeyMap = 12,
+    Turbo = 13,
+    ProfileName = 14,
+    Profile = 15,
+    /// A [`Profile`](crate::profile::Profile) that isn't in use, or that a switch is still taking
+    /// up, in a slot for each
+    ProfileSlot0 = 16,
+    ProfileSlot1 = 17,
+    ProfileSlot2 = 18,
+    ProfileSlot3 = 19,
+    FastAdvertising = 20,
+    SlowAdvertising = 21,
+    IdleTimeouts = 22,
+    BatchRate = 23,
+}
+
+impl Key {
+    /// Where profile `index` waits while another is in use
+    pub fn profile_slot(index: u8) -> Self {
+        match index {
+            0 => Self::ProfileSlot0,
+            1 => Self::ProfileSlot1,
+            2 => Self::ProfileSlot2,
+            _ => Self::ProfileSlot3,
+        }
+    }
+}
+
+/// Where each tunable is stored
+const SETTING_KEYS: [(Uuid, Key); 17] = [
+    (gatt::config::NAME, Key::DeviceName),
+    (gatt::config::DEADZONE, Key::Deadzone),
+    (gatt::config::CURVE, Key::Curve),
+    (gatt::config::DEBOUNCE, Key::Debounce),
+    (gatt::config::REPORT_RATE, Key::ReportRate),
+    (gatt::config::BRIGHTNESS, Key::Brightness),
+    (gatt::config::VOLUME, Key::Volume),
+    (gatt::config::MAPPING, Key::ButtonMapping),
+    (gatt::config::HID_MODE, Key::HidMode),
+    (gatt::config::KEY_MAP, Key::KeyMap),
+    (gatt::config::TURBO, Key::Turbo),
+    (gatt::config::PROFILE_NAME, Key::ProfileName),
+    (gatt::config::PROFILE, Key::Profile),
+    (gatt::config::FAST_ADVERTISING, Key::FastAdvertising),
+    (gatt::config::SLOW_ADVERTISING, Key::SlowAdvertising),
+    (gatt::config::IDLE_TIMEOUTS, Key::IdleTimeouts),
+    (gatt::config::BATCH_RATE, Key::BatchRate),
+];
+
+/// Every tunable, as currently applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Settings {
+    pub name: DeviceName,
+    /// Raw stick counts either side of the centre that read as centred
+    pub deadzone: u16,
+    pub curve: Curve,
+    pub debounce_ms: u8,
+    /// Stick samples per second
+    pub report_rate_hz: u8,
+    pub brightness: Brightness,
+    pub volume: Volume,
+    pub mapping: ButtonMapping,
+    pub hid_mode: HidMode,
+    /// What each button does in [`HidMode::KeyboardMouse`]
+    pub key_map: KeyMap,
+    pub turbo: Turbo,
+    pub profile_name: ProfileName,
+    /// The profile in use, from 0
+    pub profile: u8,
+    /// See [`AdvertisingConfig`](crate::advertising::AdvertisingConfig)
+    pub fast_advertising: AdvertisingPhase,
+    pub slow_advertising: AdvertisingPhase,
+    /// See [`idle_watch`](crate::power::idle_watch)
+    pub idle: IdleTimeouts,
+    /// See [`ReportScheduler`](crate::scheduler::ReportScheduler)
+    pub batch_rate_hz: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            name: DeviceName::new("Rust Gamepad").unwrap(),
+            deadzone: 0,
+            curve: Curve::Linear,
+            debounce_ms: BUTTON_DEBOUNCE.as_millis() as u8,
+            report_rate_hz: (1000 / STICK_INTERVAL.as_millis()) as u8,
+            brightness: Brightness::MAX,
+            volume: Volume::MAX,
+            mapping: ButtonMapping::IDENTITY,
+            hid_mode: HidMode::Gamepad,
+            key_map: KeyMap::DEFAULT,
+            turbo: Turbo::NONE,
+            profile_name: ProfileName::numbered(0),
+            profile: 0,
+            // the intervals Apple's accessory guidelines ask for: 20 ms for the first 30
+            // seconds, then one of their longer intervals, here for five minutes
+            fast_advertising: AdvertisingPhase::new(20_000, 30_000, 30),
+            slow_advertising: AdvertisingPhase::new(1_022_500, 1_022_500, 5 * 60),
+            idle: IdleTimeouts {
+                dim_after_s: 60,
+                sleep_after_s: 10 * 60,
+            },
+            // one batch per connection event at the shortest interval
+            batch_rate_hz: 133,
+        }
+    }
+}
+
+impl Settings {
+    /// Each tunable, as it would be read from its characteristic
+    pub fn all(&self) -> [Setting; 17] {
+        [
+            Setting::Name(self.name),
+            Setting::Deadzone(self.deadzone),
+            Setting::Curve(self.curve),
+            Setting::DebounceMs(self.debounce_ms),
+            Setting::ReportRateHz(self.report_rate_hz),
+            Setting::Brightness(self.brightness.level()),
+            Setting::Volume(self.volume.level()),
+            Setting::Mapping(self.mapping),
+            Setting::HidMode(self.hid_mode),
+            Setting::KeyMap(self.key_map),
+            Setting::Turbo(self.turbo),
+            Setting::ProfileName(self.profile_name),
+            Setting::Profile(self.profile),
+            Setting::FastAdvertising(self.fast_advertising),
+            Setting::SlowAdvertising(self.slow_advertising),
+            Setting::IdleTimeouts(self.idle),
+            Setting::BatchRateHz(self.batch_rate_hz),
+        ]
+    }
+
+    pub fn apply(&mut self, setting: Setting) {
+        match setting {
+            Setting::Name(name) => self.name = name,
+            Setting::Deadzone(deadzone) => self.deadzone = deadzone,
+            Setting::Curve(curve) => self.curve = curve,
+            Setting::DebounceMs(ms) => self.debounce_ms = ms,
+            Setting::ReportRateHz(hz) => self.report_rate_hz = hz,
+            Setting::Brightness(level) => self.brightness = Brightness::new(level),
+            Setting::Volume(level) => self.volume = Volume::new(level),
+            Setting::Mapping(mapping) => self.mapping = mapping,
+            Setting::HidMode(mode) => self.hid_mode = mode,
+            Setting::KeyMap(map) => self.key_map = map,
+            Setting::Turbo(turbo) => self.turbo = turbo,
+            Setting::ProfileName(name) => self.profile_name = name,
+            // only the index, see `profile::switch` for taking up the profile
+            Setting::Profile(index) => self.profile = index,
+            Setting::FastAdvertising(phase) => self.fast_advertising = phase,
+            Setting::SlowAdvertising(phase) => self.slow_advertising = phase,
+            Setting::IdleTimeouts(timeouts) => self.idle = timeouts,
+            Setting::BatchRateHz(hz) => self.batch_rate_hz = hz,
+        }
+    }
+
+    /// The saved settings, with the default for any that weren't saved or are no longer valid.
+    /// A profile switch cut short is finished first.
+    pub async fn load<F: Flash>(store: &mut Store<F>) -> Result<Self, StoreError<F::Error>> {
+        let mut settings = Self::default();
+        for (characteristic, key) in SETTING_KEYS {
+            if let Some(bytes) = store.get(key).await? {
+                if let Ok(setting) = Setting::decode(characteristic, &bytes) {
+                    settings.apply(setting);
+                }
+            }
+        }
+        profile::resume(store, &mut settings).await?;
+        Ok(settings)
+    }
+
+    /// Save `setting` so [`Settings::load`] finds it after a restart
+    pub async fn save<F: Flash>(
+        store: &mut Store<F>,
+        setting: &Setting,
+    ) -> Result<(), StoreError<F::Error>> {
+        let characteristic = setting.characteristic();
+        let (_, key) = SETTING_KEYS
+            .into_iter()
+            .find(|(c, _)| *c == characteristic)
+            .expect("every setting has a key");
+        let mut bytes = [0; gamepad_protocol::value::MAX_SIZE];
+        let len = setting.encode(&mut bytes);
+        store.set(key, &bytes[..len]).await
+    }
+}
+
+/// Convert the entries stored under an older schema, for [`Store::mount`]. The store hands them
+/// over in key order, so the profile in use is known by the time its slot comes up. Anything
+/// older than 1 came from a development build and its settings go back to their defaults.
+pub fn migration() -> impl FnMut(u16, &mut Entry) -> bool {
+    let mut in_use = 0;
+    move |from, entry| match from {
+        1 => {
+            if entry.key == Key::Profile as u8 {
+                if let Ok(Setting::Profile(index)) =
+                    Setting::decode(gatt::config::PROFILE, &entry.value)
+                {
+                    in_use = index;
+                }
+            }
+            entry.key != Key::profile_slot(in_use) as u8
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,409 @@
+//! A log structured key/value store over a few pages of flash.
+//!
+//! Each page starts with a header, followed by records appended one after another. The newest
+//! record for a key wins. When the page fills up, the latest value of every key is copied into the
+//! next page and that becomes the active one, so erases are spread evenly over all the pages.
+//!
+//! Power can be cut at any point: a record that didn't finish writing fails its CRC and is
+//! ignored, and a page only becomes active once its header has been written, after everything
+//! was copied into it.
+
+use heapless::Vec;
+
+use gamepad_protocol::Value;
+
+use super::Key;
+use crate::hal::Flash;
+
+/// Marks a page that belongs to the store
+const MAGIC: u32 = 0x4643_5047;
+/// magic u32, sequence u32, schema version u16, CRC u16
+const HEADER_SIZE: u32 = 12;
+/// key u8, length u8, CRC u16 over those and the value
+const RECORD_HEADER_SIZE: u32 = 4;
+
+/// Keys are below this
+pub const MAX_KEYS: usize = 32;
+/// The longest value that can be stored
+pub const MAX_VALUE: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StoreError<E> {
+    Flash(E),
+    /// The value is longer than [`MAX_VALUE`]
+    TooLong,
+    /// The latest values together don't fit in a page
+    Full,
+}
+
+/// A stored value, as handed to a migration
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub key: u8,
+    pub value: Vec<u8, MAX_VALUE>,
+}
+
+struct Header {
+    sequence: u32,
+    version: u16,
+}
+
+enum Record {
+    /// Erased flash, nothing has been written here yet
+    End,
+    /// A record that didn't finish writing or has been damaged
+    Corrupt,
+    Valid {
+        key: u8,
+        size: u32,
+    },
+}
+
+/// Configuration values kept in a [`Flash`] region of at least two pages
+pub struct Store<F> {
+    flash: F,
+    version: u16,
+    /// The page records are appended to
+    active: u32,
+    sequence: u32,
+    /// Where the next record goes in the active page
+    end: u32,
+    /// A damaged record was found, so the page is compacted before anything else is written to it
+    dirty: bool,
+}
+
+impl<F: Flash> Store<F> {
+    /// Open the store, formatting the region if it holds none. Values written under an older
+    /// schema `version` are passed through `migrate`, which drops them by returning false; values
+    /// from a newer schema than this firmware knows are all dropped.
+    pub async fn mount(
+        flash: F,
+        version: u16,
+        mut migrate: impl FnMut(u16, &mut Entry) -> bool,
+    ) -> Result<Self, StoreError<F::Error>> {
+        assert!(flash.pages() >= 2, "the store needs at least two pages");
+        let mut store = Self {
+            flash,
+            version,
+            active: 0,
+            sequence: 0,
+            end: HEADER_SIZE,
+            dirty: false,
+        };
+        let mut newest: Option<(u32, Header)> = None;
+        for page in 0..store.flash.pages() {
+            if let Some(header) = store.header(page).await? {
+                if newest
+                    .as_ref()
+                    .is_none_or(|(_, newest)| header.sequence > newest.sequence)
+                {
+                    newest = Some((page, header));
+                }
+            }
+        }
+        let Some((page, header)) = newest else {
+            store.format(0, 1).await?;
+            return Ok(store);
+        };
+        store.active = page;
+        store.sequence = header.sequence;
+        store.index().await?;
+        if header.version < version {
+            store.compact(header.version, &mut migrate).await?;
+        } else if header.version > version {
+            store.factory_reset().await?;
+        }
+        Ok(store)
+    }
+
+    /// The raw bytes stored under `key`
+    pub async fn get(
+        &mut self,
+        key: Key,
+    ) -> Result<Option<Vec<u8, MAX_VALUE>>, StoreError<F::Error>> {
+        let index = self.index().await?;
+        let Some(offset) = index[key as usize] else {
+            return Ok(None);
+        };
+        let entry = self.entry(self.active, offset).await?;
+        Ok((!entry.value.is_empty()).then_some(entry.value))
+    }
+
+    /// Store `value` under `key`, unless it's already there
+    pub async fn set(&mut self, key: Key, value: &[u8]) -> Result<(), StoreError<F::Error>> {
+        if value.len() > MAX_VALUE {
+            return Err(StoreError::TooLong);
+        }
+        if !value.is_empty() && self.get(key).await?.as_deref() == Some(value) {
+            return Ok(());
+        }
+        self.append(key as u8, value).await
+    }
+
+    /// Forget the value under `key`
+    pub async fn remove(&mut self, key: Key) -> Result<(), StoreError<F::Error>> {
+        if self.get(key).await?.is_none() {
+            return Ok(());
+        }
+        self.append(key as u8, &[]).await
+    }
+
+    /// The value under `key`, `None` if there isn't one or it doesn't decode
+    pub async fn load<V: Value>(&mut self, key: Key) -> Result<Option<V>, StoreError<F::Error>> {
+        Ok(self
+            .get(key)
+            .await?
+            .and_then(|bytes| V::decode(&bytes).ok()))
+    }
+
+    pub async fn save<V: Value>(
+        &mut self,
+        key: Key,
+        value: &V,
+    ) -> Result<(), StoreError<F::Error>> {
+        let mut bytes = [0; MAX_VALUE];
+        let bytes = bytes.get_mut(..V::SIZE).ok_or(StoreError::TooLong)?;
+        value.encode(bytes);
+        self.set(key, bytes).await
+    }
+
+    /// Erase everything, leaving an empty store
+    pub async fn factory_reset(&mut self) -> Result<(), StoreError<F::Error>> {
+        for page in 0..self.flash.pages() {
+            self.flash
+                .erase(page * F::PAGE_SIZE)
+                .await
+                .map_err(StoreError::Flash)?;
+        }
+        self.format(0, 1).await
+    }
+
+    /// Erase `page` and make it the active one, empty
+    async fn format(&mut self, page: u32, sequence: u32) -> Result<(), StoreError<F::Error>> {
+        self.flash
+            .erase(page * F::PAGE_SIZE)
+            .await
+            .map_err(StoreError::Flash)?;
+        self.write_header(page, sequence).await?;
+        self.active = page;
+        self.sequence = sequence;
+        self.end = HEADER_SIZE;
+        self.dirty = false;
+        Ok(())
+    }
+
+    async fn header(&mut self, page: u32) -> Result<Option<Header>, StoreError<F::Error>> {
+        let mut bytes = [0; HEADER_SIZE as usize];
+        self.flash
+            .read(page * F::PAGE_SIZE, &mut bytes)
+            .await
+            .map_err(StoreError::Flash)?;
+        let magic = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let crc = u16::from_le_bytes([bytes[10], bytes[11]]);
+        if magic != MAGIC || crc != crc16(&bytes[..10]) {
+            return Ok(None);
+        }
+        Ok(Some(Header {
+            sequence: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            version: u16::from_le_bytes([bytes[8], bytes[9]]),
+        }))
+    }
+
+    async fn write_header(&mut self, page: u32, sequence: u32) -> Result<(), StoreError<F::Error>> {
+        let mut bytes = [0; HEADER_SIZE as usize];
+        bytes[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&sequence.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.version.to_le_bytes());
+        let crc = crc16(&bytes[..10]);
+        bytes[10..].copy_from_slice(&crc.to_le_bytes());
+        self.flash
+            .write(page * F::PAGE_SIZE, &bytes)
+            .await
+            .map_err(StoreError::Flash)
+    }
+
+    /// Where the latest record for each key is in the active page. Also finds the end of the log,
+    /// and whether anything in it is damaged.
+    async fn index(&mut self) -> Result<[Option<u32>; MAX_KEYS], StoreError<F::Error>> {
+        let mut index = [None; MAX_KEYS];
+        let mut offset = HEADER_SIZE;
+        loop {
+            match self.record(self.active, offset).await? {
+                Record::End => break,
+                Record::Corrupt => {
+                    // nothing after a damaged record can be trusted to be where it seems
+                    self.dirty = true;
+                    offset = F::PAGE_SIZE;
+                    break;
+                }
+                Record::Valid { key, size, .. } => {
+                    index[key as usize] = Some(offset);
+                    offset += size;
+                }
+            }
+        }
+        self.end = offset;
+        Ok(index)
+    }
+
+    async fn record(&mut self, page: u32, offset: u32) -> Result<Record, StoreError<F::Error>> {
+        if offset + RECORD_HEADER_SIZE > F::PAGE_SIZE {
+            return Ok(Record::End);
+        }
+        let base = page * F::PAGE_SIZE + offset;
+        let mut header = [0; RECORD_HEADER_SIZE as usize];
+        self.flash
+            .read(base, &mut header)
+            .await
+            .map_err(StoreError::Flash)?;
+        if header == [0xff; RECORD_HEADER_SIZE as usize] {
+            return Ok(Record::End);
+        }
+        let [key, len, crc @ ..] = header;
+        let size = record_size::<F>(len as usize);
+        if key as usize >= MAX_KEYS || len as usize > MAX_VALUE || offset + size > F::PAGE_SIZE {
+            return Ok(Record::Corrupt);
+        }
+        let mut value = [0; MAX_VALUE];
+        let value = &mut value[..len as usize];
+        self.flash
+            .read(base + RECORD_HEADER_SIZE, value)
+            .await
+            .map_err(StoreError::Flash)?;
+        if u16::from_le_bytes(crc) != record_crc(key, value) {
+            return Ok(Record::Corrupt);
+        }
+        Ok(Record::Valid { key, size })
+    }
+
+    async fn entry(&mut self, page: u32, offset: u32) -> Result<Entry, StoreError<F::Error>> {
+        let base = page * F::PAGE_SIZE + offset;
+        let mut header = [0; RECORD_HEADER_SIZE as usize];
+        self.flash
+            .read(base, &mut header)
+            .await
+            .map_err(StoreError::Flash)?;
+        let mut value = Vec::new();
+        // the record was checked when it was indexed, so the length fits
+        let _ = value.resize(header[1] as usize, 0);
+        self.flash
+            .read(base + RECORD_HEADER_SIZE, &mut value)
+            .await
+            .map_err(StoreError::Flash)?;
+        Ok(Entry {
+            key: header[0],
+            value,
+        })
+    }
+
+    async fn append(&mut self, key: u8, value: &[u8]) -> Result<(), StoreError<F::Error>> {
+        let size = record_size::<F>(value.len());
+        if self.dirty || self.end + size > F::PAGE_SIZE {
+            self.compact(self.version, &mut |_, _| true).await?;
+        }
+        if self.end + size > F::PAGE_SIZE {
+            return Err(StoreError::Full);
+        }
+        self.write_record(self.active, self.end, key, value).await?;
+        self.end += size;
+        Ok(())
+    }
+
+    async fn write_record(
+        &mut self,
+        page: u32,
+        offset: u32,
+        key: u8,
+        value: &[u8],
+    ) -> Result<(), StoreError<F::Error>> {
+        let mut bytes = [0xff; RECORD_HEADER_SIZE as usize + MAX_VALUE + 8];
+        let size = record_size::<F>(value.len()) as usize;
+        bytes[0] = key;
+        bytes[1] = value.len() as u8;
+        bytes[2..4].copy_from_slice(&record_crc(key, value).to_le_bytes());
+        bytes[4..4 + value.len()].copy_from_slice(value);
+        self.flash
+            .write(page * F::PAGE_SIZE + offset, &bytes[..size])
+            .await
+            .map_err(StoreError::Flash)
+    }
+
+    /// Copy the latest values into the next page and make that the active one. Values stored under
+    /// schema `from` go through `migrate` if that isn't the current one.
+    async fn compact(
+        &mut self,
+        from: u16,
+        migrate: &mut impl FnMut(u16, &mut Entry) -> bool,
+    ) -> Result<(), StoreError<F::Error>> {
+        let index = self.index().await?;
+        let target = (self.active + 1) % self.flash.pages();
+        self.flash
+            .erase(target * F::PAGE_SIZE)
+            .await
+            .map_err(StoreError::Flash)?;
+        let mut end = HEADER_SIZE;
+        for offset in index.into_iter().flatten() {
+            let mut entry = self.entry(self.active, offset).await?;
+            if entry.value.is_empty() || (from != self.version && !migrate(from, &mut entry)) {
+                continue;
+            }
+            if entry.key as usize >= MAX_KEYS {
+                continue;
+            }
+            let size = record_size::<F>(entry.value.len());
+            if end + size > F::PAGE_SIZE {
+                return Err(StoreError::Full);
+            }
+            self.write_record(target, end, entry.key, &entry.value)
+                .await?;
+            end += size;
+        }
+        // only now does the new page take over
+        self.write_header(target, self.sequence + 1).await?;
+        self.active = target;
+        self.sequence += 1;
+        self.end = end;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Bytes taken by a record holding `len` bytes, padded out to whole writes
+fn record_size<F: Flash>(len: usize) -> u32 {
+    (RECORD_HEADER_SIZE + len as u32).next_multiple_of(F::WRITE_SIZE)
+}
+
+fn record_crc(key: u8, value: &[u8]) -> u16 {
+    let mut bytes = [0; 2 + MAX_VALUE];
+    bytes[0] = key;
+    bytes[1] = value.len() as u8;
+    bytes[2..2 + value.len()].copy_from_slice(value);
+    crc16(&bytes[..2 + value.len()])
+}
+
+/// CRC-16/CCITT-FALSE
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+    }
+}
@@ -0,0 +1,457 @@
+//! The controller's top level state machine.
+//!
+//! The firmware feeds [`Event`]s in as things happen and performs the [`Action`]s that come
+//! back, so what the controller shows, plays and does in every situation is decided here
+//! rather than by the order of an async main loop.
+//!
+//! On a transition the actions run in the order: exit actions of the old state, actions
+//! belonging to the transition itself, then entry actions of the new state. Events that have
+//! no transition from the current state are ignored and produce no actions.
+//!
+//! A fault moves to [`State::Error`] from anywhere, and [`Controller::recovery`] says how to
+//! get out of it, escalating while faults keep happening without a successful connection.
+
+use heapless::Vec;
+
+use crate::{
+    advertising::Phase,
+    audio::Tune,
+    error::{ErrorCode, Recovery},
+    hal::{Display5x5, ToneOutput},
+};
+
+/// Most actions a single event can produce
+pub const MAX_ACTIONS: usize = 4;
+
+/// Faults in a row, without managing to connect in between, before restarting the BLE stack.
+/// One more and the board is reset.
+const FAULTS_BEFORE_STACK_RESTART: u8 = 2;
+
+pub type Actions = Vec<Action, MAX_ACTIONS>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum State {
+    Booting,
+    /// Waiting for a central to connect
+    Advertising(Phase),
+    /// A central is connected and inputs are being reported
+    Connected,
+    /// Still connected, but running the compass calibration instead of reporting inputs
+    Calibrating,
+    /// Radio and display off until woken
+    Sleeping,
+    /// Something went wrong that needs recovering from
+    Error(ErrorCode),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// Hardware and BLE stack are up
+    Booted,
+    Connected,
+    /// The central went away
+    Disconnected,
+    /// A notification could not be sent, the link is treated as lost
+    NotifyFailed,
+    /// The user shook the controller to release the current host
+    Shaken,
+    CalibrationRequested,
+    CalibrationFinished,
+    /// The current advertising phase ran out without a connection
+    AdvertisingTimeout,
+    /// Nothing has happened for long enough to go to sleep
+    Idle,
+    /// A button was pressed while asleep
+    Wake,
+    /// An unexpected failure, from any state
+    Fault(ErrorCode),
+    /// The error has been dealt with
+    Recovered,
+}
+
+/// What the display should show for a state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Indicator {
+    Booting,
+    Advertising(Phase),
+    Connected,
+    Calibrating,
+    Off,
+    Error(ErrorCode),
+}
+
+/// Side effects for the firmware to carry out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    Show(Indicator),
+    Play(Tune),
+    /// Drop the current connection
+    Disconnect,
+}
+
+pub struct Controller {
+    state: State,
+    /// Faults since the last successful connection
+    faults: u8,
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Controller {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Booting,
+            faults: 0,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// How to get out of the error state, escalating if the same thing keeps failing
+    pub fn recovery(&self) -> Option<Recovery> {
+        let State::Error(code) = self.state else {
+            return None;
+        };
+        let escalation = match self.faults {
+            n if n > FAULTS_BEFORE_STACK_RESTART => Recovery::Reset,
+            FAULTS_BEFORE_STACK_RESTART => Recovery::RestartStack,
+            _ => Recovery::RestartAdvertising,
+        };
+        Some(code.recovery().max(escalation))
+    }
+
+    /// Actions to perform when first powered up, before any event
+    pub fn start(&self) -> Actions {
+        entry(self.state)
+    }
+
+    /// Apply an event, returning the actions to perform in order
+    pub fn handle(&mut self, event: Event) -> Actions {
+        use {Event as E, State as S};
+        let mut during: Actions = Vec::new();
+        let next = match (self.state, event) {
+            (_, E::Fault(code)) => {
+                self.faults = self.faults.saturating_add(1);
+                S::Error(code)
+            }
+            (S::Booting, E::Booted) => S::Advertising(Phase::Fast),
+            (S::Advertising(_), E::Connected) => {
+                self.faults = 0;
+                S::Connected
+            }
+            (S::Advertising(Phase::Fast), E::AdvertisingTimeout) => S::Advertising(Phase::Slow),
+            (S::Advertising(Phase::Slow), E::AdvertisingTimeout) => S::Sleeping,
+            (S::Advertising(_), E::Idle) => S::Sleeping,
+            (S::Connected | S::Calibrating, E::Disconnected) => {
+                push(&mut during, Action::Play(Tune::Disconnect));
+                S::Advertising(Phase::Fast)
+            }
+            (S::Connected, E::NotifyFailed | E::Shaken) => {
+                push(&mut during, Action::Disconnect);
+                push(&mut during, Action::Play(Tune::Disconnect));
+                S::Advertising(Phase::Fast)
+            }
+            (S::Connected, E::Idle) => {
+                push(&mut during, Action::Disconnect);
+                S::Sleeping
+            }
+            (S::Connected, E::CalibrationRequested) => S::Calibrating,
+            (S::Calibrating, E::CalibrationFinished) => S::Connected,
+            (S::Sleeping, E::Wake) => S::Advertising(Phase::Fast),
+            (S::Error(_), E::Recovered) => S::Advertising(Phase::Fast),
+            _ => return Vec::new(),
+        };
+        let mut actions = exit(self.state, next);
+        for action in during.into_iter().chain(entry_from(self.state, next)) {
+            push(&mut actions, action);
+        }
+        self.state = next;
+        actions
+    }
+}
+
+/// Show and play the actions in order, returning `true` if one of them asks to drop the
+/// connection, which is left to the caller
+pub async fn perform(
+    actions: Actions,
+    display: &impl Display5x5,
+    speaker: &impl ToneOutput,
+) -> bool {
+    let mut disconnect = false;
+    for action in actions {
+        match action {
+            Action::Show(indicator) => display.indicate(indicator).await,
+            Action::Play(tune) => speaker.play_tune(tune).await,
+            Action::Disconnect => disconnect = true,
+        }
+    }
+    disconnect
+}
+
+fn push(actions: &mut Actions, action: Action) {
+    actions
+        .push(action)
+        .expect("a transition has more than MAX_ACTIONS actions");
+}
+
+fn entry(state: State) -> Actions {
+    let mut actions = Vec::new();
+    match state {
+        State::Booting => push(&mut actions, Action::Show(Indicator::Booting)),
+        State::Advertising(phase) => {
+            push(&mut actions, Action::Show(Indicator::Advertising(phase)))
+        }
+        State::Connected => {
+            push(&mut actions, Action::Play(Tune::Connect));
+            push(&mut actions, Action::Show(Indicator::Connected));
+        }
+        State::Calibrating => push(&mut actions, Action::Show(Indicator::Calibrating)),
+        State::Sleeping => push(&mut actions, Action::Show(Indicator::Off)),
+        State::Error(code) => {
+            push(&mut actions, Action::Play(Tune::Error));
+            push(&mut actions, Action::Show(Indicator::Error(code)));
+        }
+    }
+    actions
+}
+
+/// Entry actions, except returning from calibration which never left the connection
+fn entry_from(previous: State, next: State) -> Actions {
+    match (previous, next) {
+        (State::Calibrating, State::Connected) => {
+            let mut actions = Vec::new();
+            push(&mut actions, Action::Show(Indicator::Connected));
+            actions
+        }
+        _ => entry(next),
+    }
+}
+
+fn exit(state: State, next: State) -> Actions {
+    let mut actions = Vec::new();
+    // a fault while connected can't leave the central hanging
+    if matches!(state, State::Connected | State::Calibrating) && matches!(next, State::Error(_)) {
+        push(&mut actions, Action::Disconnect);
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected() -> Controller {
+        let mut controller = Controller::new();
+        controller.handle(Event::Booted);
+        controller.handle(Event::Connected);
+        controller
+    }
+
+    #[test]
+    fn boots_into_advertising() {
+        let mut controller = Controller::new();
+        assert_eq!(controller.start(), [Action::Show(Indicator::Booting)]);
+        assert_eq!(
+            controller.handle(Event::Booted),
+            [Action::Show(Indicator::Advertising(Phase::Fast))]
+        );
+        assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+    }
+
+    #[test]
+    fn connecting_plays_and_shows() {
+        let mut controller = Controller::new();
+        controller.handle(Event::Booted);
+        assert_eq!(
+            controller.handle(Event::Connected),
+            [
+                Action::Play(Tune::Connect),
+                Action::Show(Indicator::Connected)
+            ]
+        );
+        assert_eq!(controller.state(), State::Connected);
+    }
+
+    #[test]
+    fn disconnect_returns_to_advertising() {
+        let mut controller = connected();
+        assert_eq!(
+            controller.handle(Event::Disconnected),
+            [
+                Action::Play(Tune::Disconnect),
+                Action::Show(Indicator::Advertising(Phase::Fast))
+            ]
+        );
+        assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+    }
+
+    #[test]
+    fn failed_notify_drops_the_link() {
+        let mut controller = connected();
+        assert_eq!(
+            controller.handle(Event::NotifyFailed),
+            [
+                Action::Disconnect,
+                Action::Play(Tune::Disconnect),
+                Action::Show(Indicator::Advertising(Phase::Fast))
+            ]
+        );
+        assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+    }
+
+    #[test]
+    fn shake_releases_the_host() {
+        let mut controller = connected();
+        assert_eq!(controller.handle(Event::Shaken)[0], Action::Disconnect);
+        assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+    }
+
+    #[test]
+    fn calibration_keeps_the_connection() {
+        let mut controller = connected();
+        assert_eq!(
+            controller.handle(Event::CalibrationRequested),
+            [Action::Show(Indicator::Calibrating)]
+        );
+        assert_eq!(controller.state(), State::Calibrating);
+        assert_eq!(
+            controller.handle(Event::CalibrationFinished),
+            [Action::Show(Indicator::Connected)]
+        );
+        assert_eq!(controller.state(), State::Connected);
+    }
+
+    #[test]
+    fn disconnect_while_calibrating() {
+        let mut controller = connected();
+        controller.handle(Event::CalibrationRequested);
+        controller.handle(Event::Disconnected);
+        assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+    }
+
+    #[test]
+    fn sleeps_and_wakes() {
+        let mut controller = Controller::new();
+        controller.handle(Event::Booted);
+        assert_eq!(
+            controller.handle(Event::Idle),
+            [Action::Show(Indicator::Off)]
+        );
+        assert_eq!(controller.state(), State::Sleeping);
+        assert_eq!(
+            controller.handle(Event::Wake),
+            [Action::Show(Indicator::Advertising(Phase::Fast))]
+        );
+    }
+
+    #[test]
+    fn advertising_slows_down_then_sleeps() {
+        let mut controller = Controller::new();
+        controller.handle(Event::Booted);
+        assert_eq!(
+            controller.handle(Event::AdvertisingTimeout),
+            [Action::Show(Indicator::Advertising(Phase::Slow))]
+        );
+        assert_eq!(controller.state(), State::Advertising(Phase::Slow));
+        assert_eq!(
+            controller.handle(Event::AdvertisingTimeout),
+            [Action::Show(Indicator::Off)]
+        );
+        assert_eq!(controller.state(), State::Sleeping);
+        // waking starts over with fast advertising
+        controller.handle(Event::Wake);
+        assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+        controller.handle(Event::AdvertisingTimeout);
+        controller.handle(Event::Connected);
+        controller.handle(Event::Disconnected);
+        assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+    }
+
+    #[test]
+    fn idle_while_connected_disconnects_and_sleeps() {
+        let mut controller = connected();
+        assert_eq!(
+            controller.handle(Event::Idle),
+            [Action::Disconnect, Action::Show(Indicator::Off)]
+        );
+        assert_eq!(controller.state(), State::Sleeping);
+    }
+
+    #[test]
+    fn fault_while_connected_disconnects_then_recovers() {
+        let mut controller = connected();
+        let code = ErrorCode::Attribute;
+        assert_eq!(
+            controller.handle(Event::Fault(code)),
+            [
+                Action::Disconnect,
+                Action::Play(Tune::Error),
+                Action::Show(Indicator::Error(code))
+            ]
+        );
+        assert_eq!(controller.state(), State::Error(code));
+        assert_eq!(controller.recovery(), Some(Recovery::RestartAdvertising));
+        controller.handle(Event::Recovered);
+        assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+        assert_eq!(controller.recovery(), None);
+    }
+
+    #[test]
+    fn repeated_faults_escalate() {
+        let mut controller = Controller::new();
+        controller.handle(Event::Booted);
+        let mut recoveries = Vec::<Recovery, 4>::new();
+        for _ in 0..4 {
+            controller.handle(Event::Fault(ErrorCode::Advertising));
+            recoveries.push(controller.recovery().unwrap()).unwrap();
+            controller.handle(Event::Recovered);
+        }
+        assert_eq!(
+            recoveries,
+            [
+                Recovery::RestartAdvertising,
+                Recovery::RestartStack,
+                Recovery::Reset,
+                Recovery::Reset
+            ]
+        );
+        // connecting proves the stack works again
+        controller.handle(Event::Connected);
+        controller.handle(Event::Fault(ErrorCode::Notify));
+        assert_eq!(controller.recovery(), Some(Recovery::RestartAdvertising));
+    }
+
+    #[test]
+    fn some_faults_always_reset() {
+        let mut controller = Controller::new();
+        controller.handle(Event::Fault(ErrorCode::GattServer));
+        assert_eq!(controller.recovery(), Some(Recovery::Reset));
+    }
+
+    #[test]
+    fn unexpected_events_are_ignored() {
+        let mut controller = connected();
+        for event in [
+            Event::Booted,
+            Event::Connected,
+            Event::Wake,
+            Event::Recovered,
+        ] {
+            assert!(controller.handle(event).is_empty());
+            assert_eq!(controller.state(), State::Connected);
+        }
+        let mut controller = Controller::new();
+        assert!(controller.handle(Event::NotifyFailed).is_empty());
+        assert_eq!(controller.state(), State::Booting);
+    }
+}
@@ -1,8 +1,8 @@
-use microbit_bsp::display::{fonts::frame_5x5, Frame};
+use super::Bitmap;
 
 #[rustfmt::skip]
 /// A heart bitmap.
-pub const HEART: Frame<5, 5> = frame_5x5(&[
+pub const HEART: Bitmap = Bitmap::from_rows([
     0b01010,
     0b10101,
     0b10001,
@@ -12,7 +12,7 @@ pub const HEART: Frame<5, 5> = frame_5x5(&[
 
 #[rustfmt::skip]
 /// A smile bitmap.
-pub const SMILE: Frame<5, 5> = frame_5x5(&[
+pub const SMILE: Bitmap = Bitmap::from_rows([
     0b00000,
     0b01010,
     0b00000,
@@ -22,7 +22,7 @@ pub const SMILE: Frame<5, 5> = frame_5x5(&[
 
 #[rustfmt::skip]
 /// A sad bitmap.
-pub const SAD: Frame<5, 5> = frame_5x5(&[
+pub const SAD: Bitmap = Bitmap::from_rows([
     0b00000,
     0b01010,
     0b00000,
@@ -32,7 +32,7 @@ pub const SAD: Frame<5, 5> = frame_5x5(&[
 
 #[rustfmt::skip]
 /// An up arrow bitmap.
-pub const ARROW_UP: Frame<5, 5> = frame_5x5(&[
+pub const ARROW_UP: Bitmap = Bitmap::from_rows([
     0b00100,
     0b01110,
     0b10101,
@@ -42,7 +42,7 @@ pub const ARROW_UP: Frame<5, 5> = frame_5x5(&[
 
 #[rustfmt::skip]
 /// A down arrow bitmap.
-pub const ARROW_DOWN: Frame<5, 5> = frame_5x5(&[
+pub const ARROW_DOWN: Bitmap = Bitmap::from_rows([
     0b00100,
     0b00100,
     0b10101,
@@ -50,9 +50,29 @@ pub const ARROW_DOWN: Frame<5, 5> = frame_5x5(&[
     0b00100,
 ]);
 
+#[rustfmt::skip]
+/// A left arrow bitmap.
+pub const ARROW_LEFT: Bitmap = Bitmap::from_rows([
+    0b00100,
+    0b01000,
+    0b11111,
+    0b01000,
+    0b00100,
+]);
+
+#[rustfmt::skip]
+/// A right arrow bitmap.
+pub const ARROW_RIGHT: Bitmap = Bitmap::from_rows([
+    0b00100,
+    0b00010,
+    0b11111,
+    0b00010,
+    0b00100,
+]);
+
 #[rustfmt::skip]
 /// A question mark bitmap.
-pub const QUESTION_MARK: Frame<5, 5> = frame_5x5(&[
+pub const QUESTION_MARK: Bitmap = Bitmap::from_rows([
     0b01100,
     0b10010,
     0b00100,
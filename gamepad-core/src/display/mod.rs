@@ -0,0 +1,131 @@
+//! What can be shown on the 5x5 LED matrix, independent of how it is driven.
+
+use embassy_time::Duration;
+use gamepad_protocol::ProfileName;
+
+pub mod bitmap;
+
+/// One frame of the 5x5 matrix, a row per byte with bit 4 as the left-most LED
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Bitmap([u8; 5]);
+
+impl Bitmap {
+    pub const fn empty() -> Self {
+        Self([0; 5])
+    }
+
+    /// Rows from top to bottom, written as `0b01010` so they read like the LEDs
+    pub const fn from_rows(rows: [u8; 5]) -> Self {
+        let mut masked = [0; 5];
+        let mut y = 0;
+        while y < 5 {
+            masked[y] = rows[y] & 0b11111;
+            y += 1;
+        }
+        Self(masked)
+    }
+
+    pub fn rows(&self) -> [u8; 5] {
+        self.0
+    }
+
+    /// Light the LED at column `x` and row `y`, with (0,0) at the top left
+    pub fn set(&mut self, x: usize, y: usize) {
+        self.0[y] |= 1 << (4 - x);
+    }
+
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        self.0[y] & (1 << (4 - x)) != 0
+    }
+}
+
+/// LED brightness level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Brightness(u8);
+
+impl Brightness {
+    pub const MIN: Self = Self(0);
+    pub const MAX: Self = Self(10);
+
+    /// Clamped to [`Brightness::MIN`]..=[`Brightness::MAX`]
+    pub fn new(level: u8) -> Self {
+        Self(level.min(Self::MAX.0))
+    }
+
+    pub fn level(&self) -> u8 {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayFrame {
+    DisplayFrame(Bitmap),
+    /// Display a single pixel at the given coordinates, where (0,0) is the center of the display.
+    Coord {
+        x: i8,
+        y: i8,
+    },
+    Letter(char),
+    Heart,
+    Smile,
+    Sad,
+    QuestionMark,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl DisplayFrame {
+    pub fn to_bitmap(&self) -> Bitmap {
+        match self {
+            DisplayFrame::DisplayFrame(bitmap) => *bitmap,
+            DisplayFrame::Heart => bitmap::HEART,
+            DisplayFrame::Smile => bitmap::SMILE,
+            DisplayFrame::Sad => bitmap::SAD,
+            DisplayFrame::QuestionMark => bitmap::QUESTION_MARK,
+            DisplayFrame::Left => bitmap::ARROW_LEFT,
+            DisplayFrame::Right => bitmap::ARROW_RIGHT,
+            DisplayFrame::Up => bitmap::ARROW_UP,
+            DisplayFrame::Down => bitmap::ARROW_DOWN,
+            DisplayFrame::Coord { x, y } => {
+                let mut frame = Bitmap::empty();
+                // convert from cartesian coordinates to display coordinates
+                let x = (x + 2).clamp(0, 4);
+                let y = (y + 2).clamp(0, 4);
+                frame.set(x as usize, y as usize);
+                frame
+            }
+            DisplayFrame::Letter(c) => {
+                // temporary
+                match c {
+                    'A' => bitmap::SMILE,
+                    'B' => bitmap::SAD,
+                    'C' => bitmap::ARROW_LEFT,
+                    'D' => bitmap::ARROW_UP,
+                    'E' => bitmap::ARROW_DOWN,
+                    'F' => bitmap::ARROW_RIGHT,
+                    _ => bitmap::QUESTION_MARK,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayAction {
+    /// Set the brightness of the display.
+    SetBrightness(Brightness),
+    /// Display a frame.
+    SetFrame {
+        frame: DisplayFrame,
+        duration: Option<Duration>,
+    },
+    /// Blank the display, including any held frame.
+    Clear,
+    Scroll(&'static str),
+    /// Scroll a profile's name, which unlike other text isn't known until it's shown
+    ScrollName(ProfileName),
+}
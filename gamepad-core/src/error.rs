@@ -0,0 +1,139 @@
+//! Error codes shared by the firmware, the LED matrix and the diagnostics characteristic.
+
+use gamepad_protocol::{LastError, Value};
+
+/// What went wrong, as shown on the display and reported over GATT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum ErrorCode {
+    /// The softdevice controller could not be brought up
+    BleInit = 1,
+    /// The GATT attribute table could not be built
+    GattServer = 2,
+    /// The BLE host runner stopped with an error
+    BleRunner = 3,
+    Advertising = 4,
+    Notify = 5,
+    /// The accelerometer and magnetometer didn't respond, motion is disabled
+    MotionSensor = 6,
+    /// A characteristic value could not be read or written
+    Attribute = 7,
+    /// The config store in flash could not be read or written
+    Config = 8,
+}
+
+/// How hard to try to get going again, in increasing order of disruption
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Recovery {
+    /// Carry on from advertising, nothing else is affected
+    RestartAdvertising,
+    /// Restart the BLE host before advertising again
+    RestartStack,
+    /// Nothing short of a system reset will help
+    Reset,
+}
+
+impl ErrorCode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::BleInit,
+            2 => Self::GattServer,
+            3 => Self::BleRunner,
+            4 => Self::Advertising,
+            5 => Self::Notify,
+            6 => Self::MotionSensor,
+            7 => Self::Attribute,
+            8 => Self::Config,
+            _ => return None,
+        })
+    }
+
+    /// The least disruptive way to recover from this error
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            Self::BleInit | Self::GattServer => Recovery::Reset,
+            Self::BleRunner => Recovery::RestartStack,
+            Self::Advertising
+            | Self::Notify
+            | Self::MotionSensor
+            | Self::Attribute
+            | Self::Config => Recovery::RestartAdvertising,
+        }
+    }
+
+    /// Short text to scroll across the display
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::BleInit => "E1",
+            Self::GattServer => "E2",
+            Self::BleRunner => "E3",
+            Self::Advertising => "E4",
+            Self::Notify => "E5",
+            Self::MotionSensor => "E6",
+            Self::Attribute => "E7",
+            Self::Config => "E8",
+        }
+    }
+}
+
+/// The most recent error, kept across resets so it can be read back afterwards
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ErrorRecord {
+    pub code: ErrorCode,
+    /// How many times in a row this code has been recorded
+    pub count: u16,
+    /// Seconds since boot when it last happened
+    pub uptime_secs: u32,
+}
+
+impl ErrorRecord {
+    pub const SIZE: usize = LastError::SIZE;
+
+    /// Encoded as a [`LastError`]
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        LastError {
+            code: self.code as u8,
+            count: self.count,
+            uptime_secs: self.uptime_secs,
+        }
+        .encode(&mut bytes);
+        bytes
+    }
+
+    /// `None` if the bytes don't hold a valid record, including all zeros for "no error"
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Option<Self> {
+        let record = LastError::decode(bytes).ok()?;
+        Some(Self {
+            code: ErrorCode::from_u8(record.code)?,
+            count: record.count,
+            uptime_secs: record.uptime_secs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_round_trip() {
+        let record = ErrorRecord {
+            code: ErrorCode::BleRunner,
+            count: 3,
+            uptime_secs: 0x0102_0304,
+        };
+        let bytes = record.to_bytes();
+        assert_eq!(bytes, [3, 0, 3, 0, 4, 3, 2, 1]);
+        assert_eq!(ErrorRecord::from_bytes(&bytes), Some(record));
+    }
+
+    #[test]
+    fn no_record() {
+        assert_eq!(ErrorRecord::from_bytes(&[0; 8]), None);
+        assert_eq!(ErrorRecord::from_bytes(&[0xff; 8]), None);
+    }
+}
@@ -0,0 +1,315 @@
+//! Orientation estimation from the accelerometer and magnetometer.
+//!
+//! The micro:bit has no gyroscope, so every step measures an absolute orientation from gravity
+//! and magnetic north, and a complementary filter blends it into the running estimate. Because
+//! the reference is absolute the yaw cannot drift, and the filter's time constant trades noise
+//! rejection for lag.
+
+use libm::sqrtf;
+
+/// A unit quaternion rotating sensor (body) coordinates into East-North-Up world coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub const IDENTITY: Self = Self {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn normalize(self) -> Self {
+        let norm = sqrtf(self.dot(&self));
+        Self {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    /// Rotate a body frame vector into the world frame
+    pub fn rotate(&self, v: [f32; 3]) -> [f32; 3] {
+        let Self { w, x, y, z } = *self;
+        [
+            (1.0 - 2.0 * (y * y + z * z)) * v[0]
+                + 2.0 * (x * y - w * z) * v[1]
+                + 2.0 * (x * z + w * y) * v[2],
+            2.0 * (x * y + w * z) * v[0]
+                + (1.0 - 2.0 * (x * x + z * z)) * v[1]
+                + 2.0 * (y * z - w * x) * v[2],
+            2.0 * (x * z - w * y) * v[0]
+                + 2.0 * (y * z + w * x) * v[1]
+                + (1.0 - 2.0 * (x * x + y * y)) * v[2],
+        ]
+    }
+
+    /// Fixed point Q1.14 encoding of each component, in w, x, y, z order
+    pub fn to_q14(&self) -> [i16; 4] {
+        [self.w, self.x, self.y, self.z].map(|c| (c * 16384.0) as i16)
+    }
+
+    /// Orientation measured from a single accelerometer and magnetometer sample.
+    ///
+    /// Returns `None` if either vector is zero or they are parallel.
+    pub fn from_gravity_and_field(accel: [f32; 3], mag: [f32; 3]) -> Option<Self> {
+        // at rest the accelerometer reads the reaction to gravity, which points up
+        let up = unit(accel)?;
+        let east = unit(cross(mag, up))?;
+        let north = cross(up, east);
+        Some(from_rows([east, north, up]))
+    }
+}
+
+/// A fixed-step complementary filter producing a stable orientation
+pub struct Fusion {
+    /// Blend factor towards the measurement each step
+    alpha: f32,
+    orientation: Option<Quaternion>,
+}
+
+impl Fusion {
+    /// `sample_rate_hz` is the fixed rate [`Fusion::update`] is called at, and `time_constant`
+    /// (seconds) how quickly the estimate follows the measurements.
+    pub fn new(sample_rate_hz: u32, time_constant: f32) -> Self {
+        let dt = 1.0 / sample_rate_hz as f32;
+        Self {
+            alpha: dt / (time_constant + dt),
+            orientation: None,
+        }
+    }
+
+    /// The current estimate, or identity before the first valid sample
+    pub fn orientation(&self) -> Quaternion {
+        self.orientation.unwrap_or(Quaternion::IDENTITY)
+    }
+
+    /// Advance the filter by one step with the latest accelerometer and magnetometer readings.
+    ///
+    /// Units don't matter as each vector is normalised. Degenerate readings are skipped.
+    pub fn update(&mut self, accel: [f32; 3], mag: [f32; 3]) -> Quaternion {
+        let Some(measured) = Quaternion::from_gravity_and_field(accel, mag) else {
+            return self.orientation();
+        };
+        let estimate = match self.orientation {
+            None => measured,
+            Some(current) => {
+                // q and -q are the same rotation, blend along the shorter path
+                let sign = if current.dot(&measured) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                let a = self.alpha;
+                Quaternion {
+                    w: current.w + a * (sign * measured.w - current.w),
+                    x: current.x + a * (sign * measured.x - current.x),
+                    y: current.y + a * (sign * measured.y - current.y),
+                    z: current.z + a * (sign * measured.z - current.z),
+                }
+                .normalize()
+            }
+        };
+        self.orientation = Some(estimate);
+        estimate
+    }
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn unit(v: [f32; 3]) -> Option<[f32; 3]> {
+    let norm = sqrtf(v[0] * v[0] + v[1] * v[1] + v[2] * v[2]);
+    (norm > f32::EPSILON).then(|| v.map(|c| c / norm))
+}
+
+/// Quaternion for the rotation matrix whose rows are the world axes in body coordinates
+fn from_rows(m: [[f32; 3]; 3]) -> Quaternion {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let q = if trace > 0.0 {
+        let s = sqrtf(trace + 1.0) * 2.0;
+        Quaternion {
+            w: s / 4.0,
+            x: (m[2][1] - m[1][2]) / s,
+            y: (m[0][2] - m[2][0]) / s,
+            z: (m[1][0] - m[0][1]) / s,
+        }
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = sqrtf(1.0 + m[0][0] - m[1][1] - m[2][2]) * 2.0;
+        Quaternion {
+            w: (m[2][1] - m[1][2]) / s,
+            x: s / 4.0,
+            y: (m[0][1] + m[1][0]) / s,
+            z: (m[0][2] + m[2][0]) / s,
+        }
+    } else if m[1][1] > m[2][2] {
+        let s = sqrtf(1.0 + m[1][1] - m[0][0] - m[2][2]) * 2.0;
+        Quaternion {
+            w: (m[0][2] - m[2][0]) / s,
+            x: (m[0][1] + m[1][0]) / s,
+            y: s / 4.0,
+            z: (m[1][2] + m[2][1]) / s,
+        }
+    } else {
+        let s = sqrtf(1.0 + m[2][2] - m[0][0] - m[1][1]) * 2.0;
+        Quaternion {
+            w: (m[1][0] - m[0][1]) / s,
+            x: (m[0][2] + m[2][0]) / s,
+            y: (m[1][2] + m[2][1]) / s,
+            z: s / 4.0,
+        }
+    };
+    q.normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 50;
+    /// Field pointing north and down into the ground, as in the northern hemisphere (nT)
+    const FIELD: [f32; 3] = [0.0, 20_000.0, -45_000.0];
+    const GRAVITY: [f32; 3] = [0.0, 0.0, 1000.0];
+
+    fn axis_angle(axis: [f32; 3], degrees: f32) -> Quaternion {
+        let [x, y, z] = unit(axis).unwrap();
+        let half = degrees.to_radians() / 2.0;
+        Quaternion {
+            w: half.cos(),
+            x: x * half.sin(),
+            y: y * half.sin(),
+            z: z * half.sin(),
+        }
+    }
+
+    /// What the sensors would read for a board with this orientation
+    fn readings(orientation: &Quaternion) -> ([f32; 3], [f32; 3]) {
+        let inverse = Quaternion {
+            w: orientation.w,
+            x: -orientation.x,
+            y: -orientation.y,
+            z: -orientation.z,
+        };
+        (inverse.rotate(GRAVITY), inverse.rotate(FIELD))
+    }
+
+    /// Angle between two orientations in degrees
+    fn error(a: &Quaternion, b: &Quaternion) -> f32 {
+        (2.0 * a.dot(b).abs().min(1.0).acos()).to_degrees()
+    }
+
+    /// Deterministic noise in -amplitude..amplitude
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self, amplitude: f32) -> f32 {
+            // xorshift32
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            ((self.0 >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0) * amplitude
+        }
+
+        fn add(&mut self, v: [f32; 3], amplitude: f32) -> [f32; 3] {
+            v.map(|c| c + self.next(amplitude))
+        }
+    }
+
+    fn orientations() -> [Quaternion; 5] {
+        [
+            Quaternion::IDENTITY,
+            axis_angle([0.0, 0.0, 1.0], 90.0),
+            axis_angle([0.0, 0.0, 1.0], -135.0),
+            axis_angle([1.0, 0.0, 0.0], 30.0),
+            axis_angle([1.0, -2.0, 0.5], 200.0),
+        ]
+    }
+
+    #[test]
+    fn measures_known_orientations() {
+        for truth in orientations() {
+            let (accel, mag) = readings(&truth);
+            let measured = Quaternion::from_gravity_and_field(accel, mag).unwrap();
+            assert!(error(&measured, &truth) < 0.1, "{truth:?} vs {measured:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_degenerate_readings() {
+        assert!(Quaternion::from_gravity_and_field([0.0; 3], FIELD).is_none());
+        assert!(Quaternion::from_gravity_and_field(GRAVITY, [0.0, 0.0, 5.0]).is_none());
+    }
+
+    #[test]
+    fn follows_a_turn_within_five_time_constants() {
+        let time_constant = 0.2;
+        let mut fusion = Fusion::new(RATE, time_constant);
+        let (accel, mag) = readings(&Quaternion::IDENTITY);
+        for _ in 0..RATE {
+            fusion.update(accel, mag);
+        }
+        let turned = axis_angle([0.0, 0.0, 1.0], 90.0);
+        let (accel, mag) = readings(&turned);
+        fusion.update(accel, mag);
+        assert!(error(&fusion.orientation(), &turned) > 60.0, "should lag");
+        for _ in 0..(5.0 * time_constant * RATE as f32) as u32 {
+            fusion.update(accel, mag);
+        }
+        assert!(error(&fusion.orientation(), &turned) < 1.0);
+    }
+
+    #[test]
+    fn smooths_noisy_readings() {
+        let mut noise = Noise(28);
+        let mut fusion = Fusion::new(RATE, 0.5);
+        let truth = axis_angle([0.3, 1.0, 0.0], 40.0);
+        let (accel, mag) = readings(&truth);
+        let (mut raw_error, mut fused_error) = (0.0, 0.0);
+        for step in 0..(10 * RATE) {
+            let accel = noise.add(accel, 100.0);
+            let mag = noise.add(mag, 5_000.0);
+            let fused = fusion.update(accel, mag);
+            // let the filter settle before comparing
+            if step >= RATE {
+                let raw = Quaternion::from_gravity_and_field(accel, mag).unwrap();
+                raw_error += error(&raw, &truth);
+                fused_error += error(&fused, &truth);
+            }
+        }
+        assert!(
+            fused_error < raw_error / 4.0,
+            "{fused_error} vs {raw_error}"
+        );
+    }
+
+    #[test]
+    fn yaw_does_not_drift() {
+        let mut noise = Noise(2028);
+        let mut fusion = Fusion::new(RATE, 0.5);
+        let truth = axis_angle([0.0, 0.0, 1.0], 60.0);
+        let (accel, mag) = readings(&truth);
+        // ten minutes of a stationary, noisy controller never wanders off
+        for step in 0..(600 * RATE) {
+            let fused = fusion.update(noise.add(accel, 20.0), noise.add(mag, 500.0));
+            if step >= RATE {
+                assert!(error(&fused, &truth) < 2.0, "step {step}");
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Motion gesture recognition from a stream of accelerometer samples.
+//!
+//! The detector is fed one sample at a time at [`SAMPLE_RATE_HZ`], with each axis in milli-g
+//! using the micro:bit convention that a board lying face up reads roughly `(0, 0, -1000)`.
+
+pub use gamepad_protocol::Gesture;
+
+/// Rate the detector expects samples to arrive at
+pub const SAMPLE_RATE_HZ: u32 = 50;
+
+/// Total acceleration below this (milli-g) counts as falling
+const FREEFALL_MG: i32 = 400;
+/// Consecutive falling samples before freefall is reported
+const FREEFALL_SAMPLES: u8 = 5;
+/// Sample to sample change in total acceleration (milli-g) that counts as a jolt
+const JOLT_MG: i32 = 700;
+/// A tap is a jolt lasting no more than this many samples...
+const TAP_MAX_SAMPLES: u8 = 3;
+/// ...followed by at least this many quiet samples
+const TAP_QUIET_SAMPLES: u8 = 3;
+/// A second tap within this many samples of the first makes a double tap
+const DOUBLE_TAP_WINDOW: u8 = 20;
+/// Jolts within the last second needed to call it a shake
+const SHAKE_JOLTS: u32 = 10;
+/// Jolts within the last second above which taps are ignored
+const BUSY_JOLTS: u32 = 6;
+/// Samples to ignore taps and shakes for after a shake or a fall
+const COOLDOWN_SAMPLES: u8 = 50;
+/// Gravity along z (milli-g) needed to be considered lying flat
+const FLAT_MG: i32 = 800;
+/// Samples the board must stay flat before the orientation is reported
+const FLAT_SAMPLES: u8 = 25;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Orientation {
+    Unknown,
+    FaceUp,
+    FaceDown,
+}
+
+/// Classifies accelerometer samples into [`Gesture`]s
+pub struct GestureDetector {
+    /// Total acceleration of the previous sample
+    last_magnitude: Option<i32>,
+    /// One bit per sample for the last second, set if that sample was a jolt
+    jolts: u64,
+    /// Length of the jolt currently in progress
+    jolt_len: u8,
+    /// Quiet samples since the last jolt ended
+    quiet: u8,
+    /// Age in samples of a tap waiting to see if it becomes a double tap
+    pending_tap: Option<u8>,
+    falling: u8,
+    fell: bool,
+    cooldown: u8,
+    flat: u8,
+    candidate: Orientation,
+    orientation: Orientation,
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GestureDetector {
+    pub const fn new() -> Self {
+        Self {
+            last_magnitude: None,
+            jolts: 0,
+            jolt_len: 0,
+            quiet: 0,
+            pending_tap: None,
+            falling: 0,
+            fell: false,
+            cooldown: 0,
+            flat: 0,
+            candidate: Orientation::Unknown,
+            orientation: Orientation::Unknown,
+        }
+    }
+
+    /// Feed the next sample, returning a gesture if one has just completed.
+    ///
+    /// At most one gesture is reported per sample; anything else that completed at the same
+    /// time is reported on a following sample.
+    pub fn update(&mut self, accel: [i32; 3]) -> Option<Gesture> {
+        let [x, y, z] = accel;
+        let magnitude = isqrt(x * x + y * y + z * z);
+        let jolt = self
+            .last_magnitude
+            .is_some_and(|last| (magnitude - last).abs() > JOLT_MG);
+        self.last_magnitude = Some(magnitude);
+        self.jolts = (self.jolts << 1 | jolt as u64) & ((1 << SAMPLE_RATE_HZ) - 1);
+        self.cooldown = self.cooldown.saturating_sub(1);
+        if let Some(age) = self.pending_tap.as_mut() {
+            *age = age.saturating_add(1);
+        }
+
+        self.freefall(magnitude)
+            .or_else(|| self.shake())
+            .or_else(|| self.tap(jolt))
+            .or_else(|| self.orientation(z, magnitude))
+    }
+
+    fn freefall(&mut self, magnitude: i32) -> Option<Gesture> {
+        if magnitude >= FREEFALL_MG {
+            self.falling = 0;
+            self.fell = false;
+            return None;
+        }
+        self.falling = self.falling.saturating_add(1);
+        if self.falling < FREEFALL_SAMPLES {
+            return None;
+        }
+        // the landing will jolt, which is not a tap
+        self.cooldown = COOLDOWN_SAMPLES;
+        self.pending_tap = None;
+        if !self.fell {
+            self.fell = true;
+            return Some(Gesture::Freefall);
+        }
+        None
+    }
+
+    fn shake(&mut self) -> Option<Gesture> {
+        if self.cooldown == 0 && self.jolts.count_ones() >= SHAKE_JOLTS {
+            self.cooldown = COOLDOWN_SAMPLES;
+            self.pending_tap = None;
+            self.jolt_len = 0;
+            return Some(Gesture::Shake);
+        }
+        None
+    }
+
+    fn tap(&mut self, jolt: bool) -> Option<Gesture> {
+        if jolt {
+            self.jolt_len = self.jolt_len.saturating_add(1);
+            self.quiet = 0;
+        } else if self.jolt_len > 0 {
+            self.quiet += 1;
+            if self.quiet >= TAP_QUIET_SAMPLES {
+                let short = self.jolt_len <= TAP_MAX_SAMPLES;
+                self.jolt_len = 0;
+                let busy = self.jolts.count_ones() > BUSY_JOLTS;
+                if short && !busy && self.cooldown == 0 {
+                    if self.pending_tap.take().is_some() {
+                        return Some(Gesture::DoubleTap);
+                    }
+                    self.pending_tap = Some(0);
+                }
+            }
+        }
+        if self.jolt_len == 0 && self.pending_tap.is_some_and(|age| age > DOUBLE_TAP_WINDOW) {
+            self.pending_tap = None;
+            return Some(Gesture::SingleTap);
+        }
+        None
+    }
+
+    fn orientation(&mut self, z: i32, magnitude: i32) -> Option<Gesture> {
+        // only trust gravity when the board is otherwise still
+        let still = (magnitude - 1000).abs() < 200;
+        let candidate = match z {
+            z if still && z < -FLAT_MG => Orientation::FaceUp,
+            z if still && z > FLAT_MG => Orientation::FaceDown,
+            _ => Orientation::Unknown,
+        };
+        if candidate != self.candidate {
+            self.candidate = candidate;
+            self.flat = 0;
+        }
+        self.flat = self.flat.saturating_add(1);
+        if self.flat < FLAT_SAMPLES || candidate == self.orientation {
+            return None;
+        }
+        self.orientation = candidate;
+        match candidate {
+            Orientation::FaceUp => Some(Gesture::FaceUp),
+            Orientation::FaceDown => Some(Gesture::FaceDown),
+            Orientation::Unknown => None,
+        }
+    }
+}
+
+/// Integer square root, enough precision for milli-g magnitudes
+fn isqrt(value: i32) -> i32 {
+    if value <= 0 {
+        return 0;
+    }
+    let mut root = value;
+    let mut next = (root + 1) / 2;
+    while next < root {
+        root = next;
+        next = (root + value / root) / 2;
+    }
+    root
+}
@@ -0,0 +1,161 @@
+//! The hardware the gamepad logic needs, so it can run on the micro:bit or against mocks on a
+//! host.
+//!
+//! Inputs are owned by one task and take `&mut self`. Outputs are shared between tasks and take
+//! `&self`, implementations queue the request and return rather than owning the peripheral.
+#![allow(async_fn_in_trait)]
+
+use embassy_time::{Duration, Timer};
+use gamepad_protocol::{hid::HidReport, ConnectionParams, ProfileName};
+
+use crate::{
+    advertising::Phase,
+    audio::{AudioAction, Note, Tune, Volume},
+    controller::Indicator,
+    display::{Brightness, DisplayAction, DisplayFrame},
+    input::Report,
+};
+
+/// A button or other pin that is pulled high and reads low while active
+pub trait DigitalInput {
+    async fn wait_for_low(&mut self);
+    async fn wait_for_high(&mut self);
+    fn is_low(&mut self) -> bool;
+}
+
+/// `N` analog channels sampled together
+pub trait AnalogSampler<const N: usize> {
+    /// Run once before sampling, the default does nothing
+    async fn calibrate(&mut self) {}
+    async fn sample(&mut self, buf: &mut [i16; N]);
+}
+
+/// The 5x5 LED matrix
+pub trait Display5x5 {
+    async fn apply(&self, action: DisplayAction);
+
+    async fn set_brightness(&self, brightness: Brightness) {
+        self.apply(DisplayAction::SetBrightness(brightness)).await;
+    }
+
+    async fn clear(&self) {
+        self.apply(DisplayAction::Clear).await;
+    }
+
+    async fn scroll(&self, text: &'static str) {
+        self.apply(DisplayAction::Scroll(text)).await;
+    }
+
+    async fn scroll_name(&self, name: ProfileName) {
+        self.apply(DisplayAction::ScrollName(name)).await;
+    }
+
+    /// Non-blocking display
+    async fn display(&self, frame: DisplayFrame, duration: Duration) {
+        self.apply(DisplayAction::SetFrame {
+            frame,
+            duration: Some(duration),
+        })
+        .await;
+    }
+
+    /// Display until something else is shown
+    async fn display_held(&self, frame: DisplayFrame) {
+        self.apply(DisplayAction::SetFrame {
+            frame,
+            duration: None,
+        })
+        .await;
+    }
+
+    /// Blocking display
+    async fn display_blocking(&self, frame: DisplayFrame, duration: Duration) {
+        self.display(frame, duration).await;
+        Timer::after(duration).await;
+    }
+
+    /// Show what state the controller is in
+    async fn indicate(&self, indicator: Indicator) {
+        match indicator {
+            Indicator::Booting => self.scroll("BLE!").await,
+            Indicator::Advertising(Phase::Fast) => {
+                self.display_held(DisplayFrame::QuestionMark).await
+            }
+            // a single dot, to show it's still on without lighting the whole matrix for minutes
+            Indicator::Advertising(Phase::Slow) => {
+                self.display_held(DisplayFrame::Coord { x: 0, y: 0 }).await
+            }
+            Indicator::Connected => {
+                self.display_blocking(DisplayFrame::Heart, Duration::from_secs(1))
+                    .await
+            }
+            // wave it in a figure of eight
+            Indicator::Calibrating => self.scroll("8").await,
+            Indicator::Off => self.clear().await,
+            Indicator::Error(code) => {
+                self.scroll(code.label()).await;
+                self.display_held(DisplayFrame::Sad).await;
+            }
+        }
+    }
+}
+
+/// The speaker
+pub trait ToneOutput {
+    async fn apply(&self, action: AudioAction);
+
+    /// Play a note on the speaker
+    async fn play_note(&self, note: Note) {
+        self.apply(AudioAction::PlayNote(note)).await;
+    }
+
+    /// Play a sequence of notes on the speaker
+    async fn play_tune(&self, tune: Tune) {
+        self.apply(AudioAction::PlayTune(tune)).await;
+    }
+
+    /// Play everything after this at `volume`
+    async fn set_volume(&self, volume: Volume) {
+        self.apply(AudioAction::SetVolume(volume)).await;
+    }
+}
+
+/// Where input changes are sent, normally GATT notifications to the connected central
+pub trait ReportSink {
+    type Error;
+
+    async fn report(&self, report: Report) -> Result<(), Self::Error>;
+}
+
+/// Where HID input reports are sent, normally the HID service's report characteristics
+pub trait HidOutput {
+    type Error;
+
+    async fn send(&self, report: HidReport) -> Result<(), Self::Error>;
+}
+
+/// The connection to the central
+pub trait Link {
+    /// Ask the central to switch to `params`. It may refuse or pick other values in the range,
+    /// which isn't an error, so implementations log the outcome rather than return it.
+    async fn request_params(&self, params: ConnectionParams);
+}
+
+/// A region of NOR flash. Erasing sets a whole page to `0xff` and writes can only clear bits.
+/// Offsets are from the start of the region.
+pub trait Flash {
+    type Error: core::fmt::Debug;
+
+    /// Bytes in a page, the unit of erasing
+    const PAGE_SIZE: u32;
+    /// Writes start on a multiple of this and are a multiple of it long
+    const WRITE_SIZE: u32;
+
+    /// How many pages the region has
+    fn pages(&self) -> u32;
+
+    async fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+    async fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+    /// Erase the page starting at `offset`
+    async fn erase(&mut self, offset: u32) -> Result<(), Self::Error>;
+}
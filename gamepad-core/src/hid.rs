@@ -0,0 +1,502 @@
+//! Being a keyboard and mouse, or a media remote, instead of a gamepad.
+//!
+//! In [`HidMode::KeyboardMouse`] the input tasks run just as they do for the gamepad services,
+//! and their reports are batched the same way, but they end up at [`KeyboardMouse`] rather than
+//! the button and stick characteristics. Each button presses whatever the [`KeyMap`] binds it
+//! to, and while the stick is off centre the pointer moves in steps that grow the further it
+//! is pushed. With the stick centred, tilting the board moves the pointer the same way, faster
+//! the further it is tipped.
+//!
+//! [`HidMode::MediaRemote`] sends them to [`MediaRemote`] instead, which has fixed controls for
+//! presenting and listening: A and B turn slides with page down and page up, which
+//! presentation software takes where it ignores the track keys, C plays or pauses, as does a
+//! shake, the stick's x holds fast forward or rewind, and its y steps the volume, faster the
+//! further it's pushed.
+
+use core::{
+    cell::{Cell, RefCell},
+    convert::Infallible,
+};
+
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+pub use gamepad_protocol::hid::{
+    key, Binding, ConsumerReport, HidMode, HidReport, KeyMap, KeyboardReport, MediaControl,
+    MouseReport,
+};
+use gamepad_protocol::value::AXIS_MAX;
+
+use crate::{
+    config::Settings,
+    hal::{HidOutput, ReportSink},
+    input::{AxisId, ButtonId, Report},
+};
+
+/// Time between pointer movements while the stick is off centre
+pub const POINTER_INTERVAL: Duration = Duration::from_millis(10);
+/// How far the pointer moves each interval at each stick level, from the centre out
+pub const POINTER_STEPS: [i8; AXIS_MAX as usize + 1] = [0, 1, 3, 8];
+/// Tilt, in mg along the accelerometer's x or y, that moves the pointer at each level after
+/// the centre. Below the first, about 15°, the board is taken to be held level.
+pub const TILT_LEVELS_MG: [i32; AXIS_MAX as usize] = [250, 450, 650];
+/// Time between volume steps while the stick is pushed up or down, at each level from the
+/// centre out
+pub const VOLUME_INTERVALS: [Duration; AXIS_MAX as usize + 1] = [
+    Duration::MAX,
+    Duration::from_millis(400),
+    Duration::from_millis(200),
+    Duration::from_millis(80),
+];
+
+struct Held {
+    /// What each button pressed, so it releases the same thing if the key map changes
+    bindings: [Binding; 6],
+    /// Keys down, in the order they went down
+    keys: [u8; 6],
+    stick: [i8; 2],
+    /// Levels for how far the board is tipped, like the stick's
+    tilt: [i8; 2],
+}
+
+impl Held {
+    fn keyboard(&self) -> KeyboardReport {
+        let mut modifiers = 0;
+        for binding in self.bindings {
+            if let Binding::Modifier(bit) = binding {
+                modifiers |= 1 << bit;
+            }
+        }
+        KeyboardReport {
+            modifiers,
+            keys: self.keys,
+        }
+    }
+
+    fn mouse(&self, x: i8, y: i8) -> MouseReport {
+        let mut buttons = 0;
+        for binding in self.bindings {
+            if let Binding::MouseButton(button) = binding {
+                buttons |= 1 << button;
+            }
+        }
+        MouseReport { buttons, x, y }
+    }
+
+    /// The levels the pointer moves at, the stick's unless it's centred
+    fn aim(&self) -> [i8; 2] {
+        match self.stick {
+            [0, 0] => self.tilt,
+            stick => stick,
+        }
+    }
+}
+
+/// Turns input reports into keyboard and mouse reports, sent to `O`
+pub struct KeyboardMouse<'a, O> {
+    out: &'a O,
+    settings: &'a Cell<Settings>,
+    held: RefCell<Held>,
+    moved: Signal<NoopRawMutex, ()>,
+}
+
+impl<'a, O: HidOutput> KeyboardMouse<'a, O> {
+    /// Bind buttons as the key map in `settings` says when they're pressed
+    pub fn new(out: &'a O, settings: &'a Cell<Settings>) -> Self {
+        Self {
+            out,
+            settings,
+            held: RefCell::new(Held {
+                bindings: [Binding::Nothing; 6],
+                keys: [0; 6],
+                stick: [0; 2],
+                tilt: [0; 2],
+            }),
+            moved: Signal::new(),
+        }
+    }
+
+    /// Move the pointer every [`POINTER_INTERVAL`] while the stick is off centre or the board
+    /// is tilted, for as long as the reports can be sent. Stick levels count right and down, as
+    /// the pointer does.
+    pub async fn pointer(&self) -> Result<Infallible, O::Error> {
+        loop {
+            let [x, y] = self.held.borrow().aim();
+            if x == 0 && y == 0 {
+                self.moved.wait().await;
+                continue;
+            }
+            let report = self.held.borrow().mouse(step(x), step(y));
+            self.out.send(HidReport::Mouse(report)).await?;
+            Timer::after(POINTER_INTERVAL).await;
+        }
+    }
+
+    /// Take the board's tilt from an accelerometer reading in mg, x then y then z. Tipping it
+    /// right or towards its bottom edge counts the way the stick does.
+    pub fn tilt(&self, accel: [i32; 3]) {
+        let tilt = [tilt_level(accel[0]), tilt_level(accel[1])];
+        let mut held = self.held.borrow_mut();
+        if held.tilt != tilt {
+            held.tilt = tilt;
+            self.moved.signal(());
+        }
+    }
+
+    /// Press or release what `button` is bound to, the report to send if that changed anything
+    fn press(&self, button: ButtonId, pressed: bool) -> Option<HidReport> {
+        let mut held = self.held.borrow_mut();
+        let binding = match pressed {
+            true => self.settings.get().key_map.0[button as usize],
+            false => held.bindings[button as usize],
+        };
+        held.bindings[button as usize] = if pressed { binding } else { Binding::Nothing };
+        match binding {
+            Binding::Nothing => None,
+            Binding::Key(usage) => {
+                // another button bound to the same key keeps it down
+                let down = held.bindings.contains(&binding);
+                let slot = held.keys.iter().position(|&key| key == usage);
+                match (down, slot) {
+                    (true, None) => {
+                        // a seventh key is left out, as a keyboard would
+                        if let Some(free) = held.keys.iter_mut().find(|key| **key == 0) {
+                            *free = usage;
+                        }
+                    }
+                    (false, Some(slot)) => {
+                        held.keys.copy_within(slot + 1.., slot);
+                        held.keys[5] = 0;
+                    }
+                    _ => {}
+                }
+                Some(HidReport::Keyboard(held.keyboard()))
+            }
+            Binding::Modifier(_) => Some(HidReport::Keyboard(held.keyboard())),
+            Binding::MouseButton(_) => Some(HidReport::Mouse(held.mouse(0, 0))),
+        }
+    }
+}
+
+/// Pointer movement for a stick level
+fn step(level: i8) -> i8 {
+    POINTER_STEPS[level.unsigned_abs().min(AXIS_MAX as u8) as usize] * level.signum()
+}
+
+/// Stick level for a tilt along one axis
+fn tilt_level(mg: i32) -> i8 {
+    let level = TILT_LEVELS_MG.iter().filter(|&&at| mg.abs() >= at).count() as i8;
+    level * mg.signum() as i8
+}
+
+/// Buttons are sent on at once. Stick levels only change the pointer's speed, so sending can
+/// only fail for a button.
+impl<O: HidOutput> ReportSink for KeyboardMouse<'_, O> {
+    type Error = O::Error;
+
+    async fn report(&self, report: Report) -> Result<(), Self::Error> {
+        match report {
+            Report::Button { button, pressed } => {
+                if let Some(report) = self.press(button, pressed) {
+                    self.out.send(report).await?;
+                }
+            }
+            Report::Axis { axis, value } => {
+                self.held.borrow_mut().stick[axis as usize] = value;
+                self.moved.signal(());
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Remote {
+    /// Slide keys down, in the order they went down
+    slides: [u8; 2],
+    /// Media controls held by buttons or the stick
+    controls: ConsumerReport,
+    stick: [i8; 2],
+}
+
+/// Turns input reports into slide keys and media controls, sent to `O`
+pub struct MediaRemote<'a, O> {
+    out: &'a O,
+    held: RefCell<Remote>,
+    moved: Signal<NoopRawMutex, ()>,
+}
+
+impl<'a, O: HidOutput> MediaRemote<'a, O> {
+    pub fn new(out: &'a O) -> Self {
+        Self {
+            out,
+            held: RefCell::new(Remote {
+                slides: [0; 2],
+                controls: ConsumerReport::default(),
+                stick: [0; 2],
+            }),
+            moved: Signal::new(),
+        }
+    }
+
+    /// Step the volume every [`VOLUME_INTERVALS`] while the stick is pushed up or down, for as
+    /// long as the reports can be sent
+    pub async fn volume(&self) -> Result<Infallible, O::Error> {
+        loop {
+            let y = self.held.borrow().stick[1];
+            if y == 0 {
+                self.moved.wait().await;
+                continue;
+            }
+            // stick levels count down, so up is negative
+            let control = match y < 0 {
+                true => MediaControl::VolumeUp,
+                false => MediaControl::VolumeDown,
+            };
+            self.tap(control).await?;
+            let level = y.unsigned_abs().min(AXIS_MAX as u8) as usize;
+            Timer::after(VOLUME_INTERVALS[level]).await;
+        }
+    }
+
+    /// Play or pause, for a shake
+    pub async fn play_pause(&self) -> Result<(), O::Error> {
+        self.tap(MediaControl::PlayPause).await
+    }
+
+    /// Press and release `control`, leaving whatever else is held down
+    async fn tap(&self, control: MediaControl) -> Result<(), O::Error> {
+        let controls = self.held.borrow().controls;
+        self.out
+            .send(HidReport::Consumer(controls.with(control)))
+            .await?;
+        let controls = self.held.borrow().controls;
+        self.out.send(HidReport::Consumer(controls)).await
+    }
+
+    fn slide(&self, usage: u8, pressed: bool) -> HidReport {
+        let mut held = self.held.borrow_mut();
+        let slot = held.slides.iter().position(|&key| key == usage);
+        match (pressed, slot) {
+            (true, None) => {
+                if let Some(free) = held.slides.iter_mut().find(|key| **key == 0) {
+                    *free = usage;
+                }
+            }
+            (false, Some(slot)) => {
+                held.slides.copy_within(slot + 1.., slot);
+                held.slides[1] = 0;
+            }
+            _ => {}
+        }
+        let mut keys = [0; 6];
+        keys[..2].copy_from_slice(&held.slides);
+        HidReport::Keyboard(KeyboardReport { modifiers: 0, keys })
+    }
+
+    /// Hold `control` alone out of `group`, or none of them, the report to send if that
+    /// changed anything
+    fn hold(&self, group: &[MediaControl], control: Option<MediaControl>) -> Option<HidReport> {
+        let mut held = self.held.borrow_mut();
+        let mut controls = held.controls;
+        for other in group {
+            controls.controls &= !other.bit();
+        }
+        if let Some(control) = control {
+            controls = controls.with(control);
+        }
+        if controls == held.controls {
+            return None;
+        }
+        held.controls = controls;
+        Some(HidReport::Consumer(controls))
+    }
+}
+
+/// Buttons and the stick's x are sent on at once. The stick's y only changes how fast the
+/// volume steps.
+impl<O: HidOutput> ReportSink for MediaRemote<'_, O> {
+    type Error = O::Error;
+
+    async fn report(&self, report: Report) -> Result<(), Self::Error> {
+        const PLAY: &[MediaControl] = &[MediaControl::PlayPause];
+        const SEEK: &[MediaControl] = &[MediaControl::FastForward, MediaControl::Rewind];
+        let report = match report {
+            Report::Button { button, pressed } => match button {
+                ButtonId::A => Some(self.slide(key::PAGE_DOWN, pressed)),
+                ButtonId::B => Some(self.slide(key::PAGE_UP, pressed)),
+                ButtonId::C => self.hold(PLAY, pressed.then_some(MediaControl::PlayPause)),
+                _ => None,
+            },
+            Report::Axis {
+                axis: AxisId::X,
+                value,
+            } => self.hold(
+                SEEK,
+                match value.signum() {
+                    1 => Some(MediaControl::FastForward),
+                    -1 => Some(MediaControl::Rewind),
+                    _ => None,
+                },
+            ),
+            Report::Axis {
+                axis: AxisId::Y,
+                value,
+            } => {
+                self.held.borrow_mut().stick[1] = value;
+                self.moved.signal(());
+                None
+            }
+        };
+        if let Some(report) = report {
+            self.out.send(report).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<HidReport>>);
+
+    impl HidOutput for Recorder {
+        type Error = Infallible;
+
+        async fn send(&self, report: HidReport) -> Result<(), Self::Error> {
+            self.0.borrow_mut().push(report);
+            Ok(())
+        }
+    }
+
+    fn keys(modifiers: u8, keys: &[u8]) -> HidReport {
+        let mut report = KeyboardReport {
+            modifiers,
+            keys: [0; 6],
+        };
+        report.keys[..keys.len()].copy_from_slice(keys);
+        HidReport::Keyboard(report)
+    }
+
+    fn press(sink: &impl ReportSink, button: ButtonId, pressed: bool) {
+        let _ = block_on(sink.report(Report::Button { button, pressed }));
+    }
+
+    #[test]
+    fn keys_go_down_in_order_and_come_up_when_released() {
+        let out = Recorder::default();
+        let mut settings = Settings::default();
+        settings.key_map.0[4] = Binding::Modifier(1);
+        // C and D both press enter
+        settings.key_map.0[3] = Binding::Key(key::ENTER);
+        let settings = Cell::new(settings);
+        let keyboard = KeyboardMouse::new(&out, &settings);
+        press(&keyboard, ButtonId::A, true);
+        press(&keyboard, ButtonId::C, true);
+        press(&keyboard, ButtonId::E, true);
+        press(&keyboard, ButtonId::D, true);
+        press(&keyboard, ButtonId::A, false);
+        press(&keyboard, ButtonId::C, false);
+        press(&keyboard, ButtonId::D, false);
+        press(&keyboard, ButtonId::E, false);
+        assert_eq!(
+            out.0.take(),
+            [
+                keys(0, &[key::SPACE]),
+                keys(0, &[key::SPACE, key::ENTER]),
+                keys(0b10, &[key::SPACE, key::ENTER]),
+                keys(0b10, &[key::SPACE, key::ENTER]),
+                keys(0b10, &[key::ENTER]),
+                keys(0b10, &[key::ENTER]),
+                keys(0b10, &[]),
+                keys(0, &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_release_follows_the_press_when_the_map_changes() {
+        let out = Recorder::default();
+        let settings = Cell::new(Settings::default());
+        let keyboard = KeyboardMouse::new(&out, &settings);
+        press(&keyboard, ButtonId::B, true);
+        let mut changed = settings.get();
+        changed.key_map.0[1] = Binding::Nothing;
+        settings.set(changed);
+        press(&keyboard, ButtonId::B, false);
+        press(&keyboard, ButtonId::B, true);
+        let click = |buttons| {
+            HidReport::Mouse(MouseReport {
+                buttons,
+                x: 0,
+                y: 0,
+            })
+        };
+        assert_eq!(out.0.take(), [click(1), click(0)]);
+    }
+
+    #[test]
+    fn the_remote_turns_slides_and_seeks() {
+        let out = Recorder::default();
+        let remote = MediaRemote::new(&out);
+        press(&remote, ButtonId::A, true);
+        press(&remote, ButtonId::B, true);
+        press(&remote, ButtonId::A, false);
+        press(&remote, ButtonId::B, false);
+        press(&remote, ButtonId::D, true);
+        for value in [2, 3, -1, 0] {
+            let _ = block_on(remote.report(Report::Axis {
+                axis: AxisId::X,
+                value,
+            }));
+        }
+        block_on(remote.play_pause()).unwrap();
+        let media = |controls: &[MediaControl]| {
+            HidReport::Consumer(
+                controls
+                    .iter()
+                    .fold(ConsumerReport::default(), |report, &c| report.with(c)),
+            )
+        };
+        assert_eq!(
+            out.0.take(),
+            [
+                keys(0, &[key::PAGE_DOWN]),
+                keys(0, &[key::PAGE_DOWN, key::PAGE_UP]),
+                keys(0, &[key::PAGE_UP]),
+                keys(0, &[]),
+                media(&[MediaControl::FastForward]),
+                media(&[MediaControl::Rewind]),
+                media(&[]),
+                media(&[MediaControl::PlayPause]),
+                media(&[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn pointer_steps_grow_with_the_stick() {
+        assert_eq!([-3, -1, 0, 2, 3].map(step), [-8, -1, 0, 3, 8]);
+    }
+
+    #[test]
+    fn tilt_aims_the_pointer_while_the_stick_is_centred() {
+        assert_eq!(
+            [-900, -300, 100, 250, 500].map(tilt_level),
+            [-3, -1, 0, 1, 2]
+        );
+        let out = Recorder::default();
+        let settings = Cell::new(Settings::default());
+        let mouse = KeyboardMouse::new(&out, &settings);
+        mouse.tilt([300, -700, 1000]);
+        assert_eq!(mouse.held.borrow().aim(), [1, -3]);
+        block_on(mouse.report(Report::Axis {
+            axis: AxisId::Y,
+            value: 2,
+        }))
+        .unwrap();
+        assert_eq!(mouse.held.borrow().aim(), [0, 2]);
+    }
+}
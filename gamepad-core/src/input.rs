@@ -0,0 +1,390 @@
+//! Turning raw button and analog stick readings into reports for the central.
+//!
+//! Buttons are debounced and the stick is quantised to a few levels per axis, so only real
+//! changes are reported and the link isn't flooded with noise.
+
+use embassy_futures::select::{select3, select_array, Either3};
+use embassy_time::{Duration, Instant, Timer};
+
+use core::cell::Cell;
+
+use gamepad_protocol::{value::AXIS_MAX, Notification};
+pub use gamepad_protocol::{AxisId, ButtonId, Curve};
+
+use crate::{
+    config::Settings,
+    display::DisplayFrame,
+    hal::{AnalogSampler, DigitalInput, Display5x5, ReportSink},
+    profile::PROFILE_CHORD,
+};
+
+/// How long a button has to settle after changing before it is read again, unless the debounce
+/// setting says otherwise
+pub const BUTTON_DEBOUNCE: Duration = Duration::from_millis(50);
+/// Time between analog stick samples, unless the report rate setting says otherwise
+pub const STICK_INTERVAL: Duration = Duration::from_millis(20);
+/// Time between a turbo button's reported presses and releases while it's held, so it fires
+/// ten times a second
+pub const TURBO_INTERVAL: Duration = Duration::from_millis(50);
+/// Analog stick full range is around 3740, centred on half of that
+pub const STICK_OFFSET: i16 = 3740 / 2;
+/// Divides the stick range into -3..=3
+pub const STICK_DIVIDER: i16 = 623;
+
+/// A change of input to send to the central
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Report {
+    Button { button: ButtonId, pressed: bool },
+    Axis { axis: AxisId, value: i8 },
+}
+
+impl From<Report> for Notification {
+    fn from(report: Report) -> Self {
+        match report {
+            Report::Button { button, pressed } => Notification::Button { button, pressed },
+            Report::Axis { axis, value } => Notification::Axis { axis, value },
+        }
+    }
+}
+
+/// A button and the input it is read from
+pub struct GamepadButton<I> {
+    pub id: ButtonId,
+    pub input: I,
+}
+
+/// All of the gamepad's buttons
+pub struct GamepadInputs<I> {
+    pub buttons: [GamepadButton<I>; 6],
+}
+
+impl<I: DigitalInput> GamepadInputs<I> {
+    pub fn new(a: I, b: I, c: I, d: I, e: I, f: I) -> Self {
+        let mut inputs = [a, b, c, d, e, f].into_iter();
+        Self {
+            buttons: ButtonId::ALL.map(|id| GamepadButton {
+                id,
+                input: inputs.next().unwrap(),
+            }),
+        }
+    }
+}
+
+/// Contact bounce filter. A change is reported as soon as it is seen, then the contact is left to
+/// settle, for [`BUTTON_DEBOUNCE`] by default, before it is looked at again.
+///
+/// It is fed the pin's edges as they happen, so a recording of them can be filtered on a host
+/// exactly as the button task filters the pin.
+#[derive(Clone, Copy, Debug)]
+pub struct Debounce {
+    low: bool,
+    pressed: bool,
+    settled_at: Instant,
+    time: Duration,
+}
+
+impl Debounce {
+    pub const fn new() -> Self {
+        Self {
+            low: false,
+            pressed: false,
+            settled_at: Instant::from_ticks(0),
+            time: BUTTON_DEBOUNCE,
+        }
+    }
+
+    /// Leave the contact to settle for `time` after the next change
+    pub fn set_time(&mut self, time: Duration) {
+        self.time = time;
+    }
+
+    /// The last level seen on the pin
+    pub fn is_low(&self) -> bool {
+        self.low
+    }
+
+    /// When a change seen while the contact was settling is due to be reported
+    pub fn pending(&self) -> Option<Instant> {
+        (self.low != self.pressed).then_some(self.settled_at)
+    }
+
+    /// The pin went `low` (or high) at `now`, the new pressed state if that's to be reported
+    pub fn edge(&mut self, now: Instant, low: bool) -> Option<bool> {
+        self.low = low;
+        self.poll(now)
+    }
+
+    /// The pressed state if a change is due to be reported by `now`
+    pub fn poll(&mut self, now: Instant) -> Option<bool> {
+        if now < self.settled_at || self.low == self.pressed {
+            return None;
+        }
+        self.pressed = self.low;
+        self.settled_at = now + self.time;
+        Some(self.pressed)
+    }
+}
+
+impl Default for Debounce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Report whenever this button is pressed or released, as the button it is mapped to. While a
+/// button the turbo setting has is held, it's reported released and pressed again every
+/// [`TURBO_INTERVAL`], except for the [`PROFILE_CHORD`] which would switch profiles each time.
+pub async fn notify_button_state<I: DigitalInput, S: ReportSink>(
+    button: &mut GamepadButton<I>,
+    display: &impl Display5x5,
+    sink: &S,
+    settings: &Cell<Settings>,
+) -> Result<(), S::Error> {
+    let mut debounce = Debounce::new();
+    // a release goes to whatever the press went to, even if the mapping changed in between
+    let mut reported = button.id;
+    // whether the central last heard it pressed, which turbo changes while it's held
+    let mut down = false;
+    // when turbo next changes that
+    let mut turbo: Option<Instant> = None;
+    loop {
+        let settings = settings.get();
+        debounce.set_time(Duration::from_millis(settings.debounce_ms.into()));
+        // watch every edge, bounces included, until a change has settled
+        let low = !debounce.is_low();
+        let edge = async {
+            if low {
+                button.input.wait_for_low().await;
+            } else {
+                button.input.wait_for_high().await;
+            }
+        };
+        let settled = async {
+            match debounce.pending() {
+                Some(at) => Timer::at(at).await,
+                None => core::future::pending().await,
+            }
+        };
+        let fire = async {
+            match turbo {
+                Some(at) => Timer::at(at).await,
+                None => core::future::pending().await,
+            }
+        };
+        let pressed = match select3(edge, settled, fire).await {
+            Either3::First(()) => debounce.edge(Instant::now(), low),
+            Either3::Second(()) => debounce.poll(Instant::now()),
+            Either3::Third(()) => {
+                down = !down;
+                turbo = turbo.map(|at| at + TURBO_INTERVAL);
+                sink.report(Report::Button {
+                    button: reported,
+                    pressed: down,
+                })
+                .await?;
+                continue;
+            }
+        };
+        let Some(pressed) = pressed else {
+            continue;
+        };
+        if pressed {
+            reported = settings.mapping.get(button.id);
+            let fires = settings.turbo.contains(reported) && !PROFILE_CHORD.contains(&reported);
+            turbo = fires.then(|| Instant::now() + TURBO_INTERVAL);
+        } else {
+            turbo = None;
+        }
+        if pressed == down {
+            // released while turbo had it up already
+            continue;
+        }
+        down = pressed;
+        sink.report(Report::Button {
+            button: reported,
+            pressed,
+        })
+        .await?;
+        if pressed {
+            display
+                .display(
+                    DisplayFrame::Letter(reported.name()),
+                    Duration::from_millis(200),
+                )
+                .await;
+        }
+    }
+}
+
+/// Report every button, until one of the reports fails
+pub async fn buttons_task<I: DigitalInput, S: ReportSink>(
+    buttons: &mut GamepadInputs<I>,
+    display: &impl Display5x5,
+    sink: &S,
+    settings: &Cell<Settings>,
+) -> Result<(), S::Error> {
+    let futures = buttons
+        .buttons
+        .each_mut()
+        .map(|button| notify_button_state(button, display, sink, settings));
+    select_array(futures).await.0
+}
+
+/// One analog stick axis, quantised so that only real movement is reported
+pub struct Axis {
+    offset: i16,
+    divider: i16,
+    deadzone: u16,
+    curve: Curve,
+    old: i8,
+}
+
+impl Axis {
+    /// Levels `divider` apart either side of `offset`, with no dead zone
+    pub fn new(offset: i16, divider: i16) -> Self {
+        Self {
+            offset,
+            divider,
+            deadzone: 0,
+            curve: Curve::Linear,
+            old: 0,
+        }
+    }
+
+    /// Read anything within `deadzone` of the centre as centred, and spread the levels over the
+    /// rest of the travel along `curve`
+    pub fn set_response(&mut self, deadzone: u16, curve: Curve) {
+        self.deadzone = deadzone;
+        self.curve = curve;
+    }
+
+    /// The new level if the raw reading has moved to a different one
+    pub fn changed(&mut self, new_raw: i16) -> Option<i8> {
+        let new = -self.level(new_raw); // invert the value
+        if new != self.old {
+            self.old = new;
+            Some(new)
+        } else {
+            None
+        }
+    }
+
+    /// The last reported level
+    pub fn value(&self) -> i8 {
+        self.old
+    }
+
+    fn level(&self, raw: i16) -> i8 {
+        let distance = i64::from(raw) - i64::from(self.offset);
+        let range = i64::from(AXIS_MAX) * i64::from(self.divider) - i64::from(self.deadzone);
+        if range <= 0 {
+            return 0;
+        }
+        let travel = (distance.abs() - i64::from(self.deadzone)).clamp(0, range);
+        let power = match self.curve {
+            Curve::Linear => 1,
+            Curve::Quadratic => 2,
+            Curve::Cubic => 3,
+        };
+        let level = i64::from(AXIS_MAX) * travel.pow(power) / range.pow(power);
+        (level * distance.signum()) as i8
+    }
+}
+
+impl Default for Axis {
+    fn default() -> Self {
+        Self::new(STICK_OFFSET, STICK_DIVIDER)
+    }
+}
+
+/// Report the analog stick position whenever it moves to a new level
+pub async fn analog_stick_task<S: ReportSink>(
+    sampler: &mut impl AnalogSampler<2>,
+    display: &impl Display5x5,
+    sink: &S,
+    settings: &Cell<Settings>,
+) -> Result<(), S::Error> {
+    let mut buf = [0i16; 2];
+    sampler.calibrate().await;
+    let mut x_axis = Axis::default();
+    let mut y_axis = Axis::default();
+    loop {
+        let settings = settings.get();
+        x_axis.set_response(settings.deadzone, settings.curve);
+        y_axis.set_response(settings.deadzone, settings.curve);
+        let interval = Duration::from_hz(settings.report_rate_hz.into());
+        sampler.sample(&mut buf).await;
+        if let Some(value) = x_axis.changed(buf[0]) {
+            sink.report(Report::Axis {
+                axis: AxisId::X,
+                value,
+            })
+            .await?;
+        }
+        if let Some(value) = y_axis.changed(buf[1]) {
+            sink.report(Report::Axis {
+                axis: AxisId::Y,
+                value,
+            })
+            .await?;
+        }
+        let (x, y) = (x_axis.value(), y_axis.value());
+        if !(x == 0 && y == 0) {
+            // only display if the stick is not centered
+            display
+                .display(DisplayFrame::Coord { x, y }, interval)
+                .await;
+        }
+        Timer::after(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debounce_reports_first_edge_then_settled_level() {
+        let ms = Instant::from_millis;
+        let mut debounce = Debounce::new();
+        assert_eq!(debounce.edge(ms(100), true), Some(true));
+        // bouncing while it settles
+        assert_eq!(debounce.edge(ms(102), false), None);
+        assert_eq!(debounce.edge(ms(104), true), None);
+        assert_eq!(debounce.pending(), None);
+        // released before it settled, reported once it has
+        assert_eq!(debounce.edge(ms(120), false), None);
+        assert_eq!(debounce.pending(), Some(ms(150)));
+        assert_eq!(debounce.poll(ms(149)), None);
+        assert_eq!(debounce.poll(ms(150)), Some(false));
+        assert_eq!(debounce.pending(), None);
+        assert_eq!(debounce.edge(ms(300), true), Some(true));
+    }
+
+    #[test]
+    fn axis_quantises_and_inverts() {
+        let mut axis = Axis::default();
+        assert_eq!(axis.changed(STICK_OFFSET), None);
+        assert_eq!(axis.changed(STICK_OFFSET + 100), None);
+        assert_eq!(axis.changed(3740), Some(-3));
+        assert_eq!(axis.changed(3500), Some(-2));
+        assert_eq!(axis.changed(0), Some(3));
+        assert_eq!(axis.value(), 3);
+    }
+
+    #[test]
+    fn axis_response_follows_deadzone_and_curve() {
+        let mut axis = Axis::default();
+        axis.set_response(300, Curve::Linear);
+        assert_eq!(axis.changed(STICK_OFFSET - 299), None);
+        // the levels are spread over what's left beyond the dead zone
+        assert_eq!(axis.changed(STICK_OFFSET - 300 - 530), Some(1));
+        axis.set_response(300, Curve::Quadratic);
+        assert_eq!(axis.changed(STICK_OFFSET - 300 - 530), Some(0));
+        assert_eq!(axis.changed(STICK_OFFSET - 300 - 1300), Some(2));
+        axis.set_response(300, Curve::Cubic);
+        assert_eq!(axis.changed(STICK_OFFSET - 300 - 1300), Some(1));
+        assert_eq!(axis.changed(0), Some(3));
+    }
+}
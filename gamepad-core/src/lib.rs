@@ -0,0 +1,24 @@
+//! Hardware independent gamepad logic, shared by the firmware and host-side tests.
+//!
+//! The logic talks to the board through the traits in [`hal`]. With the `std` feature,
+//! [`mock`] implements them so the whole pipeline runs on a host.
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+pub mod advertising;
+pub mod audio;
+pub mod centrals;
+pub mod config;
+pub mod controller;
+pub mod display;
+pub mod error;
+pub mod fusion;
+pub mod gesture;
+pub mod hal;
+pub mod hid;
+pub mod input;
+#[cfg(feature = "std")]
+pub mod mock;
+pub mod power;
+pub mod profile;
+pub mod recording;
+pub mod scheduler;
@@ -0,0 +1,341 @@
+//! Stand-ins for the hardware, for running the gamepad logic on a host.
+//!
+//! Each mock is a cheap handle onto shared state, so a test keeps a clone to drive inputs and
+//! inspect outputs while the logic under test owns the other.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    vec::Vec,
+};
+
+use embassy_time::{Duration, Timer};
+use gamepad_protocol::{hid::HidReport, ConnectionParams};
+
+use crate::{
+    audio::AudioAction,
+    display::DisplayAction,
+    hal::{
+        AnalogSampler, DigitalInput, Display5x5, Flash, HidOutput, Link, ReportSink, ToneOutput,
+    },
+    input::Report,
+};
+
+/// How often a waiting mock input checks its level
+const POLL: Duration = Duration::from_millis(1);
+
+/// A button, released until [`MockInput::press`]ed
+#[derive(Clone, Default)]
+pub struct MockInput {
+    low: Arc<AtomicBool>,
+}
+
+impl MockInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn press(&self) {
+        self.low.store(true, Ordering::SeqCst);
+    }
+
+    pub fn release(&self) {
+        self.low.store(false, Ordering::SeqCst);
+    }
+}
+
+impl DigitalInput for MockInput {
+    async fn wait_for_low(&mut self) {
+        while !self.is_low() {
+            Timer::after(POLL).await;
+        }
+    }
+
+    async fn wait_for_high(&mut self) {
+        while self.is_low() {
+            Timer::after(POLL).await;
+        }
+    }
+
+    fn is_low(&mut self) -> bool {
+        self.low.load(Ordering::SeqCst)
+    }
+}
+
+/// Analog channels that read whatever was last [`MockSampler::set`]
+#[derive(Clone)]
+pub struct MockSampler<const N: usize> {
+    values: Arc<Mutex<[i16; N]>>,
+}
+
+impl<const N: usize> MockSampler<N> {
+    pub fn new(values: [i16; N]) -> Self {
+        Self {
+            values: Arc::new(Mutex::new(values)),
+        }
+    }
+
+    pub fn set(&self, values: [i16; N]) {
+        *self.values.lock().unwrap() = values;
+    }
+}
+
+impl<const N: usize> AnalogSampler<N> for MockSampler<N> {
+    async fn sample(&mut self, buf: &mut [i16; N]) {
+        *buf = *self.values.lock().unwrap();
+    }
+}
+
+/// Records every action sent to the display
+#[derive(Clone, Default)]
+pub struct MockDisplay {
+    actions: Arc<Mutex<Vec<DisplayAction>>>,
+}
+
+impl MockDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything shown since the last call
+    pub fn take(&self) -> Vec<DisplayAction> {
+        core::mem::take(&mut self.actions.lock().unwrap())
+    }
+}
+
+impl Display5x5 for MockDisplay {
+    async fn apply(&self, action: DisplayAction) {
+        self.actions.lock().unwrap().push(action);
+    }
+}
+
+/// Records every note and tune played
+#[derive(Clone, Default)]
+pub struct MockTone {
+    actions: Arc<Mutex<Vec<AudioAction>>>,
+}
+
+impl MockTone {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything played since the last call
+    pub fn take(&self) -> Vec<AudioAction> {
+        core::mem::take(&mut self.actions.lock().unwrap())
+    }
+}
+
+impl ToneOutput for MockTone {
+    async fn apply(&self, action: AudioAction) {
+        self.actions.lock().unwrap().push(action);
+    }
+}
+
+/// The central went away
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Disconnected;
+
+/// Records reports, or fails them once [`MockSink::disconnect`]ed
+#[derive(Clone, Default)]
+pub struct MockSink {
+    reports: Arc<Mutex<Vec<Report>>>,
+    disconnected: Arc<AtomicBool>,
+}
+
+impl MockSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every report received since the last call
+    pub fn take(&self) -> Vec<Report> {
+        core::mem::take(&mut self.reports.lock().unwrap())
+    }
+
+    pub fn disconnect(&self) {
+        self.disconnected.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ReportSink for MockSink {
+    type Error = Disconnected;
+
+    async fn report(&self, report: Report) -> Result<(), Self::Error> {
+        if self.disconnected.load(Ordering::SeqCst) {
+            return Err(Disconnected);
+        }
+        self.reports.lock().unwrap().push(report);
+        Ok(())
+    }
+}
+
+/// Records HID reports
+#[derive(Clone, Default)]
+pub struct MockHid {
+    reports: Arc<Mutex<Vec<HidReport>>>,
+}
+
+impl MockHid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every report sent since the last call
+    pub fn take(&self) -> Vec<HidReport> {
+        core::mem::take(&mut self.reports.lock().unwrap())
+    }
+}
+
+impl HidOutput for MockHid {
+    type Error = Disconnected;
+
+    async fn send(&self, report: HidReport) -> Result<(), Self::Error> {
+        self.reports.lock().unwrap().push(report);
+        Ok(())
+    }
+}
+
+/// Records every change of connection parameters asked for
+#[derive(Clone, Default)]
+pub struct MockLink {
+    requests: Arc<Mutex<Vec<ConnectionParams>>>,
+}
+
+impl MockLink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every request since the last call
+    pub fn take(&self) -> Vec<ConnectionParams> {
+        core::mem::take(&mut self.requests.lock().unwrap())
+    }
+}
+
+impl Link for MockLink {
+    async fn request_params(&self, params: ConnectionParams) {
+        self.requests.lock().unwrap().push(params);
+    }
+}
+
+/// Power was cut part way through a flash write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerCut;
+
+struct FlashState {
+    data: Vec<u8>,
+    erases: Vec<u32>,
+    /// Writes that complete before the power is cut
+    writes_left: Option<usize>,
+    off: bool,
+}
+
+/// NOR flash held in memory, 4K pages written a word at a time like the nRF52's
+#[derive(Clone)]
+pub struct MockFlash {
+    state: Arc<Mutex<FlashState>>,
+}
+
+impl MockFlash {
+    /// A region of `pages` pages, all erased
+    pub fn new(pages: u32) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FlashState {
+                data: vec![0xff; (pages * Self::PAGE_SIZE) as usize],
+                erases: vec![0; pages as usize],
+                writes_left: None,
+                off: false,
+            })),
+        }
+    }
+
+    /// How many times each page has been erased
+    pub fn erase_counts(&self) -> Vec<u32> {
+        self.state.lock().unwrap().erases.clone()
+    }
+
+    /// Let `writes` more writes through, then cut the power half way through the next one and
+    /// fail everything after it until [`MockFlash::power_on`]
+    pub fn cut_power_after(&self, writes: usize) {
+        self.state.lock().unwrap().writes_left = Some(writes);
+    }
+
+    pub fn power_on(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.writes_left = None;
+        state.off = false;
+    }
+
+    /// Damage the byte at `offset`, as a worn out cell would
+    pub fn corrupt(&self, offset: u32) {
+        self.state.lock().unwrap().data[offset as usize] ^= 0x10;
+    }
+}
+
+impl Flash for MockFlash {
+    type Error = PowerCut;
+
+    const PAGE_SIZE: u32 = 4096;
+    const WRITE_SIZE: u32 = 4;
+
+    fn pages(&self) -> u32 {
+        self.state.lock().unwrap().erases.len() as u32
+    }
+
+    async fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error> {
+        let state = self.state.lock().unwrap();
+        let offset = offset as usize;
+        buf.copy_from_slice(&state.data[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error> {
+        assert!(
+            offset.is_multiple_of(Self::WRITE_SIZE)
+                && (data.len() as u32).is_multiple_of(Self::WRITE_SIZE),
+            "unaligned flash write of {} bytes at {offset:#x}",
+            data.len()
+        );
+        let mut state = self.state.lock().unwrap();
+        if state.off {
+            return Err(PowerCut);
+        }
+        let (data, result) = match &mut state.writes_left {
+            Some(0) => {
+                state.off = true;
+                (&data[..data.len() / 2], Err(PowerCut))
+            }
+            Some(left) => {
+                *left -= 1;
+                (data, Ok(()))
+            }
+            None => (data, Ok(())),
+        };
+        for (cell, &byte) in state.data[offset as usize..].iter_mut().zip(data) {
+            assert_eq!(
+                *cell, 0xff,
+                "flash written at {offset:#x} without erasing it"
+            );
+            *cell = byte;
+        }
+        result
+    }
+
+    async fn erase(&mut self, offset: u32) -> Result<(), Self::Error> {
+        assert!(
+            offset.is_multiple_of(Self::PAGE_SIZE),
+            "erase at {offset:#x} isn't a page"
+        );
+        let mut state = self.state.lock().unwrap();
+        if state.off {
+            return Err(PowerCut);
+        }
+        let page = (offset / Self::PAGE_SIZE) as usize;
+        state.data[offset as usize..][..Self::PAGE_SIZE as usize].fill(0xff);
+        state.erases[page] += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+//! Going to sleep when nobody is using the gamepad.
+//!
+//! While a central is connected, [`idle_watch`] keeps the connection interval short for as
+//! long as the inputs are in use. Once they've been quiet for a while it dims the display and
+//! relaxes the connection, and when they've been quiet for longer it ends, which the
+//! controller takes as [`Event::Idle`](crate::controller::Event::Idle). While disconnected, the advertising
+//! timeouts in [`AdvertisingConfig`](crate::advertising::AdvertisingConfig) do the same job.
+//! Either way the board then turns off until a button wakes it.
+
+use core::cell::Cell;
+
+use embassy_time::{Duration, Instant, Timer};
+use gamepad_protocol::ConnectionParams;
+
+use crate::{
+    config::Settings,
+    display::Brightness,
+    hal::{Display5x5, Link, ReportSink},
+    input::Report,
+};
+
+/// How often a dimmed display checks whether the inputs have woken up
+const DIMMED_POLL: Duration = Duration::from_millis(100);
+/// Longest wait before the idle timeouts in the settings are read again
+const SETTINGS_POLL: Duration = Duration::from_secs(1);
+
+/// How the connection and display are eased off while idle. When that happens is up to the
+/// [`IdleTimeouts`](gamepad_protocol::IdleTimeouts) in the settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerConfig {
+    /// How bright a dimmed display is, if the setting isn't already lower
+    pub dimmed: Brightness,
+    /// Asked for while the inputs are in use
+    pub active: ConnectionParams,
+    /// Asked for once the display dims
+    pub relaxed: ConnectionParams,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            dimmed: Brightness::new(1),
+            // as short as the link layer allows, so inputs go out at the next event
+            active: ConnectionParams::new(7_500, 15_000, 0, 4_000),
+            // the central can still write settings, just less promptly
+            relaxed: ConnectionParams::new(100_000, 150_000, 4, 6_000),
+        }
+    }
+}
+
+/// When the inputs last reported anything
+pub struct Activity {
+    last: Cell<Instant>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Self {
+            last: Cell::new(Instant::now()),
+        }
+    }
+
+    pub fn touch(&self) {
+        self.last.set(Instant::now());
+    }
+
+    pub fn last(&self) -> Instant {
+        self.last.get()
+    }
+}
+
+/// Passes reports on to `sink`, noting each one in `activity`
+pub struct ActivitySink<'a, S> {
+    pub sink: &'a S,
+    pub activity: &'a Activity,
+}
+
+impl<S: ReportSink> ReportSink for ActivitySink<'_, S> {
+    type Error = S::Error;
+
+    async fn report(&self, report: Report) -> Result<(), Self::Error> {
+        self.activity.touch();
+        self.sink.report(report).await
+    }
+}
+
+/// Dim the display and relax the connection after the `dim_after_s` in `settings` without
+/// activity, undoing both if the inputs start up, and return after its `sleep_after_s`. The
+/// timeouts are read again at least every [`SETTINGS_POLL`], so a central can change them
+/// mid-connection. The display may still be dimmed when this returns or is dropped, so the
+/// caller sets the brightness back afterwards.
+pub async fn idle_watch(
+    activity: &Activity,
+    config: &PowerConfig,
+    display: &impl Display5x5,
+    link: &impl Link,
+    settings: &Cell<Settings>,
+) {
+    activity.touch();
+    link.request_params(config.active).await;
+    let mut dimmed = false;
+    loop {
+        let (idle, last) = (settings.get().idle, activity.last());
+        let dim_at = last + Duration::from_secs(idle.dim_after_s.into());
+        let sleep_at = last + Duration::from_secs(idle.sleep_after_s.into());
+        let now = Instant::now();
+        if now >= sleep_at {
+            break;
+        }
+        if now < dim_at {
+            if dimmed {
+                display.set_brightness(settings.get().brightness).await;
+                link.request_params(config.active).await;
+                dimmed = false;
+            }
+            Timer::at(dim_at.min(now + SETTINGS_POLL)).await;
+            continue;
+        }
+        if !dimmed {
+            let brightness = settings.get().brightness.min(config.dimmed);
+            display.set_brightness(brightness).await;
+            link.request_params(config.relaxed).await;
+            dimmed = true;
+        }
+        Timer::after(DIMMED_POLL).await;
+    }
+}
@@ -0,0 +1,314 @@
+//! Named sets of the settings that suit a game or an app, switched between on the board or by a
+//! central.
+//!
+//! The profile in use is part of [`Settings`], each of its settings saved under its own key like
+//! any other, so a central tuning it needs to know nothing about profiles. The others wait in a
+//! slot each, and [`switch`] swaps the one in use into its slot and the chosen one out of its
+//! own. Holding the buttons in [`PROFILE_CHORD`] together moves on to the next profile, which a
+//! [`ChordSink`] watches for.
+
+use core::cell::Cell;
+
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, signal::Signal};
+use gamepad_protocol::{
+    gatt,
+    hid::{HidMode, KeyMap},
+    value::{DecodeError, MAX_SIZE},
+    ButtonId, ButtonMapping, Curve, ProfileName, Setting, Turbo, Value,
+};
+
+use crate::{
+    config::{Key, Settings, Store, StoreError},
+    hal::{Flash, ReportSink},
+    input::Report,
+};
+
+/// How many profiles there are to switch between
+pub const PROFILE_COUNT: u8 = *gatt::config::PROFILE_RANGE.end() + 1;
+/// Buttons that, held together, switch to the next profile. They're the buttons as reported,
+/// after the mapping, and the central sees them pressed as usual.
+pub const PROFILE_CHORD: [ButtonId; 2] = [ButtonId::E, ButtonId::F];
+
+/// The settings that change with the profile
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Profile {
+    pub name: ProfileName,
+    pub mapping: ButtonMapping,
+    pub deadzone: u16,
+    pub curve: Curve,
+    pub turbo: Turbo,
+    pub hid_mode: HidMode,
+    pub key_map: KeyMap,
+}
+
+impl Profile {
+    /// Profile `index` before anything has been changed in it
+    pub fn numbered(index: u8) -> Self {
+        Self {
+            name: ProfileName::numbered(index),
+            ..Self::of(&Settings::default())
+        }
+    }
+
+    /// The profile in use in `settings`
+    pub fn of(settings: &Settings) -> Self {
+        Self {
+            name: settings.profile_name,
+            mapping: settings.mapping,
+            deadzone: settings.deadzone,
+            curve: settings.curve,
+            turbo: settings.turbo,
+            hid_mode: settings.hid_mode,
+            key_map: settings.key_map,
+        }
+    }
+
+    /// Each setting the profile holds, to apply and save when taking it up
+    pub fn settings(&self) -> [Setting; 7] {
+        [
+            Setting::ProfileName(self.name),
+            Setting::Mapping(self.mapping),
+            Setting::Deadzone(self.deadzone),
+            Setting::Curve(self.curve),
+            Setting::Turbo(self.turbo),
+            Setting::HidMode(self.hid_mode),
+            Setting::KeyMap(self.key_map),
+        ]
+    }
+}
+
+impl Value for Profile {
+    /// Each setting as it's written to its characteristic, in the order of the fields
+    const SIZE: usize = ProfileName::SIZE
+        + ButtonMapping::SIZE
+        + u16::SIZE
+        + Curve::SIZE
+        + Turbo::SIZE
+        + HidMode::SIZE
+        + KeyMap::SIZE;
+
+    fn encode(&self, out: &mut [u8]) {
+        let mut at = 0;
+        for setting in self.settings() {
+            let mut bytes = [0; MAX_SIZE];
+            let len = setting.encode(&mut bytes);
+            out[at..at + len].copy_from_slice(&bytes[..len]);
+            at += len;
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() != Self::SIZE {
+            return Err(DecodeError::Length {
+                expected: Self::SIZE,
+                found: bytes.len(),
+            });
+        }
+        let mut profile = Self::numbered(0);
+        let mut at = 0;
+        // each setting is as long as any other value of it
+        for setting in profile.settings() {
+            let len = setting.encode(&mut [0; MAX_SIZE]);
+            match Setting::decode(setting.characteristic(), &bytes[at..at + len])? {
+                Setting::ProfileName(name) => profile.name = name,
+                Setting::Mapping(mapping) => profile.mapping = mapping,
+                Setting::Deadzone(deadzone) => profile.deadzone = deadzone,
+                Setting::Curve(curve) => profile.curve = curve,
+                Setting::Turbo(turbo) => profile.turbo = turbo,
+                Setting::HidMode(mode) => profile.hid_mode = mode,
+                Setting::KeyMap(map) => profile.key_map = map,
+                _ => return Err(DecodeError::UnknownCharacteristic),
+            }
+            at += len;
+        }
+        Ok(profile)
+    }
+}
+
+/// The profile after `index`, back to the first after the last
+pub fn next(index: u8) -> u8 {
+    (index + 1) % PROFILE_COUNT
+}
+
+/// Put the profile in use in `settings` in its slot and take up profile `to` from `saved`, or
+/// as it starts out if there's nothing saved for it. Returns the profile that was in use.
+pub fn swap(settings: &mut Settings, to: u8, saved: Option<Profile>) -> Profile {
+    let left = Profile::of(settings);
+    for setting in saved.unwrap_or(Profile::numbered(to)).settings() {
+        settings.apply(setting);
+    }
+    settings.profile = to;
+    left
+}
+
+/// Switch `settings` to profile `to`. The profile being left goes in its slot and the one taken
+/// up in its own before the index is saved, which commits the switch: a power cut before then
+/// leaves the profile being left in use, and one after it is [`resume`]d on the next load.
+pub async fn switch<F: Flash>(
+    store: &mut Store<F>,
+    settings: &mut Settings,
+    to: u8,
+) -> Result<(), StoreError<F::Error>> {
+    if to == settings.profile {
+        return Ok(());
+    }
+    let saved = store.load(Key::profile_slot(to)).await?;
+    let from = settings.profile;
+    let left = swap(settings, to, saved);
+    store.save(Key::profile_slot(from), &left).await?;
+    if saved.is_none() {
+        store
+            .save(Key::profile_slot(to), &Profile::of(settings))
+            .await?;
+    }
+    Settings::save(store, &Setting::Profile(to)).await?;
+    resume(store, settings).await
+}
+
+/// Finish taking up the profile in use in `settings` if a [`switch`] was cut short, which left it
+/// in its slot: apply it, save each of its settings under its own key and empty the slot
+pub async fn resume<F: Flash>(
+    store: &mut Store<F>,
+    settings: &mut Settings,
+) -> Result<(), StoreError<F::Error>> {
+    let slot = Key::profile_slot(settings.profile);
+    let Some(profile) = store.load::<Profile>(slot).await? else {
+        return Ok(());
+    };
+    for setting in profile.settings() {
+        settings.apply(setting);
+        Settings::save(store, &setting).await?;
+    }
+    store.remove(slot).await
+}
+
+/// Which buttons are held, to tell when the [`PROFILE_CHORD`] is
+pub struct Chord {
+    held: Cell<u8>,
+    formed: Signal<NoopRawMutex, ()>,
+}
+
+impl Default for Chord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chord {
+    pub const fn new() -> Self {
+        Self {
+            held: Cell::new(0),
+            formed: Signal::new(),
+        }
+    }
+
+    /// Wait until every button in the chord is held, once for each time they come together
+    pub async fn wait(&self) {
+        self.formed.wait().await;
+    }
+
+    fn update(&self, button: ButtonId, pressed: bool) {
+        let chord = PROFILE_CHORD
+            .iter()
+            .fold(0, |bits, &button| bits | 1 << button as u8);
+        let was = self.held.get();
+        let held = match pressed {
+            true => was | 1 << button as u8,
+            false => was & !(1 << button as u8),
+        };
+        self.held.set(held);
+        if held & chord == chord && was & chord != chord {
+            self.formed.signal(());
+        }
+    }
+}
+
+/// Passes reports on to `sink`, noting in `chord` which buttons are held
+pub struct ChordSink<'a, S> {
+    pub sink: &'a S,
+    pub chord: &'a Chord,
+}
+
+impl<S: ReportSink> ReportSink for ChordSink<'_, S> {
+    type Error = S::Error;
+
+    async fn report(&self, report: Report) -> Result<(), Self::Error> {
+        if let Report::Button { button, pressed } = report {
+            self.chord.update(button, pressed);
+        }
+        self.sink.report(report).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::{block_on, poll_once};
+
+    use super::*;
+
+    struct Nowhere;
+
+    impl ReportSink for Nowhere {
+        type Error = ();
+
+        async fn report(&self, _: Report) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn profiles_round_trip() {
+        let settings = Settings {
+            deadzone: 200,
+            turbo: Turbo(0b11),
+            hid_mode: HidMode::MediaRemote,
+            profile_name: ProfileName::new("RACING").unwrap(),
+            ..Settings::default()
+        };
+        let profile = Profile::of(&settings);
+        let mut bytes = [0; Profile::SIZE];
+        profile.encode(&mut bytes);
+        assert_eq!(Profile::decode(&bytes), Ok(profile));
+    }
+
+    #[test]
+    fn swapping_keeps_what_isnt_in_a_profile() {
+        let mut settings = Settings {
+            volume: crate::audio::Volume::new(3),
+            curve: Curve::Cubic,
+            ..Settings::default()
+        };
+        let left = swap(&mut settings, 2, None);
+        assert_eq!(left.curve, Curve::Cubic);
+        assert_eq!(settings.curve, Curve::Linear);
+        assert_eq!(settings.profile_name.as_str(), "P3");
+        assert_eq!(settings.profile, 2);
+        assert_eq!(settings.volume.level(), 3);
+        swap(&mut settings, 0, Some(left));
+        assert_eq!(settings.curve, Curve::Cubic);
+        assert_eq!(next(3), 0);
+    }
+
+    #[test]
+    fn the_chord_forms_once_until_broken() {
+        let chord = Chord::new();
+        let sink = ChordSink {
+            sink: &Nowhere,
+            chord: &chord,
+        };
+        let press = |button, pressed| {
+            let _ = block_on(sink.report(Report::Button { button, pressed }));
+        };
+        press(ButtonId::E, true);
+        press(ButtonId::A, true);
+        assert!(poll_once(chord.wait()).is_pending());
+        press(ButtonId::F, true);
+        assert!(poll_once(chord.wait()).is_ready());
+        press(ButtonId::A, false);
+        assert!(poll_once(chord.wait()).is_pending());
+        press(ButtonId::F, false);
+        press(ButtonId::F, true);
+        assert!(poll_once(chord.wait()).is_ready());
+    }
+}
@@ -0,0 +1,263 @@
+//! Recording raw inputs, and replaying a recording through the same filters the firmware uses.
+//!
+//! The firmware keeps the most recent samples in a ring buffer that a central can download. On
+//! a host, [`replay`] turns them back into what the central was sent, so a report like "the
+//! stick twitched" can be reproduced and stepped through.
+
+use embassy_time::Instant;
+use heapless::HistoryBuffer;
+
+use gamepad_protocol::{Notification, RawInput, Sample};
+
+use crate::{
+    gesture::GestureDetector,
+    input::{Axis, AxisId, ButtonId, Debounce, Report},
+};
+
+/// The latest `N` raw inputs, while recording is on
+pub struct Recorder<const N: usize> {
+    samples: HistoryBuffer<Sample, N>,
+    started: Option<Instant>,
+}
+
+impl<const N: usize> Recorder<N> {
+    pub const fn new() -> Self {
+        Self {
+            samples: HistoryBuffer::new(),
+            started: None,
+        }
+    }
+
+    /// Throw away what was recorded and start again, with times counted from `now`
+    pub fn start(&mut self, now: Instant) {
+        self.samples.clear();
+        self.started = Some(now);
+    }
+
+    /// Stop recording, keeping the samples
+    pub fn stop(&mut self) {
+        self.started = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Keep `input` if recording, overwriting the oldest sample once full
+    pub fn record(&mut self, now: Instant, input: RawInput) {
+        if let Some(started) = self.started {
+            let at_ms = now.saturating_duration_since(started).as_millis();
+            self.samples.write(Sample {
+                at_ms: at_ms.try_into().unwrap_or(u32::MAX),
+                input,
+            });
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The samples held, oldest first
+    pub fn samples(&self) -> impl Iterator<Item = &Sample> {
+        self.samples.oldest_ordered()
+    }
+
+    /// Up to `M` of the samples held, starting `from` places after the oldest. Skipping ahead
+    /// doesn't walk the samples before `from`, so copying them all out a chunk at a time takes
+    /// one pass.
+    pub fn chunk<const M: usize>(&self, from: usize) -> heapless::Vec<Sample, M> {
+        let (older, newer) = self.samples.as_slices();
+        older
+            .iter()
+            .chain(newer)
+            .skip(from)
+            .take(M)
+            .copied()
+            .collect()
+    }
+}
+
+impl<const N: usize> Default for Recorder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Feed `samples`, oldest first, through button debouncing, stick quantisation and gesture
+/// detection, passing `out` each notification the firmware would have sent and when.
+///
+/// Each filter starts from rest, as if recording started with the buttons up and the stick
+/// centred.
+pub fn replay(samples: impl IntoIterator<Item = Sample>, mut out: impl FnMut(u32, Notification)) {
+    let mut buttons = [Debounce::new(); 6];
+    let mut axes = [Axis::default(), Axis::default()];
+    let mut gestures = GestureDetector::new();
+    let mut send = |at: Instant, notification| out(at.as_millis() as u32, notification);
+    for sample in samples {
+        let now = Instant::from_millis(sample.at_ms.into());
+        settle(&mut buttons, Some(now), &mut send);
+        match sample.input {
+            RawInput::Edge { button, low } => {
+                if let Some(pressed) = buttons[button as usize].edge(now, low) {
+                    send(now, Report::Button { button, pressed }.into());
+                }
+            }
+            RawInput::Stick(raw) => {
+                for ((axis, id), raw) in axes.iter_mut().zip([AxisId::X, AxisId::Y]).zip(raw) {
+                    if let Some(value) = axis.changed(raw) {
+                        send(now, Report::Axis { axis: id, value }.into());
+                    }
+                }
+            }
+            RawInput::Accel(accel) => {
+                if let Some(gesture) = gestures.update(accel.map(i32::from)) {
+                    send(now, Notification::Gesture(gesture));
+                }
+            }
+        }
+    }
+    settle(&mut buttons, None, &mut send);
+}
+
+/// Report the button changes that settle before `until`, or all of them, in time order. An edge
+/// at the very moment a change settles is seen first, as in the button task.
+fn settle(
+    buttons: &mut [Debounce; 6],
+    until: Option<Instant>,
+    send: &mut impl FnMut(Instant, Notification),
+) {
+    loop {
+        let due = ButtonId::ALL
+            .into_iter()
+            .zip(buttons.iter())
+            .filter_map(|(button, debounce)| Some((debounce.pending()?, button)))
+            .filter(|(at, _)| until.is_none_or(|until| *at < until))
+            .min_by_key(|(at, _)| *at);
+        let Some((at, button)) = due else {
+            return;
+        };
+        if let Some(pressed) = buttons[button as usize].poll(at) {
+            send(at, Report::Button { button, pressed }.into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gamepad_protocol::Gesture;
+
+    use super::*;
+    use crate::input::{STICK_DIVIDER, STICK_OFFSET};
+
+    fn sample(at_ms: u32, input: RawInput) -> Sample {
+        Sample { at_ms, input }
+    }
+
+    fn edge(at_ms: u32, low: bool) -> Sample {
+        sample(
+            at_ms,
+            RawInput::Edge {
+                button: ButtonId::C,
+                low,
+            },
+        )
+    }
+
+    fn replayed(samples: Vec<Sample>) -> Vec<(u32, Notification)> {
+        let mut out = Vec::new();
+        replay(samples, |at, notification| out.push((at, notification)));
+        out
+    }
+
+    fn button(pressed: bool) -> Notification {
+        Notification::Button {
+            button: ButtonId::C,
+            pressed,
+        }
+    }
+
+    #[test]
+    fn recorder_keeps_the_latest_while_recording() {
+        let mut recorder = Recorder::<3>::new();
+        let input = RawInput::Stick([0, 0]);
+        recorder.record(Instant::from_millis(5), input);
+        assert!(recorder.is_empty());
+        recorder.start(Instant::from_millis(1000));
+        for ms in [1000, 1010, 1020, 1030] {
+            recorder.record(Instant::from_millis(ms), input);
+        }
+        recorder.stop();
+        recorder.record(Instant::from_millis(1040), input);
+        let times: Vec<u32> = recorder.samples().map(|s| s.at_ms).collect();
+        assert_eq!(times, [10, 20, 30]);
+        recorder.start(Instant::from_millis(2000));
+        assert_eq!(recorder.len(), 0);
+    }
+
+    #[test]
+    fn chunks_follow_the_samples_round_the_ring() {
+        let mut recorder = Recorder::<5>::new();
+        recorder.start(Instant::from_millis(0));
+        for ms in 0..7 {
+            recorder.record(Instant::from_millis(ms), RawInput::Stick([0, 0]));
+        }
+        let times = |chunk: heapless::Vec<Sample, 2>| chunk.iter().map(|s| s.at_ms).collect();
+        let chunks: Vec<Vec<u32>> = (0..4).map(|i| times(recorder.chunk(i * 2))).collect();
+        assert_eq!(chunks, [vec![2, 3], vec![4, 5], vec![6], vec![]]);
+    }
+
+    #[test]
+    fn replay_debounces_edges() {
+        let samples = vec![
+            edge(100, true),
+            edge(101, false),
+            edge(103, true),
+            // released during the settling time, reported once it has settled
+            edge(130, false),
+            edge(400, true),
+        ];
+        assert_eq!(
+            replayed(samples),
+            [
+                (100, button(true)),
+                (150, button(false)),
+                (400, button(true))
+            ]
+        );
+    }
+
+    #[test]
+    fn replay_quantises_the_stick() {
+        let level = |level: i16| STICK_OFFSET - level * STICK_DIVIDER;
+        let samples = vec![
+            sample(0, RawInput::Stick([STICK_OFFSET, STICK_OFFSET])),
+            sample(20, RawInput::Stick([STICK_OFFSET + 40, STICK_OFFSET - 40])),
+            // a twitch over the boundary, and back
+            sample(40, RawInput::Stick([level(1) - 10, STICK_OFFSET])),
+            sample(60, RawInput::Stick([level(1) + 10, STICK_OFFSET])),
+        ];
+        let axis = |value| Notification::Axis {
+            axis: AxisId::X,
+            value,
+        };
+        assert_eq!(replayed(samples), [(40, axis(1)), (60, axis(0))]);
+    }
+
+    #[test]
+    fn replay_detects_gestures() {
+        let samples = (0..100)
+            .map(|i| {
+                let swing = if i % 2 == 0 { 3000 } else { 0 };
+                sample(i * 20, RawInput::Accel([swing, 0, -1000]))
+            })
+            .collect();
+        assert!(replayed(samples)
+            .iter()
+            .any(|(_, n)| *n == Notification::Gesture(Gesture::Shake)));
+    }
+}
@@ -0,0 +1,135 @@
+//! Batching input reports to the pace of the connection.
+//!
+//! The input tasks report each change as it happens, but the central only hears from the
+//! gamepad once per connection event. [`ReportScheduler`] collects changes in between and
+//! sends them together at most once per period, the longer of the connection interval and
+//! `1 / max_rate`. Stick levels are coalesced to the latest one; button edges are all kept, in
+//! order, so a tap shorter than a period still arrives as a press and a release.
+
+use core::{cell::RefCell, convert::Infallible};
+
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::{Deque, Vec};
+
+use crate::{
+    hal::ReportSink,
+    input::{AxisId, ButtonId, Report},
+};
+
+/// Button edges held between batches before the buttons have to wait for one to go out
+pub const MAX_EDGES: usize = 16;
+
+/// Most reports in one batch, every held edge and both axes
+const MAX_BATCH: usize = MAX_EDGES + 2;
+
+#[derive(Default)]
+struct Pending {
+    edges: Deque<(ButtonId, bool), MAX_EDGES>,
+    axes: [Option<i8>; 2],
+    /// The last level sent for each axis, so a stick that comes back to it sends nothing
+    sent: [Option<i8>; 2],
+    connection_interval: Duration,
+}
+
+/// Collects reports from the input tasks and passes them on in batches from [`Self::run`]
+pub struct ReportScheduler {
+    min_period: Duration,
+    pending: RefCell<Pending>,
+    changed: Signal<NoopRawMutex, ()>,
+    drained: Signal<NoopRawMutex, ()>,
+}
+
+impl ReportScheduler {
+    /// Send at most `max_rate_hz` batches a second, however short the connection interval
+    pub fn new(max_rate_hz: u32) -> Self {
+        Self {
+            min_period: Duration::from_hz(max_rate_hz.into()),
+            pending: RefCell::default(),
+            changed: Signal::new(),
+            drained: Signal::new(),
+        }
+    }
+
+    /// Pace batches to the connection interval the central settled on, so two batches never
+    /// wait for the same connection event
+    pub fn follow(&self, interval: Duration) {
+        self.pending.borrow_mut().connection_interval = interval;
+    }
+
+    /// How long to leave between batches
+    pub fn period(&self) -> Duration {
+        self.min_period
+            .max(self.pending.borrow().connection_interval)
+    }
+
+    /// Send batches to `sink` for as long as it takes them
+    pub async fn run<S: ReportSink>(&self, sink: &S) -> Result<Infallible, S::Error> {
+        let mut last: Option<Instant> = None;
+        loop {
+            self.changed.wait().await;
+            if let Some(last) = last {
+                Timer::at(last + self.period()).await;
+            }
+            let batch = self.take();
+            if batch.is_empty() {
+                continue;
+            }
+            last = Some(Instant::now());
+            self.drained.signal(());
+            for report in batch {
+                sink.report(report).await?;
+            }
+        }
+    }
+
+    /// Everything pending, edges first so a press isn't reported after the stick it started
+    fn take(&self) -> Vec<Report, MAX_BATCH> {
+        let mut pending = self.pending.borrow_mut();
+        let mut batch = Vec::new();
+        while let Some((button, pressed)) = pending.edges.pop_front() {
+            // room for every edge was made when the batch was sized
+            let _ = batch.push(Report::Button { button, pressed });
+        }
+        for (i, axis) in [AxisId::X, AxisId::Y].into_iter().enumerate() {
+            let Some(value) = pending.axes[i].take() else {
+                continue;
+            };
+            if pending.sent[i] != Some(value) {
+                pending.sent[i] = Some(value);
+                let _ = batch.push(Report::Axis { axis, value });
+            }
+        }
+        batch
+    }
+}
+
+/// The input tasks report here. Reports can't fail to be queued; delivery failures come out
+/// of [`ReportScheduler::run`].
+impl ReportSink for ReportScheduler {
+    type Error = Infallible;
+
+    async fn report(&self, report: Report) -> Result<(), Self::Error> {
+        match report {
+            Report::Button { button, pressed } => loop {
+                if self
+                    .pending
+                    .borrow_mut()
+                    .edges
+                    .push_back((button, pressed))
+                    .is_ok()
+                {
+                    break;
+                }
+                // never drop an edge, wait for the next batch to make room
+                self.changed.signal(());
+                self.drained.wait().await;
+            },
+            Report::Axis { axis, value } => {
+                self.pending.borrow_mut().axes[axis as usize] = Some(value);
+            }
+        }
+        self.changed.signal(());
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+//! The config store against an in-memory flash, including power cuts part way through writes.
+
+use embassy_futures::block_on;
+use gamepad_core::{
+    config::{migration, Entry, Key, Settings, Store, StoreError, SCHEMA_VERSION},
+    mock::{MockFlash, PowerCut},
+    profile::{self, Profile},
+};
+use gamepad_protocol::{hid::HidMode, Curve, ProfileName, Setting, Turbo};
+
+const PAGES: u32 = 4;
+
+fn mount(flash: &MockFlash) -> Store<MockFlash> {
+    block_on(Store::mount(flash.clone(), SCHEMA_VERSION, migration())).unwrap()
+}
+
+fn player(store: &mut Store<MockFlash>) -> Option<u8> {
+    block_on(store.load(Key::PlayerIndex)).unwrap()
+}
+
+fn set_player(store: &mut Store<MockFlash>, index: u8) -> Result<(), StoreError<PowerCut>> {
+    block_on(store.save(Key::PlayerIndex, &index))
+}
+
+#[test]
+fn values_survive_a_remount() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    assert_eq!(player(&mut store), None);
+    set_player(&mut store, 3).unwrap();
+    block_on(store.set(Key::CompassCalibration, &[1, 2, 3, 4, 5])).unwrap();
+    set_player(&mut store, 2).unwrap();
+
+    let mut store = mount(&flash);
+    assert_eq!(player(&mut store), Some(2));
+    assert_eq!(
+        block_on(store.get(Key::CompassCalibration))
+            .unwrap()
+            .as_deref(),
+        Some(&[1, 2, 3, 4, 5][..])
+    );
+    block_on(store.remove(Key::CompassCalibration)).unwrap();
+    let mut store = mount(&flash);
+    assert_eq!(block_on(store.get(Key::CompassCalibration)).unwrap(), None);
+    assert_eq!(player(&mut store), Some(2));
+}
+
+#[test]
+fn values_too_long_are_refused() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    assert_eq!(
+        block_on(store.set(Key::PlayerIndex, &[0; 65])),
+        Err(StoreError::TooLong)
+    );
+}
+
+#[test]
+fn torn_write_keeps_the_previous_value() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    set_player(&mut store, 1).unwrap();
+    flash.cut_power_after(0);
+    assert_eq!(set_player(&mut store, 2), Err(StoreError::Flash(PowerCut)));
+
+    flash.power_on();
+    let mut store = mount(&flash);
+    assert_eq!(player(&mut store), Some(1));
+    // the half written record is compacted away before anything else is written
+    set_player(&mut store, 3).unwrap();
+    let mut store = mount(&flash);
+    assert_eq!(player(&mut store), Some(3));
+}
+
+#[test]
+fn power_cut_at_any_write_loses_at_most_that_write() {
+    for cut in 0..250 {
+        let flash = MockFlash::new(PAGES);
+        let mut store = mount(&flash);
+        flash.cut_power_after(cut);
+        let mut last = None;
+        for index in 0..200u8 {
+            if set_player(&mut store, index).is_err() {
+                break;
+            }
+            last = Some(index);
+        }
+        flash.power_on();
+        let mut store = mount(&flash);
+        let found = player(&mut store);
+        // the write that was cut either made it or didn't
+        let next = last.map_or(0, |last| last + 1);
+        assert!(
+            found == last || found == Some(next),
+            "cut after {cut} writes: found {found:?}, last complete {last:?}"
+        );
+        set_player(&mut store, 7).unwrap();
+        assert_eq!(player(&mut mount(&flash)), Some(7));
+    }
+}
+
+#[test]
+fn damaged_record_is_dropped() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    set_player(&mut store, 4).unwrap();
+    // the value byte of the first record, just after the page and record headers
+    flash.corrupt(12 + 4);
+    let mut store = mount(&flash);
+    assert_eq!(player(&mut store), None);
+    set_player(&mut store, 5).unwrap();
+    assert_eq!(player(&mut mount(&flash)), Some(5));
+}
+
+#[test]
+fn erases_are_spread_over_every_page() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    for i in 0..5000u32 {
+        set_player(&mut store, i as u8).unwrap();
+        block_on(store.set(Key::CompassCalibration, &i.to_le_bytes())).unwrap();
+    }
+    let erases = flash.erase_counts();
+    let (min, max) = (erases.iter().min().unwrap(), erases.iter().max().unwrap());
+    assert!(*min >= 5, "every page is used: {erases:?}");
+    assert!(max - min <= 1, "wear is even: {erases:?}");
+    assert_eq!(player(&mut mount(&flash)), Some((4999 % 256) as u8));
+}
+
+#[test]
+fn older_schema_is_migrated_once() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = block_on(Store::mount(flash.clone(), 1, |_, _| true)).unwrap();
+    set_player(&mut store, 1).unwrap();
+    block_on(store.set(Key::CompassCalibration, &[9; 24])).unwrap();
+
+    // version 2 counts players from 1, and has a new calibration format
+    let mut migrated = Vec::new();
+    let upgrade = |from: u16, entry: &mut Entry| {
+        migrated.push((from, entry.key));
+        if entry.key == Key::PlayerIndex as u8 {
+            entry.value[0] += 1;
+            true
+        } else {
+            false
+        }
+    };
+    let mut store = block_on(Store::mount(flash.clone(), 2, upgrade)).unwrap();
+    assert_eq!(
+        migrated,
+        [
+            (1, Key::PlayerIndex as u8),
+            (1, Key::CompassCalibration as u8)
+        ]
+    );
+    assert_eq!(player(&mut store), Some(2));
+    assert_eq!(block_on(store.get(Key::CompassCalibration)).unwrap(), None);
+
+    let mut store = block_on(Store::mount(flash.clone(), 2, |_, _| {
+        panic!("already migrated")
+    }))
+    .unwrap();
+    assert_eq!(player(&mut store), Some(2));
+}
+
+#[test]
+fn firmware_drops_values_from_older_schemas() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = block_on(Store::mount(flash.clone(), 0, |_, _| true)).unwrap();
+    set_player(&mut store, 1).unwrap();
+    let mut store = mount(&flash);
+    assert_eq!(player(&mut store), None);
+}
+
+#[test]
+fn newer_schema_is_discarded() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = block_on(Store::mount(flash.clone(), 3, |_, _| true)).unwrap();
+    set_player(&mut store, 1).unwrap();
+    let mut store = mount(&flash);
+    assert_eq!(player(&mut store), None);
+}
+
+#[test]
+fn schema_1_drops_the_slot_of_the_profile_in_use() {
+    let old = |slots: &[u8], in_use: Option<u8>| {
+        let flash = MockFlash::new(PAGES);
+        let mut store = block_on(Store::mount(flash.clone(), 1, |_, _| true)).unwrap();
+        for &slot in slots {
+            block_on(store.save(Key::profile_slot(slot), &Profile::numbered(slot))).unwrap();
+        }
+        if let Some(index) = in_use {
+            block_on(Settings::save(&mut store, &Setting::Profile(index))).unwrap();
+        }
+        let mut store = mount(&flash);
+        let kept: Vec<_> = (0..profile::PROFILE_COUNT)
+            .filter(|&slot| {
+                let saved: Option<Profile> = block_on(store.load(Key::profile_slot(slot))).unwrap();
+                saved.is_some()
+            })
+            .collect();
+        let settings = block_on(Settings::load(&mut store)).unwrap();
+        (kept, settings.profile)
+    };
+    // switched from 0 to 2 and back to 1, leaving the copy of 1 taken up behind
+    assert_eq!(old(&[0, 1, 2], Some(1)), (vec![0, 2], 1));
+    // never switched, so profile 0 is in use without a key of its own
+    assert_eq!(old(&[0, 3], None), (vec![3], 0));
+}
+
+#[test]
+fn factory_reset_forgets_everything() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    set_player(&mut store, 1).unwrap();
+    block_on(store.factory_reset()).unwrap();
+    assert_eq!(player(&mut store), None);
+    assert_eq!(player(&mut mount(&flash)), None);
+    set_player(&mut store, 6).unwrap();
+    assert_eq!(player(&mut mount(&flash)), Some(6));
+}
+
+#[test]
+fn settings_are_saved_one_at_a_time() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    assert_eq!(
+        block_on(Settings::load(&mut store)),
+        Ok(Settings::default())
+    );
+    block_on(Settings::save(
+        &mut store,
+        &Setting::Curve(Curve::Quadratic),
+    ))
+    .unwrap();
+    block_on(Settings::save(&mut store, &Setting::Deadzone(200))).unwrap();
+    // not something a central could have written, so the default stands
+    block_on(store.set(Key::ReportRate, &[0])).unwrap();
+
+    let mut store = mount(&flash);
+    let settings = block_on(Settings::load(&mut store)).unwrap();
+    let mut expected = Settings::default();
+    expected.apply(Setting::Curve(Curve::Quadratic));
+    expected.apply(Setting::Deadzone(200));
+    assert_eq!(settings, expected);
+}
+
+#[test]
+fn profiles_are_switched_and_kept() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    let mut settings = Settings::default();
+    settings.apply(Setting::Curve(Curve::Cubic));
+    block_on(Settings::save(&mut store, &Setting::Curve(Curve::Cubic))).unwrap();
+    block_on(Settings::save(&mut store, &Setting::Brightness(4))).unwrap();
+
+    block_on(profile::switch(&mut store, &mut settings, 2)).unwrap();
+    assert_eq!(settings.profile_name, ProfileName::numbered(2));
+    assert_eq!(settings.curve, Curve::Linear);
+    let name = ProfileName::new("RACING").unwrap();
+    settings.apply(Setting::ProfileName(name));
+    block_on(Settings::save(&mut store, &Setting::ProfileName(name))).unwrap();
+    block_on(Settings::save(&mut store, &Setting::Turbo(Turbo(0b1)))).unwrap();
+
+    // the switch to profile 2 and what was changed in it survive a restart
+    let mut store = mount(&flash);
+    let mut settings = block_on(Settings::load(&mut store)).unwrap();
+    assert_eq!(settings.profile, 2);
+    assert_eq!(settings.profile_name, name);
+    assert_eq!(settings.turbo, Turbo(0b1));
+    assert_eq!(settings.brightness.level(), 4);
+
+    block_on(profile::switch(&mut store, &mut settings, 0)).unwrap();
+    assert_eq!(settings.curve, Curve::Cubic);
+    assert_eq!(settings.turbo, Turbo::NONE);
+    assert_eq!(settings.profile_name, ProfileName::numbered(0));
+    block_on(profile::switch(&mut store, &mut settings, 2)).unwrap();
+    assert_eq!(settings.profile_name, name);
+    assert_eq!(settings.turbo, Turbo(0b1));
+    let taken_up: Option<Profile> = block_on(store.load(Key::profile_slot(2))).unwrap();
+    assert_eq!(taken_up, None);
+    let mut store = mount(&flash);
+    assert_eq!(block_on(Settings::load(&mut store)), Ok(settings));
+}
+
+#[test]
+fn power_cut_during_a_profile_switch_leaves_one_profile_or_the_other() {
+    let start = |flash: &MockFlash| {
+        let mut store = mount(flash);
+        let mut settings = Settings::default();
+        for setting in [Setting::Curve(Curve::Cubic), Setting::Turbo(Turbo(0b1))] {
+            settings.apply(setting);
+            block_on(Settings::save(&mut store, &setting)).unwrap();
+        }
+        (store, settings)
+    };
+    let (_, before) = start(&MockFlash::new(PAGES));
+    let mut after = before;
+    profile::swap(&mut after, 1, None);
+
+    for cut in 0.. {
+        let flash = MockFlash::new(PAGES);
+        let (mut store, mut settings) = start(&flash);
+        flash.cut_power_after(cut);
+        let switched = block_on(profile::switch(&mut store, &mut settings, 1)).is_ok();
+        flash.power_on();
+        let mut store = mount(&flash);
+        let loaded = block_on(Settings::load(&mut store)).unwrap();
+        assert!(
+            loaded == before || loaded == after,
+            "cut after {cut} writes: {loaded:?}"
+        );
+        // taken up for good, so later changes to it aren't undone from the slot
+        assert_eq!(block_on(Settings::load(&mut mount(&flash))), Ok(loaded));
+        if switched {
+            assert_eq!(loaded, after);
+            break;
+        }
+    }
+}
+
+#[test]
+fn a_profile_switch_brings_its_hid_mode_across_a_restart() {
+    let flash = MockFlash::new(PAGES);
+    let mut store = mount(&flash);
+    let mut settings = Settings::default();
+    block_on(profile::switch(&mut store, &mut settings, 1)).unwrap();
+    settings.apply(Setting::HidMode(HidMode::KeyboardMouse));
+    block_on(Settings::save(
+        &mut store,
+        &Setting::HidMode(HidMode::KeyboardMouse),
+    ))
+    .unwrap();
+
+    // back to the first profile's mode, which the firmware restarts to take up
+    block_on(profile::switch(&mut store, &mut settings, 0)).unwrap();
+    assert_eq!(settings.hid_mode, HidMode::Gamepad);
+    let mut store = mount(&flash);
+    let mut settings = block_on(Settings::load(&mut store)).unwrap();
+    assert_eq!(settings.hid_mode, HidMode::Gamepad);
+
+    block_on(profile::switch(&mut store, &mut settings, 1)).unwrap();
+    assert_eq!(settings.hid_mode, HidMode::KeyboardMouse);
+    let mut store = mount(&flash);
+    let settings = block_on(Settings::load(&mut store)).unwrap();
+    assert_eq!(settings.hid_mode, HidMode::KeyboardMouse);
+}
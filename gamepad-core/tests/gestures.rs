@@ -0,0 +1,63 @@
+use gamepad_core::gesture::{Gesture, GestureDetector};
+
+/// Run a `x,y,z` milli-g trace through a fresh detector and collect what it reports
+fn replay(trace: &str) -> Vec<Gesture> {
+    let mut detector = GestureDetector::new();
+    trace
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .filter_map(|line| {
+            let mut axes = line.split(',').map(|v| v.trim().parse::<i32>().unwrap());
+            let sample = [(); 3].map(|_| axes.next().expect("trace line needs x,y,z"));
+            detector.update(sample)
+        })
+        .collect()
+}
+
+#[test]
+fn resting_face_up() {
+    assert_eq!(
+        replay(include_str!("traces/face_up.csv")),
+        [Gesture::FaceUp]
+    );
+}
+
+#[test]
+fn single_tap() {
+    assert_eq!(
+        replay(include_str!("traces/single_tap.csv")),
+        [Gesture::FaceUp, Gesture::SingleTap]
+    );
+}
+
+#[test]
+fn double_tap() {
+    assert_eq!(
+        replay(include_str!("traces/double_tap.csv")),
+        [Gesture::FaceUp, Gesture::DoubleTap]
+    );
+}
+
+#[test]
+fn shake_is_not_a_tap() {
+    assert_eq!(
+        replay(include_str!("traces/shake.csv")),
+        [Gesture::FaceUp, Gesture::Shake]
+    );
+}
+
+#[test]
+fn landing_after_freefall_is_not_a_tap() {
+    assert_eq!(
+        replay(include_str!("traces/freefall.csv")),
+        [Gesture::FaceUp, Gesture::Freefall]
+    );
+}
+
+#[test]
+fn flip_face_down() {
+    assert_eq!(
+        replay(include_str!("traces/flip.csv")),
+        [Gesture::FaceUp, Gesture::FaceDown]
+    );
+}
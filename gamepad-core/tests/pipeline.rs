@@ -0,0 +1,598 @@
+//! The gamepad pipeline run end to end against mock hardware, in real time on the host.
+
+use std::cell::Cell;
+
+use embassy_futures::{
+    block_on,
+    select::{select, select3, Either, Either3},
+};
+use embassy_time::{Duration, Instant, Timer};
+use gamepad_core::{
+    advertising::Phase,
+    audio::{AudioAction, Tune},
+    config::Settings,
+    controller::{perform, Controller, Event, State},
+    display::{Brightness, DisplayAction, DisplayFrame},
+    hal::ReportSink,
+    hid::{
+        key, ConsumerReport, HidReport, KeyboardMouse, KeyboardReport, MediaControl, MediaRemote,
+        MouseReport,
+    },
+    input::{analog_stick_task, buttons_task, AxisId, ButtonId, Curve, GamepadInputs, Report},
+    mock::{
+        Disconnected, MockDisplay, MockHid, MockInput, MockLink, MockSampler, MockSink, MockTone,
+    },
+    power::{idle_watch, Activity, ActivitySink, PowerConfig},
+    scheduler::ReportScheduler,
+};
+use gamepad_protocol::{IdleTimeouts, Turbo};
+
+const CENTRE: i16 = 3740 / 2;
+
+struct Rig {
+    pins: [MockInput; 6],
+    inputs: GamepadInputs<MockInput>,
+    stick: MockSampler<2>,
+    display: MockDisplay,
+    speaker: MockTone,
+    sink: MockSink,
+    settings: Cell<Settings>,
+}
+
+impl Rig {
+    fn new() -> Self {
+        let pins: [MockInput; 6] = Default::default();
+        let [a, b, c, d, e, f] = pins.clone();
+        Self {
+            pins,
+            inputs: GamepadInputs::new(a, b, c, d, e, f),
+            stick: MockSampler::new([CENTRE, CENTRE]),
+            display: MockDisplay::new(),
+            speaker: MockTone::new(),
+            sink: MockSink::new(),
+            settings: Cell::new(Settings::default()),
+        }
+    }
+
+    fn pin(&self, id: ButtonId) -> &MockInput {
+        &self.pins[id as usize]
+    }
+
+    /// Run the input tasks alongside `script`, until either the script or a report fails
+    fn run(&mut self, script: impl core::future::Future<Output = ()>) -> Option<Disconnected> {
+        let mut sampler = self.stick.clone();
+        let buttons = buttons_task(&mut self.inputs, &self.display, &self.sink, &self.settings);
+        let stick = analog_stick_task(&mut sampler, &self.display, &self.sink, &self.settings);
+        match block_on(select3(buttons, stick, script)) {
+            Either3::First(Err(e)) | Either3::Second(Err(e)) => Some(e),
+            Either3::First(Ok(())) | Either3::Second(Ok(())) => {
+                unreachable!("input tasks only stop on a failed report")
+            }
+            Either3::Third(()) => None,
+        }
+    }
+}
+
+fn frames(actions: &[DisplayAction]) -> Vec<DisplayFrame> {
+    actions
+        .iter()
+        .filter_map(|action| match action {
+            DisplayAction::SetFrame { frame, .. } => Some(*frame),
+            _ => None,
+        })
+        .collect()
+}
+
+async fn sleep(ms: u64) {
+    Timer::after(Duration::from_millis(ms)).await;
+}
+
+#[test]
+fn button_press_and_release_are_reported() {
+    let mut rig = Rig::new();
+    let a = rig.pin(ButtonId::A).clone();
+    let e = rig.pin(ButtonId::E).clone();
+    rig.run(async {
+        sleep(10).await;
+        a.press();
+        sleep(100).await;
+        e.press();
+        sleep(100).await;
+        a.release();
+        sleep(100).await;
+        e.release();
+        sleep(100).await;
+    });
+    assert_eq!(
+        rig.sink.take(),
+        [
+            Report::Button {
+                button: ButtonId::A,
+                pressed: true
+            },
+            Report::Button {
+                button: ButtonId::E,
+                pressed: true
+            },
+            Report::Button {
+                button: ButtonId::A,
+                pressed: false
+            },
+            Report::Button {
+                button: ButtonId::E,
+                pressed: false
+            },
+        ]
+    );
+    assert_eq!(
+        frames(&rig.display.take()),
+        [DisplayFrame::Letter('A'), DisplayFrame::Letter('E')]
+    );
+}
+
+#[test]
+fn contact_bounce_is_ignored() {
+    let mut rig = Rig::new();
+    let b = rig.pin(ButtonId::B).clone();
+    rig.run(async {
+        sleep(10).await;
+        // a noisy press settles within the debounce time
+        for _ in 0..3 {
+            b.press();
+            sleep(5).await;
+            b.release();
+            sleep(5).await;
+        }
+        b.press();
+        sleep(150).await;
+        b.release();
+        sleep(100).await;
+    });
+    assert_eq!(
+        rig.sink.take(),
+        [
+            Report::Button {
+                button: ButtonId::B,
+                pressed: true
+            },
+            Report::Button {
+                button: ButtonId::B,
+                pressed: false
+            },
+        ]
+    );
+}
+
+#[test]
+fn turbo_buttons_fire_while_held() {
+    let mut rig = Rig::new();
+    // E is in the profile chord, so it never fires
+    rig.settings.set(Settings {
+        turbo: Turbo(0b01_0001),
+        ..Settings::default()
+    });
+    let a = rig.pin(ButtonId::A).clone();
+    let e = rig.pin(ButtonId::E).clone();
+    rig.run(async {
+        sleep(10).await;
+        a.press();
+        e.press();
+        sleep(230).await;
+        a.release();
+        e.release();
+        sleep(100).await;
+    });
+    let reports = rig.sink.take();
+    let (a, e): (Vec<_>, Vec<_>) = reports.into_iter().partition(|report| {
+        matches!(
+            report,
+            Report::Button {
+                button: ButtonId::A,
+                ..
+            }
+        )
+    });
+    let presses = |button| Report::Button {
+        button,
+        pressed: true,
+    };
+    let releases = |button| Report::Button {
+        button,
+        pressed: false,
+    };
+    assert_eq!(e, [presses(ButtonId::E), releases(ButtonId::E)]);
+    // pressed, then released and pressed again every 50ms, and released for good
+    assert!(a.len() % 2 == 0 && (4..=8).contains(&a.len()), "{a:?}");
+    for (i, report) in a.iter().enumerate() {
+        let expected = match i % 2 {
+            0 => presses(ButtonId::A),
+            _ => releases(ButtonId::A),
+        };
+        assert_eq!(*report, expected);
+    }
+}
+
+#[test]
+fn stick_reports_only_new_levels() {
+    let mut rig = Rig::new();
+    let stick = rig.stick.clone();
+    rig.run(async {
+        // noise around the centre stays in the dead zone
+        for offset in [-300, 200, -100, 300] {
+            stick.set([CENTRE + offset, CENTRE - offset]);
+            sleep(30).await;
+        }
+        stick.set([3740, CENTRE]);
+        sleep(100).await;
+        stick.set([3740, 0]);
+        sleep(100).await;
+    });
+    assert_eq!(
+        rig.sink.take(),
+        [
+            Report::Axis {
+                axis: AxisId::X,
+                value: -3
+            },
+            Report::Axis {
+                axis: AxisId::Y,
+                value: 3
+            },
+        ]
+    );
+    let frames = frames(&rig.display.take());
+    assert_eq!(frames.first(), Some(&DisplayFrame::Coord { x: -3, y: 0 }));
+    assert_eq!(frames.last(), Some(&DisplayFrame::Coord { x: -3, y: 3 }));
+}
+
+#[test]
+fn settings_apply_while_running() {
+    let mut rig = Rig::new();
+    let a = rig.pin(ButtonId::A).clone();
+    let stick = rig.stick.clone();
+    let settings = &rig.settings;
+    let swapped = {
+        let mut mapping = ButtonId::ALL;
+        mapping.swap(ButtonId::A as usize, ButtonId::B as usize);
+        mapping
+    };
+    let script = async {
+        sleep(10).await;
+        a.press();
+        sleep(100).await;
+        // remapped while held, the release still goes to the button that was pressed
+        let mut changed = settings.get();
+        changed.mapping.0 = swapped;
+        changed.deadzone = 900;
+        changed.curve = Curve::Quadratic;
+        settings.set(changed);
+        a.release();
+        sleep(100).await;
+        a.press();
+        sleep(100).await;
+        // within the dead zone
+        stick.set([CENTRE - 850, CENTRE]);
+        sleep(100).await;
+        stick.set([CENTRE - 1500, CENTRE]);
+        sleep(100).await;
+    };
+    let mut sampler = rig.stick.clone();
+    let buttons = buttons_task(&mut rig.inputs, &rig.display, &rig.sink, settings);
+    let analog = analog_stick_task(&mut sampler, &rig.display, &rig.sink, settings);
+    block_on(select3(buttons, analog, script));
+    assert_eq!(
+        rig.sink.take(),
+        [
+            Report::Button {
+                button: ButtonId::A,
+                pressed: true
+            },
+            Report::Button {
+                button: ButtonId::A,
+                pressed: false
+            },
+            Report::Button {
+                button: ButtonId::B,
+                pressed: true
+            },
+            // 600 of the remaining 969 counts, squared
+            Report::Axis {
+                axis: AxisId::X,
+                value: 1
+            },
+        ]
+    );
+}
+
+#[test]
+fn lost_link_goes_back_to_advertising() {
+    let mut rig = Rig::new();
+    let mut controller = Controller::new();
+    block_on(async {
+        perform(controller.start(), &rig.display, &rig.speaker).await;
+        perform(controller.handle(Event::Booted), &rig.display, &rig.speaker).await;
+        perform(
+            controller.handle(Event::Connected),
+            &rig.display,
+            &rig.speaker,
+        )
+        .await;
+    });
+    assert_eq!(controller.state(), State::Connected);
+    assert_eq!(rig.speaker.take(), [AudioAction::PlayTune(Tune::Connect)]);
+    assert_eq!(
+        frames(&rig.display.take()).last(),
+        Some(&DisplayFrame::Heart)
+    );
+
+    let a = rig.pin(ButtonId::A).clone();
+    let sink = rig.sink.clone();
+    let failure = rig.run(async {
+        sleep(10).await;
+        a.press();
+        sleep(100).await;
+        sink.disconnect();
+        a.release();
+        // the release can't be reported, so this never finishes
+        sleep(1000).await;
+    });
+    assert_eq!(failure, Some(Disconnected));
+    assert_eq!(
+        rig.sink.take(),
+        [Report::Button {
+            button: ButtonId::A,
+            pressed: true
+        }]
+    );
+
+    let actions = controller.handle(Event::NotifyFailed);
+    let disconnect = block_on(async {
+        match select(perform(actions, &rig.display, &rig.speaker), sleep(1000)).await {
+            Either::First(disconnect) => disconnect,
+            Either::Second(()) => panic!("performing actions hung"),
+        }
+    });
+    assert!(disconnect);
+    assert_eq!(controller.state(), State::Advertising(Phase::Fast));
+    assert_eq!(
+        rig.speaker.take(),
+        [AudioAction::PlayTune(Tune::Disconnect)]
+    );
+    assert_eq!(
+        frames(&rig.display.take()).last(),
+        Some(&DisplayFrame::QuestionMark)
+    );
+}
+
+#[test]
+fn idle_dims_and_relaxes_then_sleeps() {
+    let mut rig = Rig::new();
+    let a = rig.pin(ButtonId::A).clone();
+    let activity = Activity::new();
+    let config = PowerConfig::default();
+    rig.settings.set(Settings {
+        idle: IdleTimeouts {
+            dim_after_s: 1,
+            sleep_after_s: 2,
+        },
+        ..Settings::default()
+    });
+    let link = MockLink::new();
+    let sink = ActivitySink {
+        sink: &rig.sink,
+        activity: &activity,
+    };
+    let buttons = buttons_task(&mut rig.inputs, &rig.display, &sink, &rig.settings);
+    let watch = idle_watch(&activity, &config, &rig.display, &link, &rig.settings);
+    let script = async {
+        sleep(1200).await;
+        // dimmed by now, and a press brightens it again
+        a.press();
+        sleep(50).await;
+        a.release();
+        sleep(3000).await;
+    };
+    let start = Instant::now();
+    match block_on(select3(buttons, watch, script)) {
+        Either3::Second(()) => {}
+        _ => panic!("the idle watch should finish first"),
+    }
+    // the release restarted the countdown
+    assert!(start.elapsed() >= Duration::from_millis(3250));
+    let brightness: Vec<_> = rig
+        .display
+        .take()
+        .into_iter()
+        .filter_map(|action| match action {
+            DisplayAction::SetBrightness(brightness) => Some(brightness.level()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(brightness, [1, Brightness::MAX.level(), 1]);
+    let (active, relaxed) = (config.active, config.relaxed);
+    assert_eq!(link.take(), [active, relaxed, active, relaxed]);
+}
+
+#[test]
+fn idle_timeouts_are_read_again_when_changed() {
+    let rig = Rig::new();
+    let activity = Activity::new();
+    let config = PowerConfig::default();
+    let link = MockLink::new();
+    let watch = idle_watch(&activity, &config, &rig.display, &link, &rig.settings);
+    let script = async {
+        sleep(100).await;
+        // the defaults would wait a minute to dim, a central shortens it mid-connection
+        let mut settings = rig.settings.get();
+        settings.idle = IdleTimeouts {
+            dim_after_s: 1,
+            sleep_after_s: 2,
+        };
+        rig.settings.set(settings);
+        sleep(5000).await;
+    };
+    let start = Instant::now();
+    match block_on(select(watch, script)) {
+        Either::First(()) => {}
+        Either::Second(()) => panic!("the idle watch should have seen the new timeouts"),
+    }
+    assert!(start.elapsed() < Duration::from_millis(3000));
+}
+
+#[test]
+fn scheduler_batches_changes_and_keeps_every_edge() {
+    let scheduler = ReportScheduler::new(20);
+    let sink = MockSink::new();
+    let press = |button, pressed| Report::Button { button, pressed };
+    let x = |value| Report::Axis {
+        axis: AxisId::X,
+        value,
+    };
+    let script = async {
+        // the first change goes straight out
+        scheduler.report(x(10)).await.unwrap();
+        sleep(10).await;
+        assert_eq!(sink.take(), [x(10)]);
+
+        // the rest wait for the next period, 50 ms after the first
+        scheduler.report(press(ButtonId::A, true)).await.unwrap();
+        scheduler.report(x(20)).await.unwrap();
+        scheduler.report(press(ButtonId::A, false)).await.unwrap();
+        scheduler.report(x(30)).await.unwrap();
+        sleep(20).await;
+        assert_eq!(sink.take(), []);
+        sleep(40).await;
+        assert_eq!(
+            sink.take(),
+            [press(ButtonId::A, true), press(ButtonId::A, false), x(30)]
+        );
+
+        // back where it was sent last, so there's nothing to send
+        scheduler.report(x(20)).await.unwrap();
+        scheduler.report(x(30)).await.unwrap();
+        sleep(100).await;
+        assert_eq!(sink.take(), []);
+
+        // more edges than are held between batches wait rather than being dropped
+        for i in 0..40 {
+            scheduler
+                .report(press(ButtonId::B, i % 2 == 0))
+                .await
+                .unwrap();
+        }
+        sleep(100).await;
+        let edges = sink.take();
+        assert_eq!(edges.len(), 40);
+        for (i, edge) in edges.into_iter().enumerate() {
+            assert_eq!(edge, press(ButtonId::B, i % 2 == 0));
+        }
+    };
+    match block_on(select(scheduler.run(&sink), script)) {
+        Either::First(Err(e)) => panic!("{e:?}"),
+        Either::Second(()) => {}
+    }
+}
+
+#[test]
+fn scheduler_follows_the_connection_interval() {
+    let scheduler = ReportScheduler::new(100);
+    assert_eq!(scheduler.period(), Duration::from_millis(10));
+    scheduler.follow(Duration::from_millis(15));
+    assert_eq!(scheduler.period(), Duration::from_millis(15));
+    // never faster than the maximum rate
+    scheduler.follow(Duration::from_micros(7_500));
+    assert_eq!(scheduler.period(), Duration::from_millis(10));
+}
+
+#[test]
+fn keyboard_mouse_mode_types_and_points() {
+    let mut rig = Rig::new();
+    let a = rig.pin(ButtonId::A).clone();
+    let stick = rig.stick.clone();
+    let out = MockHid::new();
+    let keyboard = KeyboardMouse::new(&out, &rig.settings);
+    let scheduler = ReportScheduler::new(100);
+    let script = async {
+        a.press();
+        sleep(100).await;
+        a.release();
+        sleep(100).await;
+        let space = KeyboardReport {
+            modifiers: 0,
+            keys: [key::SPACE, 0, 0, 0, 0, 0],
+        };
+        assert_eq!(
+            out.take(),
+            [
+                HidReport::Keyboard(space),
+                HidReport::Keyboard(KeyboardReport::default())
+            ]
+        );
+        stick.set([0, CENTRE]);
+        sleep(100).await;
+        stick.set([CENTRE, CENTRE]);
+        sleep(100).await;
+    };
+    let mut sampler = rig.stick.clone();
+    let inputs = select(
+        buttons_task(&mut rig.inputs, &rig.display, &scheduler, &rig.settings),
+        analog_stick_task(&mut sampler, &rig.display, &scheduler, &rig.settings),
+    );
+    let delivery = select(scheduler.run(&keyboard), keyboard.pointer());
+    assert!(matches!(
+        block_on(select3(inputs, delivery, script)),
+        Either3::Third(())
+    ));
+
+    let moves = out.take();
+    assert!((5..=10).contains(&moves.len()), "{moves:?}");
+    for report in moves {
+        assert_eq!(
+            report,
+            HidReport::Mouse(MouseReport {
+                buttons: 0,
+                x: 8,
+                y: 0
+            })
+        );
+    }
+}
+
+#[test]
+fn media_remote_mode_steps_the_volume() {
+    let mut rig = Rig::new();
+    let stick = rig.stick.clone();
+    let out = MockHid::new();
+    let remote = MediaRemote::new(&out);
+    let scheduler = ReportScheduler::new(100);
+    let script = async {
+        stick.set([CENTRE, 0]);
+        sleep(400).await;
+        stick.set([CENTRE, CENTRE]);
+        sleep(100).await;
+    };
+    let mut sampler = rig.stick.clone();
+    let inputs = select(
+        buttons_task(&mut rig.inputs, &rig.display, &scheduler, &rig.settings),
+        analog_stick_task(&mut sampler, &rig.display, &scheduler, &rig.settings),
+    );
+    let delivery = select(scheduler.run(&remote), remote.volume());
+    assert!(matches!(
+        block_on(select3(inputs, delivery, script)),
+        Either3::Third(())
+    ));
+
+    let steps = out.take();
+    // the slowest rate would only step once
+    assert!((4..=6).contains(&(steps.len() / 2)), "{steps:?}");
+    let down = ConsumerReport::default().with(MediaControl::VolumeDown);
+    for pair in steps.chunks(2) {
+        assert_eq!(
+            pair,
+            [
+                HidReport::Consumer(down),
+                HidReport::Consumer(ConsumerReport::default())
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! What the gamepad puts in its advertisements and scan responses.
+//!
+//! Both are at most [`MAX_LEN`] bytes of AD structures, each a length, a type and the data.
+//! [`AdvertisingData`] builds them a structure at a time and refuses one that doesn't fit,
+//! rather than cutting it short.
+
+use crate::{
+    hid::HidMode,
+    value::{exact, DecodeError, Value},
+};
+
+/// Longest legacy advertisement or scan response
+pub const MAX_LEN: usize = 31;
+
+/// AD types, from the Bluetooth Assigned Numbers
+pub mod ad_type {
+    pub const FLAGS: u8 = 0x01;
+    pub const COMPLETE_SERVICE_UUIDS_16: u8 = 0x03;
+    pub const COMPLETE_LOCAL_NAME: u8 = 0x09;
+    pub const TX_POWER_LEVEL: u8 = 0x0a;
+    pub const APPEARANCE: u8 = 0x19;
+    pub const MANUFACTURER_DATA: u8 = 0xff;
+}
+
+/// LE General Discoverable, BR/EDR not supported
+pub const FLAGS: u8 = 0x06;
+/// The GAP appearance for a gamepad
+pub const APPEARANCE_GAMEPAD: u16 = 0x03c4;
+/// The GAP appearance for a keyboard, which hosts also take a mouse to be part of
+pub const APPEARANCE_KEYBOARD: u16 = 0x03c1;
+/// The GAP appearance for a generic remote control
+pub const APPEARANCE_REMOTE_CONTROL: u16 = 0x0180;
+/// The HID service, which hosts look for before offering to pair a controller
+pub const HID_SERVICE: u16 = 0x1812;
+/// The company ID set aside for testing, as the gamepad has no assigned one
+pub const COMPANY_ID: u16 = 0xffff;
+
+/// An AD structure that didn't fit in what was left of the payload
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TooLong {
+    pub ad_type: u8,
+    /// Bytes the structure needs, including its length and type
+    pub needed: usize,
+    pub left: usize,
+}
+
+/// One advertisement or scan response, built up an AD structure at a time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdvertisingData {
+    bytes: [u8; MAX_LEN],
+    len: usize,
+}
+
+impl Default for AdvertisingData {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdvertisingData {
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; MAX_LEN],
+            len: 0,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Append a structure of `ad_type` holding `parts` one after the other
+    pub fn push(mut self, ad_type: u8, parts: &[&[u8]]) -> Result<Self, TooLong> {
+        let data: usize = parts.iter().map(|part| part.len()).sum();
+        let left = MAX_LEN - self.len;
+        if 2 + data > left {
+            return Err(TooLong {
+                ad_type,
+                needed: 2 + data,
+                left,
+            });
+        }
+        self.bytes[self.len] = 1 + data as u8;
+        self.bytes[self.len + 1] = ad_type;
+        self.len += 2;
+        for part in parts {
+            self.bytes[self.len..][..part.len()].copy_from_slice(part);
+            self.len += part.len();
+        }
+        Ok(self)
+    }
+
+    pub fn flags(self, flags: u8) -> Result<Self, TooLong> {
+        self.push(ad_type::FLAGS, &[&[flags]])
+    }
+
+    pub fn appearance(self, appearance: u16) -> Result<Self, TooLong> {
+        self.push(ad_type::APPEARANCE, &[&appearance.to_le_bytes()])
+    }
+
+    /// Every 16-bit service UUID the gamepad has, which is all one of them
+    pub fn service_uuid16(self, uuid: u16) -> Result<Self, TooLong> {
+        self.push(ad_type::COMPLETE_SERVICE_UUIDS_16, &[&uuid.to_le_bytes()])
+    }
+
+    /// The radio's transmit power in dBm, so a central can estimate path loss
+    pub fn tx_power(self, dbm: i8) -> Result<Self, TooLong> {
+        self.push(ad_type::TX_POWER_LEVEL, &[&dbm.to_le_bytes()])
+    }
+
+    pub fn manufacturer(self, data: &Manufacturer) -> Result<Self, TooLong> {
+        let mut bytes = [0; Manufacturer::SIZE];
+        data.encode(&mut bytes);
+        self.push(
+            ad_type::MANUFACTURER_DATA,
+            &[&COMPANY_ID.to_le_bytes(), &bytes],
+        )
+    }
+
+    /// The whole name, never shortened
+    pub fn complete_name(self, name: &str) -> Result<Self, TooLong> {
+        self.push(ad_type::COMPLETE_LOCAL_NAME, &[name.as_bytes()])
+    }
+
+    /// The data of the first structure of `ad_type`, for centrals reading an advertisement
+    pub fn find(bytes: &[u8], ad_type: u8) -> Option<&[u8]> {
+        let mut rest = bytes;
+        while let [len, tail @ ..] = rest {
+            let len = *len as usize;
+            if len == 0 || len > tail.len() {
+                return None;
+            }
+            let (structure, next) = tail.split_at(len);
+            if structure[0] == ad_type {
+                return Some(&structure[1..]);
+            }
+            rest = next;
+        }
+        None
+    }
+}
+
+/// What the gamepad advertises: the appearance for its [`HidMode`], so hosts list it as a
+/// controller, a keyboard or a remote, and the HID service in the modes that present it, then its
+/// transmit power and [`Manufacturer`] data
+pub fn advertisement(
+    mode: HidMode,
+    tx_power: i8,
+    manufacturer: &Manufacturer,
+) -> Result<AdvertisingData, TooLong> {
+    let mut data = AdvertisingData::new()
+        .flags(FLAGS)?
+        .appearance(mode.appearance())?;
+    if mode.uses_hid_service() {
+        data = data.service_uuid16(HID_SERVICE)?;
+    }
+    data.tx_power(tx_power)?.manufacturer(manufacturer)
+}
+
+/// What the gamepad sends back to an active scan, which is its name
+pub fn scan_response(name: &str) -> Result<AdvertisingData, TooLong> {
+    AdvertisingData::new().complete_name(name)
+}
+
+/// The board a gamepad runs on, in [`Manufacturer`] data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Board {
+    MicrobitV2 = 1,
+}
+
+/// Advertised after [`COMPANY_ID`], so a central can tell gamepads apart before connecting.
+/// Encoded as the board, the player, then the battery with 0xff for unknown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Manufacturer {
+    pub board: Board,
+    /// As in the player characteristic
+    pub player: u8,
+    /// Percent charged, `None` when there's no way to measure it
+    pub battery: Option<u8>,
+}
+
+impl Value for Manufacturer {
+    const SIZE: usize = 3;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[..Self::SIZE].copy_from_slice(&[
+            self.board as u8,
+            self.player,
+            self.battery.unwrap_or(0xff),
+        ]);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let [board, player, battery] = exact::<{ Self::SIZE }>(bytes)?;
+        let board = match board {
+            1 => Board::MicrobitV2,
+            _ => return Err(DecodeError::OutOfRange),
+        };
+        let battery = match battery {
+            0xff => None,
+            0..=100 => Some(battery),
+            _ => return Err(DecodeError::OutOfRange),
+        };
+        Ok(Self {
+            board,
+            player,
+            battery,
+        })
+    }
+}
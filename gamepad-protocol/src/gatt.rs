@@ -0,0 +1,743 @@
+//! Every service and characteristic the gamepad exposes.
+//!
+//! Each service has a module holding its UUID and those of its characteristics, and
+//! [`SERVICES`] describes the whole attribute table for tools that want to walk it. Names match
+//! the fields of the firmware's GATT server.
+
+use core::{fmt, str::FromStr};
+
+use crate::{
+    hid::{self, ConsumerReport, HidMode, KeyMap, KeyboardReport, MouseReport},
+    value::{
+        AdvertisingPhase, AxisId, ButtonId, ButtonMapping, ConnectionStatus, Curve, DeviceName,
+        IdleTimeouts, LastError, LinkStatus, Orientation, ProfileName, RecordingControl, Sample,
+        Turbo, Value,
+    },
+};
+
+/// A 128-bit UUID
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Uuid(u128);
+
+/// The text wasn't a UUID in the usual `8-4-4-4-12` hex form
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ParseUuidError;
+
+impl Uuid {
+    pub const fn from_u128(value: u128) -> Self {
+        Self(value)
+    }
+
+    /// A 16-bit UUID assigned by the Bluetooth SIG, on the Bluetooth base UUID
+    pub const fn from_u16(value: u16) -> Self {
+        Self(Self::BASE.0 | (value as u128) << 96)
+    }
+
+    /// `0000xxxx-0000-1000-8000-00805f9b34fb`, which 16-bit UUIDs stand in for
+    const BASE: Self = Self::parse("00000000-0000-1000-8000-00805f9b34fb");
+
+    /// For constants, fails to compile if `text` isn't a UUID
+    pub const fn parse(text: &str) -> Self {
+        match Self::try_parse(text) {
+            Ok(uuid) => uuid,
+            Err(_) => panic!("not a UUID"),
+        }
+    }
+
+    pub const fn try_parse(text: &str) -> Result<Self, ParseUuidError> {
+        let bytes = text.as_bytes();
+        if bytes.len() != 36 {
+            return Err(ParseUuidError);
+        }
+        let mut value: u128 = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            let byte = bytes[i];
+            if matches!(i, 8 | 13 | 18 | 23) {
+                if byte != b'-' {
+                    return Err(ParseUuidError);
+                }
+            } else {
+                let digit = match byte {
+                    b'0'..=b'9' => byte - b'0',
+                    b'a'..=b'f' => byte - b'a' + 10,
+                    b'A'..=b'F' => byte - b'A' + 10,
+                    _ => return Err(ParseUuidError),
+                };
+                value = value << 4 | digit as u128;
+            }
+            i += 1;
+        }
+        Ok(Self(value))
+    }
+
+    /// The 16-bit form, if this is one of the UUIDs on the Bluetooth base UUID
+    pub const fn to_u16(&self) -> Option<u16> {
+        if self.0 & !(0xffff << 96) == Self::BASE.0 {
+            Some((self.0 >> 96) as u16)
+        } else {
+            None
+        }
+    }
+
+    pub const fn as_u128(&self) -> u128 {
+        self.0
+    }
+
+    /// The order the bytes go over the air, least significant first
+    pub const fn to_le_bytes(&self) -> [u8; 16] {
+        self.0.to_le_bytes()
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let v = self.0;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            v >> 96,
+            (v >> 80) & 0xffff,
+            (v >> 64) & 0xffff,
+            (v >> 48) & 0xffff,
+            v & 0xffff_ffff_ffff
+        )
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = ParseUuidError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::try_parse(text)
+    }
+}
+
+impl fmt::Display for ParseUuidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid UUID")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseUuidError {}
+
+/// The six buttons, each a `bool` that is notified on press and release
+pub mod button {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("260279e7-a5dd-447b-9bd8-e624ef464d6e");
+    pub const A: Uuid = Uuid::parse("c665eb11-eee4-452b-9047-a98a3916bd80");
+    pub const B: Uuid = Uuid::parse("7c9a1a08-ecf2-4f7d-a24b-0ab01615cc77");
+    pub const C: Uuid = Uuid::parse("163a7681-4b8b-4249-899d-ae1a634ce692");
+    pub const D: Uuid = Uuid::parse("c8ede9b0-4eeb-4f31-b8d4-f920881961fa");
+    pub const E: Uuid = Uuid::parse("7729d82d-a8b9-4c3e-95bf-3794b70aba56");
+    pub const F: Uuid = Uuid::parse("f8f17954-f235-4d71-8ece-1522ec067c55");
+}
+
+/// The analog stick, each axis an `i8` level in -3..=3
+pub mod stick {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("7e701cf1-b1df-42a1-bb5f-6a1028c793b0");
+    pub const X: Uuid = Uuid::parse("e3d1afe4-b414-44e3-be54-0ea26c394eba");
+    pub const Y: Uuid = Uuid::parse("65133212-952b-4000-a735-ea558db3ca7b");
+}
+
+/// Which player the controller belongs to, chosen by the central
+pub mod player {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("8f701cf1-b1df-42a1-bb5f-6a1028c793b0");
+    /// A `u8`. Shares its UUID with [`super::stick::X`], tell them apart by service.
+    pub const INDEX: Uuid = Uuid::parse("e3d1afe4-b414-44e3-be54-0ea26c394eba");
+}
+
+/// Compass heading, optionally steering an axis of its own
+pub mod heading {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("b747472b-9e9d-408f-8dd2-7dcb6780a725");
+    /// A `u16` in degrees, 0..=359
+    pub const HEADING: Uuid = Uuid::parse("34f88731-a1d5-4477-afde-4f27c453923f");
+    /// A `bool`, while set the heading drives [`AXIS`]
+    pub const STEERING: Uuid = Uuid::parse("9ef26446-28ae-47f3-b7b3-5f7567cfb5fe");
+    /// An `i8` stick level for the heading relative to where the gamepad pointed when steering
+    /// was set, notified as it changes and 0 while steering is off. It is kept apart from the
+    /// stick's x so the two never overwrite each other.
+    pub const AXIS: Uuid = Uuid::parse("9ef26447-28ae-47f3-b7b3-5f7567cfb5fe");
+    /// A `bool`, write true to start compass calibration
+    pub const CALIBRATE: Uuid = Uuid::parse("f38578cf-84f7-44a5-9ca1-fe6dd617a42f");
+}
+
+/// Discrete motion events
+pub mod gesture {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("da109ab5-ebe0-45e0-9739-39ff5d68e210");
+    /// A [`Gesture`](crate::Gesture) code
+    pub const GESTURE: Uuid = Uuid::parse("1866f54a-0613-4c99-84f5-0ecd39d80c87");
+}
+
+/// Fused orientation
+pub mod motion {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("321bd119-877f-488a-bf75-bd508d6de21c");
+    /// An [`Orientation`](crate::Orientation)
+    pub const ORIENTATION: Uuid = Uuid::parse("e58a92c6-7789-4656-8f53-b8e318c76880");
+    /// A `u8`, orientation notifications per second, 0 to disable
+    pub const RATE: Uuid = Uuid::parse("aaba81a3-16ee-4c9d-aa51-62da77f63cb3");
+}
+
+/// Health of the controller, for support and debugging
+pub mod diagnostics {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("87813cfc-50cf-4a92-a1d1-2e97b79233f1");
+    /// A [`LastError`](crate::LastError)
+    pub const LAST_ERROR: Uuid = Uuid::parse("cc3f7406-d79c-4903-9a4b-3f5ffb6ed336");
+    /// A [`ConnectionStatus`](crate::ConnectionStatus), zeros until a central connects, updated
+    /// whenever the central changes the connection parameters
+    pub const CONNECTION: Uuid = Uuid::parse("cc3f7407-d79c-4903-9a4b-3f5ffb6ed336");
+    /// A [`LinkStatus`](crate::LinkStatus), the defaults until the central agrees to a change,
+    /// then updated as the controller reports each one
+    pub const LINK: Uuid = Uuid::parse("cc3f7408-d79c-4903-9a4b-3f5ffb6ed336");
+}
+
+/// Raw inputs kept in RAM, for reproducing problems on a host
+pub mod recording {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("5c3d0e2a-7f41-4b8e-9a36-1d2b8f7c4e90");
+    /// A [`RecordingControl`](crate::value::RecordingControl)
+    pub const CONTROL: Uuid = Uuid::parse("5c3d0e2b-7f41-4b8e-9a36-1d2b8f7c4e90");
+    /// A `u16`, how many samples are held
+    pub const LENGTH: Uuid = Uuid::parse("5c3d0e2c-7f41-4b8e-9a36-1d2b8f7c4e90");
+    /// A [`Sample`](crate::value::Sample), notified once for each while downloading
+    pub const SAMPLE: Uuid = Uuid::parse("5c3d0e2d-7f41-4b8e-9a36-1d2b8f7c4e90");
+}
+
+/// The standard GAP service, which the firmware builds itself so the name can be written here as
+/// well as through [`config::NAME`]
+pub mod gap {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::from_u16(0x1800);
+    /// The name in use, UTF-8 without a terminator. Up to [`NAME_MAX`] bytes can be written, and
+    /// a name longer than a [`DeviceName`](crate::DeviceName) is cut short between characters.
+    pub const DEVICE_NAME: Uuid = Uuid::from_u16(0x2a00);
+    /// A `u16`, the [`HidMode::appearance`](crate::hid::HidMode::appearance) of the mode in use
+    pub const APPEARANCE: Uuid = Uuid::from_u16(0x2a01);
+
+    /// Longest write to [`DEVICE_NAME`]
+    pub const NAME_MAX: usize = 64;
+}
+
+/// The standard HID service, through which hosts see a keyboard and mouse in
+/// [`HidMode::KeyboardMouse`](crate::hid::HidMode::KeyboardMouse), and media keys in
+/// [`HidMode::MediaRemote`](crate::hid::HidMode::MediaRemote). It isn't there in gamepad mode.
+pub mod hid_device {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::from_u16(0x1812);
+    /// [`INFORMATION`](crate::hid::INFORMATION)
+    pub const INFORMATION: Uuid = Uuid::from_u16(0x2a4a);
+    /// [`REPORT_MAP`](crate::hid::REPORT_MAP)
+    pub const REPORT_MAP: Uuid = Uuid::from_u16(0x2a4b);
+    /// A `u8` the host writes, 0 to suspend and 1 to exit suspend
+    pub const CONTROL_POINT: Uuid = Uuid::from_u16(0x2a4c);
+    /// An input report, one characteristic for each, told apart by their report reference. Reports
+    /// only go out over an encrypted link, and the gamepad asks each central to pair when it
+    /// connects in a HID mode.
+    pub const REPORT: Uuid = Uuid::from_u16(0x2a4d);
+    /// The descriptor on each report holding its report ID, then 1 for input
+    pub const REPORT_REFERENCE: Uuid = Uuid::from_u16(0x2908);
+}
+
+/// The standard Device Information service, which HID hosts read the PnP ID from
+pub mod device_information {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::from_u16(0x180a);
+    /// [`PNP_ID`](crate::hid::PNP_ID)
+    pub const PNP_ID: Uuid = Uuid::from_u16(0x2a50);
+}
+
+/// The standard Battery service, which HID hosts expect a keyboard or remote to have
+pub mod battery {
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::from_u16(0x180f);
+    /// A `u8` percentage. The board can't measure its supply, so this is always 100.
+    pub const LEVEL: Uuid = Uuid::from_u16(0x2a19);
+}
+
+/// Tunable settings, each saved in flash and applied as soon as it is written. Writes outside the
+/// ranges here are refused.
+pub mod config {
+    use core::ops::RangeInclusive;
+
+    use super::Uuid;
+
+    pub const SERVICE: Uuid = Uuid::parse("4b9d2c60-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A [`DeviceName`](crate::value::DeviceName), advertised as soon as it's written and also
+    /// readable as [`gap::DEVICE_NAME`](super::gap::DEVICE_NAME)
+    pub const NAME: Uuid = Uuid::parse("4b9d2c61-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A `u16`, stick readings within this many counts of the centre read as centred
+    pub const DEADZONE: Uuid = Uuid::parse("4b9d2c62-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A [`Curve`](crate::value::Curve)
+    pub const CURVE: Uuid = Uuid::parse("4b9d2c63-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A `u8`, milliseconds a button is left to settle after it changes
+    pub const DEBOUNCE: Uuid = Uuid::parse("4b9d2c64-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A `u8`, stick samples per second
+    pub const REPORT_RATE: Uuid = Uuid::parse("4b9d2c65-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A `u8` level, LED matrix brightness
+    pub const BRIGHTNESS: Uuid = Uuid::parse("4b9d2c66-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A `u8` level, speaker volume where 0 is silent
+    pub const VOLUME: Uuid = Uuid::parse("4b9d2c67-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A [`ButtonMapping`](crate::value::ButtonMapping)
+    pub const MAPPING: Uuid = Uuid::parse("4b9d2c68-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A [`HidMode`](crate::hid::HidMode), saved, then the gamepad restarts to take it up
+    pub const HID_MODE: Uuid = Uuid::parse("4b9d2c69-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A [`KeyMap`](crate::hid::KeyMap)
+    pub const KEY_MAP: Uuid = Uuid::parse("4b9d2c6a-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A [`Turbo`](crate::value::Turbo)
+    pub const TURBO: Uuid = Uuid::parse("4b9d2c6b-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A [`ProfileName`](crate::value::ProfileName), of the profile in use
+    pub const PROFILE_NAME: Uuid = Uuid::parse("4b9d2c6c-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A `u8`, the profile in use. Writing another switches to it: the mapping, stick
+    /// response, turbo, HID mode, key map and name above become that profile's, and what they
+    /// were is kept for switching back.
+    pub const PROFILE: Uuid = Uuid::parse("4b9d2c6d-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// An [`AdvertisingPhase`](crate::value::AdvertisingPhase), how eagerly the gamepad
+    /// advertises after boot, a disconnect or waking up
+    pub const FAST_ADVERTISING: Uuid = Uuid::parse("4b9d2c6e-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// An [`AdvertisingPhase`](crate::value::AdvertisingPhase), how it advertises once the fast
+    /// phase is over, and while a companion could join
+    pub const SLOW_ADVERTISING: Uuid = Uuid::parse("4b9d2c6f-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// [`IdleTimeouts`](crate::value::IdleTimeouts), for dimming and sleeping while connected
+    pub const IDLE_TIMEOUTS: Uuid = Uuid::parse("4b9d2c70-3e7a-4f15-8c2d-9e6b1a7f3c50");
+    /// A `u8`, the most batches of input reports sent a second, however short the connection
+    /// interval
+    pub const BATCH_RATE: Uuid = Uuid::parse("4b9d2c71-3e7a-4f15-8c2d-9e6b1a7f3c50");
+
+    /// Up to half the stick's travel from the centre to either end
+    pub const DEADZONE_RANGE: RangeInclusive<u16> = 0..=935;
+    pub const DEBOUNCE_RANGE: RangeInclusive<u8> = 5..=100;
+    pub const REPORT_RATE_RANGE: RangeInclusive<u8> = 10..=100;
+    /// Brightness and volume
+    pub const LEVEL_RANGE: RangeInclusive<u8> = 0..=10;
+    /// One of four profiles
+    pub const PROFILE_RANGE: RangeInclusive<u8> = 0..=3;
+    /// Both ends of an advertising interval, 20 ms to 10.24 s in 0.625 ms steps as the link
+    /// layer allows
+    pub const ADVERTISING_INTERVAL_RANGE: RangeInclusive<u16> = 0x20..=0x4000;
+    /// Seconds in an advertising phase, up to an hour
+    pub const ADVERTISING_TIMEOUT_RANGE: RangeInclusive<u16> = 1..=3600;
+    /// Seconds before dimming and before sleeping, up to ten hours
+    pub const IDLE_TIMEOUT_RANGE: RangeInclusive<u16> = 1..=36_000;
+    /// Up to one batch per 7.5 ms, the shortest connection interval
+    pub const BATCH_RATE_RANGE: RangeInclusive<u8> = 10..=133;
+}
+
+/// What a central may do with a characteristic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Properties {
+    pub read: bool,
+    pub write: bool,
+    pub notify: bool,
+}
+
+const READ_NOTIFY: Properties = Properties {
+    read: true,
+    write: false,
+    notify: true,
+};
+const READ_WRITE: Properties = Properties {
+    read: true,
+    write: true,
+    notify: false,
+};
+const READ: Properties = Properties {
+    read: true,
+    write: false,
+    notify: false,
+};
+const WRITE: Properties = Properties {
+    read: false,
+    write: true,
+    notify: false,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Characteristic {
+    pub name: &'static str,
+    pub uuid: Uuid,
+    pub properties: Properties,
+    /// Length of the value in bytes, or the longest it can be for the GAP device name
+    pub size: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Service {
+    pub name: &'static str,
+    pub uuid: Uuid,
+    pub characteristics: &'static [Characteristic],
+}
+
+impl Service {
+    /// Whether the attribute table has this service in `mode`. The HID service, and the Device
+    /// Information and Battery services that go with it, are left out in gamepad mode.
+    pub fn present(&self, mode: HidMode) -> bool {
+        let hid_only = [
+            hid_device::SERVICE,
+            device_information::SERVICE,
+            battery::SERVICE,
+        ];
+        mode.uses_hid_service() || !hid_only.contains(&self.uuid)
+    }
+
+    pub fn characteristic(&self, uuid: Uuid) -> Option<&'static Characteristic> {
+        self.characteristics.iter().find(|c| c.uuid == uuid)
+    }
+}
+
+const fn characteristic(
+    name: &'static str,
+    uuid: Uuid,
+    properties: Properties,
+    size: usize,
+) -> Characteristic {
+    Characteristic {
+        name,
+        uuid,
+        properties,
+        size,
+    }
+}
+
+/// The whole attribute table, in the order the services are registered. The HID services come
+/// first, and are only there in the HID modes, see [`Service::present`].
+pub const SERVICES: &[Service] = &[
+    Service {
+        name: "hid_device",
+        uuid: hid_device::SERVICE,
+        characteristics: &[
+            characteristic(
+                "information",
+                hid_device::INFORMATION,
+                READ,
+                hid::INFORMATION.len(),
+            ),
+            characteristic(
+                "report_map",
+                hid_device::REPORT_MAP,
+                READ,
+                hid::REPORT_MAP_LEN,
+            ),
+            characteristic("control_point", hid_device::CONTROL_POINT, WRITE, u8::SIZE),
+            characteristic(
+                "keyboard",
+                hid_device::REPORT,
+                READ_NOTIFY,
+                KeyboardReport::SIZE,
+            ),
+            characteristic("mouse", hid_device::REPORT, READ_NOTIFY, MouseReport::SIZE),
+            characteristic(
+                "consumer",
+                hid_device::REPORT,
+                READ_NOTIFY,
+                ConsumerReport::SIZE,
+            ),
+        ],
+    },
+    Service {
+        name: "device_information",
+        uuid: device_information::SERVICE,
+        characteristics: &[characteristic(
+            "pnp_id",
+            device_information::PNP_ID,
+            READ,
+            hid::PNP_ID.len(),
+        )],
+    },
+    Service {
+        name: "battery",
+        uuid: battery::SERVICE,
+        characteristics: &[characteristic("level", battery::LEVEL, READ, u8::SIZE)],
+    },
+    Service {
+        name: "gap",
+        uuid: gap::SERVICE,
+        characteristics: &[
+            characteristic("device_name", gap::DEVICE_NAME, READ_WRITE, gap::NAME_MAX),
+            characteristic("appearance", gap::APPEARANCE, READ, u16::SIZE),
+        ],
+    },
+    Service {
+        name: "hid",
+        uuid: button::SERVICE,
+        characteristics: &[
+            characteristic("button_a", button::A, READ_NOTIFY, bool::SIZE),
+            characteristic("button_b", button::B, READ_NOTIFY, bool::SIZE),
+            characteristic("button_c", button::C, READ_NOTIFY, bool::SIZE),
+            characteristic("button_d", button::D, READ_NOTIFY, bool::SIZE),
+            characteristic("button_e", button::E, READ_NOTIFY, bool::SIZE),
+            characteristic("button_f", button::F, READ_NOTIFY, bool::SIZE),
+        ],
+    },
+    Service {
+        name: "stick",
+        uuid: stick::SERVICE,
+        characteristics: &[
+            characteristic("x", stick::X, READ_NOTIFY, i8::SIZE),
+            characteristic("y", stick::Y, READ_NOTIFY, i8::SIZE),
+        ],
+    },
+    Service {
+        name: "player",
+        uuid: player::SERVICE,
+        characteristics: &[characteristic("index", player::INDEX, READ_WRITE, u8::SIZE)],
+    },
+    Service {
+        name: "heading",
+        uuid: heading::SERVICE,
+        characteristics: &[
+            characteristic("heading", heading::HEADING, READ_NOTIFY, u16::SIZE),
+            characteristic("steering", heading::STEERING, READ_WRITE, bool::SIZE),
+            characteristic("axis", heading::AXIS, READ_NOTIFY, i8::SIZE),
+            characteristic("calibrate", heading::CALIBRATE, READ_WRITE, bool::SIZE),
+        ],
+    },
+    Service {
+        name: "gesture",
+        uuid: gesture::SERVICE,
+        characteristics: &[characteristic(
+            "gesture",
+            gesture::GESTURE,
+            READ_NOTIFY,
+            u8::SIZE,
+        )],
+    },
+    Service {
+        name: "motion",
+        uuid: motion::SERVICE,
+        characteristics: &[
+            characteristic(
+                "orientation",
+                motion::ORIENTATION,
+                READ_NOTIFY,
+                Orientation::SIZE,
+            ),
+            characteristic("rate", motion::RATE, READ_WRITE, u8::SIZE),
+        ],
+    },
+    Service {
+        name: "diagnostics",
+        uuid: diagnostics::SERVICE,
+        characteristics: &[
+            characteristic("last_error", diagnostics::LAST_ERROR, READ, LastError::SIZE),
+            characteristic(
+                "connection",
+                diagnostics::CONNECTION,
+                READ,
+                ConnectionStatus::SIZE,
+            ),
+            characteristic("link", diagnostics::LINK, READ, LinkStatus::SIZE),
+        ],
+    },
+    Service {
+        name: "recording",
+        uuid: recording::SERVICE,
+        characteristics: &[
+            characteristic(
+                "control",
+                recording::CONTROL,
+                READ_WRITE,
+                RecordingControl::SIZE,
+            ),
+            characteristic("length", recording::LENGTH, READ_NOTIFY, u16::SIZE),
+            characteristic("sample", recording::SAMPLE, READ_NOTIFY, Sample::SIZE),
+        ],
+    },
+    Service {
+        name: "config",
+        uuid: config::SERVICE,
+        characteristics: &[
+            characteristic("name", config::NAME, READ_WRITE, DeviceName::SIZE),
+            characteristic("deadzone", config::DEADZONE, READ_WRITE, u16::SIZE),
+            characteristic("curve", config::CURVE, READ_WRITE, Curve::SIZE),
+            characteristic("debounce", config::DEBOUNCE, READ_WRITE, u8::SIZE),
+            characteristic("report_rate", config::REPORT_RATE, READ_WRITE, u8::SIZE),
+            characteristic("brightness", config::BRIGHTNESS, READ_WRITE, u8::SIZE),
+            characteristic("volume", config::VOLUME, READ_WRITE, u8::SIZE),
+            characteristic("mapping", config::MAPPING, READ_WRITE, ButtonMapping::SIZE),
+            characteristic("hid_mode", config::HID_MODE, READ_WRITE, HidMode::SIZE),
+            characteristic("key_map", config::KEY_MAP, READ_WRITE, KeyMap::SIZE),
+            characteristic("turbo", config::TURBO, READ_WRITE, Turbo::SIZE),
+            characteristic(
+                "profile_name",
+                config::PROFILE_NAME,
+                READ_WRITE,
+                ProfileName::SIZE,
+            ),
+            characteristic("profile", config::PROFILE, READ_WRITE, u8::SIZE),
+            characteristic(
+                "fast_advertising",
+                config::FAST_ADVERTISING,
+                READ_WRITE,
+                AdvertisingPhase::SIZE,
+            ),
+            characteristic(
+                "slow_advertising",
+                config::SLOW_ADVERTISING,
+                READ_WRITE,
+                AdvertisingPhase::SIZE,
+            ),
+            characteristic(
+                "idle_timeouts",
+                config::IDLE_TIMEOUTS,
+                READ_WRITE,
+                IdleTimeouts::SIZE,
+            ),
+            characteristic("batch_rate", config::BATCH_RATE, READ_WRITE, u8::SIZE),
+        ],
+    },
+];
+
+pub fn service(uuid: Uuid) -> Option<&'static Service> {
+    SERVICES.iter().find(|s| s.uuid == uuid)
+}
+
+/// The characteristic a button is notified on
+pub fn button_characteristic(button: ButtonId) -> Uuid {
+    match button {
+        ButtonId::A => button::A,
+        ButtonId::B => button::B,
+        ButtonId::C => button::C,
+        ButtonId::D => button::D,
+        ButtonId::E => button::E,
+        ButtonId::F => button::F,
+    }
+}
+
+/// The characteristic a stick axis is notified on
+pub fn axis_characteristic(axis: AxisId) -> Uuid {
+    match axis {
+        AxisId::X => stick::X,
+        AxisId::Y => stick::Y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_text_round_trip() {
+        let text = "260279e7-a5dd-447b-9bd8-e624ef464d6e";
+        let uuid: Uuid = text.parse().unwrap();
+        assert_eq!(uuid, button::SERVICE);
+        assert_eq!(uuid.to_string(), text);
+        assert_eq!(
+            "260279E7-A5DD-447B-9BD8-E624EF464D6E".parse::<Uuid>(),
+            Ok(uuid)
+        );
+    }
+
+    #[test]
+    fn uuid_bytes_are_little_endian() {
+        assert_eq!(
+            button::SERVICE.to_le_bytes(),
+            [
+                0x6e, 0x4d, 0x46, 0xef, 0x24, 0xe6, 0xd8, 0x9b, 0x7b, 0x44, 0xdd, 0xa5, 0xe7, 0x79,
+                0x02, 0x26
+            ]
+        );
+    }
+
+    #[test]
+    fn short_uuids_are_on_the_base_uuid() {
+        assert_eq!(
+            hid_device::SERVICE.to_string(),
+            "00001812-0000-1000-8000-00805f9b34fb"
+        );
+        assert_eq!(hid_device::SERVICE.to_u16(), Some(0x1812));
+        assert_eq!(button::SERVICE.to_u16(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_uuids() {
+        for text in [
+            "",
+            "260279e7a5dd447b9bd8e624ef464d6e",
+            "260279e7-a5dd-447b-9bd8-e624ef464d6",
+            "260279e7-a5dd-447b-9bd8_e624ef464d6e",
+            "g60279e7-a5dd-447b-9bd8-e624ef464d6e",
+        ] {
+            assert_eq!(text.parse::<Uuid>(), Err(ParseUuidError), "{text}");
+        }
+    }
+
+    #[test]
+    fn uuids_are_unique_within_the_table() {
+        let mut services: Vec<Uuid> = SERVICES.iter().map(|s| s.uuid).collect();
+        services.sort();
+        services.dedup();
+        assert_eq!(services.len(), SERVICES.len());
+        for service in SERVICES {
+            // every HID report has the same UUID, and a report reference to tell them apart
+            let mut uuids: Vec<Uuid> = service
+                .characteristics
+                .iter()
+                .map(|c| c.uuid)
+                .filter(|uuid| *uuid != hid_device::REPORT)
+                .collect();
+            uuids.sort();
+            uuids.dedup();
+            let reports = service
+                .characteristics
+                .iter()
+                .filter(|c| c.uuid == hid_device::REPORT)
+                .count();
+            assert_eq!(
+                uuids.len() + reports,
+                service.characteristics.len(),
+                "{}",
+                service.name
+            );
+        }
+    }
+
+    #[test]
+    fn hid_services_are_only_present_in_hid_modes() {
+        let present = |mode| {
+            SERVICES
+                .iter()
+                .filter(|s| s.present(mode))
+                .map(|s| s.name)
+                .collect::<Vec<_>>()
+        };
+        let gamepad = present(HidMode::Gamepad);
+        assert!(gamepad.contains(&"config"));
+        assert!(!gamepad.contains(&"hid_device"));
+        assert!(!gamepad.contains(&"battery"));
+        assert_eq!(present(HidMode::KeyboardMouse).len(), SERVICES.len());
+        assert_eq!(present(HidMode::MediaRemote).len(), SERVICES.len());
+    }
+
+    #[test]
+    fn finds_services_and_characteristics() {
+        let stick = service(stick::SERVICE).unwrap();
+        assert_eq!(stick.name, "stick");
+        assert_eq!(stick.characteristic(stick::Y).unwrap().name, "y");
+        assert_eq!(axis_characteristic(AxisId::X), stick::X);
+        assert_eq!(button_characteristic(ButtonId::F), button::F);
+    }
+}
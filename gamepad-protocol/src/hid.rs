@@ -0,0 +1,632 @@
+//! The HID over GATT side of the gamepad, for hosts that only take keyboards, mice and remotes.
+//!
+//! [`REPORTS`] declares each input report as a list of [`Field`]s, and [`REPORT_MAP`] is the HID
+//! report descriptor generated from it at compile time, so the map a host parses and the
+//! encodings here can't disagree. Each report has its own report characteristic, whose value is
+//! the report without its ID.
+
+use crate::{
+    advertising::COMPANY_ID,
+    value::{exact, DecodeError, Value},
+};
+
+/// What the gamepad presents itself to the host as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum HidMode {
+    /// The gamepad services, for games and tools that know them
+    #[default]
+    Gamepad = 0,
+    /// A keyboard and mouse, through the HID service, with buttons pressing the keys in the
+    /// [`KeyMap`] and the stick, or tilting the board, moving the pointer
+    KeyboardMouse = 1,
+    /// A media remote, through the HID service, with A and B turning slides, the stick for
+    /// volume and seeking, and a shake to play or pause
+    MediaRemote = 2,
+}
+
+impl HidMode {
+    /// The GAP appearance to advertise
+    pub fn appearance(&self) -> u16 {
+        match self {
+            Self::Gamepad => crate::advertising::APPEARANCE_GAMEPAD,
+            Self::KeyboardMouse => crate::advertising::APPEARANCE_KEYBOARD,
+            Self::MediaRemote => crate::advertising::APPEARANCE_REMOTE_CONTROL,
+        }
+    }
+
+    /// Whether the mode presents the HID service, so hosts should be told it's there
+    pub fn uses_hid_service(&self) -> bool {
+        *self != Self::Gamepad
+    }
+
+    /// What the display scrolls when the board switches to the mode
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Gamepad => "PAD",
+            Self::KeyboardMouse => "KEYS",
+            Self::MediaRemote => "MEDIA",
+        }
+    }
+
+    /// The next mode, for cycling through them on the board
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Gamepad => Self::KeyboardMouse,
+            Self::KeyboardMouse => Self::MediaRemote,
+            Self::MediaRemote => Self::Gamepad,
+        }
+    }
+}
+
+impl Value for HidMode {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(match exact::<1>(bytes)?[0] {
+            0 => Self::Gamepad,
+            1 => Self::KeyboardMouse,
+            2 => Self::MediaRemote,
+            _ => return Err(DecodeError::OutOfRange),
+        })
+    }
+}
+
+/// HID usage pages, from the HID Usage Tables
+pub mod usage_page {
+    pub const GENERIC_DESKTOP: u16 = 0x01;
+    pub const KEYBOARD: u16 = 0x07;
+    pub const BUTTON: u16 = 0x09;
+    pub const CONSUMER: u16 = 0x0c;
+}
+
+/// Generic desktop usages
+pub mod usage {
+    pub const POINTER: u16 = 0x01;
+    pub const MOUSE: u16 = 0x02;
+    pub const KEYBOARD: u16 = 0x06;
+    pub const X: u16 = 0x30;
+    pub const Y: u16 = 0x31;
+}
+
+/// Consumer usages
+pub mod consumer {
+    pub const CONSUMER_CONTROL: u16 = 0x01;
+    pub const FAST_FORWARD: u16 = 0xb3;
+    pub const REWIND: u16 = 0xb4;
+    pub const SCAN_NEXT_TRACK: u16 = 0xb5;
+    pub const SCAN_PREVIOUS_TRACK: u16 = 0xb6;
+    pub const PLAY_PAUSE: u16 = 0xcd;
+    pub const VOLUME_INCREMENT: u16 = 0xe9;
+    pub const VOLUME_DECREMENT: u16 = 0xea;
+}
+
+/// Part of an input report, in the order it's sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// One bit for each usage from `first` on `page`, such as modifier keys or mouse buttons
+    Flags { page: u16, first: u16, count: u8 },
+    /// A byte slot for each of `count` usages on `page` held at once, holding the usage from 1
+    /// to `max`, or 0 for none, as a keyboard's pressed keys
+    Slots { page: u16, max: u16, count: u8 },
+    /// A signed byte for each of `usages` on `page`, a change since the last report
+    Relative { page: u16, usages: &'static [u16] },
+    /// One bit for each of `usages` on `page`, which needn't be consecutive, as media keys
+    Controls { page: u16, usages: &'static [u16] },
+    /// Bits that are always zero, to round the report up to whole bytes
+    Padding(u8),
+}
+
+impl Field {
+    /// Length in bits
+    pub const fn bits(&self) -> usize {
+        match self {
+            Self::Flags { count, .. } => *count as usize,
+            Self::Slots { count, .. } => *count as usize * 8,
+            Self::Relative { usages, .. } => usages.len() * 8,
+            Self::Controls { usages, .. } => usages.len(),
+            Self::Padding(bits) => *bits as usize,
+        }
+    }
+}
+
+/// An input report, in an application collection of its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub id: u8,
+    /// The usage page of the application
+    pub page: u16,
+    /// The application, such as a keyboard
+    pub usage: u16,
+    /// A physical collection the fields go in, as a mouse's pointer
+    pub physical: Option<u16>,
+    pub fields: &'static [Field],
+}
+
+impl Report {
+    /// Length in bytes, without the report ID
+    pub const fn size(&self) -> usize {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < self.fields.len() {
+            bits += self.fields[i].bits();
+            i += 1;
+        }
+        bits.div_ceil(8)
+    }
+}
+
+/// Modifier keys, then a reserved byte, then up to six keys held at once
+pub const KEYBOARD: Report = Report {
+    id: 1,
+    page: usage_page::GENERIC_DESKTOP,
+    usage: usage::KEYBOARD,
+    physical: None,
+    fields: &[
+        Field::Flags {
+            page: usage_page::KEYBOARD,
+            first: 0xe0,
+            count: 8,
+        },
+        Field::Padding(8),
+        Field::Slots {
+            page: usage_page::KEYBOARD,
+            max: KEY_MAX as u16,
+            count: 6,
+        },
+    ],
+};
+
+/// Three buttons, then x and y movement
+pub const MOUSE: Report = Report {
+    id: 2,
+    page: usage_page::GENERIC_DESKTOP,
+    usage: usage::MOUSE,
+    physical: Some(usage::POINTER),
+    fields: &[
+        Field::Flags {
+            page: usage_page::BUTTON,
+            first: 1,
+            count: 3,
+        },
+        Field::Padding(5),
+        Field::Relative {
+            page: usage_page::GENERIC_DESKTOP,
+            usages: &[usage::X, usage::Y],
+        },
+    ],
+};
+
+/// A bit for each [`MediaControl`]
+pub const CONSUMER: Report = Report {
+    id: 3,
+    page: usage_page::CONSUMER,
+    usage: consumer::CONSUMER_CONTROL,
+    physical: None,
+    fields: &[
+        Field::Controls {
+            page: usage_page::CONSUMER,
+            usages: &MediaControl::USAGES,
+        },
+        Field::Padding(1),
+    ],
+};
+
+/// Every input report, in report map order
+pub const REPORTS: &[Report] = &[KEYBOARD, MOUSE, CONSUMER];
+
+pub const REPORT_MAP_LEN: usize = report_map_len(REPORTS);
+
+/// The report descriptor for [`REPORTS`], the value of the report map characteristic
+pub const REPORT_MAP: [u8; REPORT_MAP_LEN] = report_map(REPORTS);
+
+/// The HID information characteristic: HID 1.11, no country, normally connectable
+pub const INFORMATION: [u8; 4] = [0x11, 0x01, 0x00, 0x02];
+
+/// The PnP ID characteristic that HID hosts look for alongside the HID service: a vendor ID
+/// from the Bluetooth SIG's company IDs, the test [`COMPANY_ID`], product 1, version 1.0.0
+pub const PNP_ID: [u8; 7] = {
+    let [vendor_lo, vendor_hi] = COMPANY_ID.to_le_bytes();
+    [0x01, vendor_lo, vendor_hi, 0x01, 0x00, 0x00, 0x01]
+};
+
+/// Short item prefixes, the tag and type with the size left as 0
+mod item {
+    pub const INPUT: u8 = 0x80;
+    pub const COLLECTION: u8 = 0xa0;
+    pub const END_COLLECTION: u8 = 0xc0;
+    pub const USAGE_PAGE: u8 = 0x04;
+    pub const LOGICAL_MINIMUM: u8 = 0x14;
+    pub const LOGICAL_MAXIMUM: u8 = 0x24;
+    pub const REPORT_SIZE: u8 = 0x74;
+    pub const REPORT_ID: u8 = 0x84;
+    pub const REPORT_COUNT: u8 = 0x94;
+    pub const USAGE: u8 = 0x08;
+    pub const USAGE_MINIMUM: u8 = 0x18;
+    pub const USAGE_MAXIMUM: u8 = 0x28;
+}
+
+/// Input item flags
+mod input {
+    pub const CONSTANT: i32 = 0x01;
+    pub const VARIABLE: i32 = 0x02;
+    pub const RELATIVE: i32 = 0x04;
+}
+
+const APPLICATION: i32 = 0x01;
+const PHYSICAL: i32 = 0x00;
+
+/// Writes short items into `N` bytes, counting any that don't fit so the length can be found
+/// with `N` = 0
+struct Writer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Writer<N> {
+    const fn byte(mut self, byte: u8) -> Self {
+        if self.len < N {
+            self.bytes[self.len] = byte;
+        }
+        self.len += 1;
+        self
+    }
+
+    /// An item holding `value` in as few bytes as keep its sign
+    const fn item(self, prefix: u8, value: i32) -> Self {
+        if value >= i8::MIN as i32 && value <= i8::MAX as i32 {
+            self.byte(prefix | 1).byte(value as u8)
+        } else {
+            let [low, high] = (value as i16).to_le_bytes();
+            self.byte(prefix | 2).byte(low).byte(high)
+        }
+    }
+
+    /// An item holding an unsigned `value`, such as a usage
+    const fn unsigned(self, prefix: u8, value: u16) -> Self {
+        if value <= u8::MAX as u16 {
+            self.byte(prefix | 1).byte(value as u8)
+        } else {
+            let [low, high] = value.to_le_bytes();
+            self.byte(prefix | 2).byte(low).byte(high)
+        }
+    }
+
+    const fn field(self, field: &Field) -> Self {
+        match *field {
+            Field::Flags { page, first, count } => self
+                .unsigned(item::USAGE_PAGE, page)
+                .unsigned(item::USAGE_MINIMUM, first)
+                .unsigned(item::USAGE_MAXIMUM, first + count as u16 - 1)
+                .item(item::LOGICAL_MINIMUM, 0)
+                .item(item::LOGICAL_MAXIMUM, 1)
+                .item(item::REPORT_SIZE, 1)
+                .item(item::REPORT_COUNT, count as i32)
+                .item(item::INPUT, input::VARIABLE),
+            Field::Slots { page, max, count } => self
+                .unsigned(item::USAGE_PAGE, page)
+                .unsigned(item::USAGE_MINIMUM, 0)
+                .unsigned(item::USAGE_MAXIMUM, max)
+                .item(item::LOGICAL_MINIMUM, 0)
+                .item(item::LOGICAL_MAXIMUM, max as i32)
+                .item(item::REPORT_SIZE, 8)
+                .item(item::REPORT_COUNT, count as i32)
+                .item(item::INPUT, 0),
+            Field::Relative { page, usages } => {
+                let mut writer = self.unsigned(item::USAGE_PAGE, page);
+                let mut i = 0;
+                while i < usages.len() {
+                    writer = writer.unsigned(item::USAGE, usages[i]);
+                    i += 1;
+                }
+                writer
+                    .item(item::LOGICAL_MINIMUM, -127)
+                    .item(item::LOGICAL_MAXIMUM, 127)
+                    .item(item::REPORT_SIZE, 8)
+                    .item(item::REPORT_COUNT, usages.len() as i32)
+                    .item(item::INPUT, input::VARIABLE | input::RELATIVE)
+            }
+            Field::Controls { page, usages } => {
+                let mut writer = self.unsigned(item::USAGE_PAGE, page);
+                let mut i = 0;
+                while i < usages.len() {
+                    writer = writer.unsigned(item::USAGE, usages[i]);
+                    i += 1;
+                }
+                writer
+                    .item(item::LOGICAL_MINIMUM, 0)
+                    .item(item::LOGICAL_MAXIMUM, 1)
+                    .item(item::REPORT_SIZE, 1)
+                    .item(item::REPORT_COUNT, usages.len() as i32)
+                    .item(item::INPUT, input::VARIABLE)
+            }
+            Field::Padding(bits) => self
+                .item(item::REPORT_SIZE, bits as i32)
+                .item(item::REPORT_COUNT, 1)
+                .item(item::INPUT, input::CONSTANT),
+        }
+    }
+
+    const fn report(self, report: &Report) -> Self {
+        let mut writer = self
+            .unsigned(item::USAGE_PAGE, report.page)
+            .unsigned(item::USAGE, report.usage)
+            .item(item::COLLECTION, APPLICATION)
+            .item(item::REPORT_ID, report.id as i32);
+        if let Some(physical) = report.physical {
+            writer = writer
+                .unsigned(item::USAGE, physical)
+                .item(item::COLLECTION, PHYSICAL);
+        }
+        let mut i = 0;
+        while i < report.fields.len() {
+            writer = writer.field(&report.fields[i]);
+            i += 1;
+        }
+        if report.physical.is_some() {
+            writer = writer.byte(item::END_COLLECTION);
+        }
+        writer.byte(item::END_COLLECTION)
+    }
+
+    const fn reports(mut self, reports: &[Report]) -> Self {
+        let mut i = 0;
+        while i < reports.len() {
+            self = self.report(&reports[i]);
+            i += 1;
+        }
+        self
+    }
+}
+
+/// Length of the report descriptor for `reports`
+pub const fn report_map_len(reports: &[Report]) -> usize {
+    Writer::<0> { bytes: [], len: 0 }.reports(reports).len
+}
+
+/// The report descriptor for `reports`, which must be [`report_map_len`] bytes long
+pub const fn report_map<const N: usize>(reports: &[Report]) -> [u8; N] {
+    let writer = Writer::<N> {
+        bytes: [0; N],
+        len: 0,
+    }
+    .reports(reports);
+    assert!(writer.len == N, "report map length");
+    writer.bytes
+}
+
+/// Highest keyboard usage a key slot can hold, Keyboard Application
+pub const KEY_MAX: u8 = 0x65;
+
+/// What a button does in keyboard and mouse mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Binding {
+    Nothing,
+    /// A keyboard usage, 0x04 (a) to [`KEY_MAX`]
+    Key(u8),
+    /// A modifier key, 0 for left control to 7 for right GUI
+    Modifier(u8),
+    /// A mouse button, 0 for the left one to 2 for the middle
+    MouseButton(u8),
+}
+
+/// Key usages for the default bindings and the media remote's slide keys
+pub mod key {
+    pub const ENTER: u8 = 0x28;
+    pub const ESCAPE: u8 = 0x29;
+    pub const SPACE: u8 = 0x2c;
+    pub const PAGE_UP: u8 = 0x4b;
+    pub const PAGE_DOWN: u8 = 0x4e;
+    pub const X: u8 = 0x1b;
+    pub const Z: u8 = 0x1d;
+}
+
+impl Value for Binding {
+    /// One byte: 0 for nothing, a keyboard usage up to [`KEY_MAX`], 0xe0 to 0xe7 for the
+    /// modifiers as in the keyboard usage table, or 0xf0 to 0xf2 for the mouse buttons
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = match *self {
+            Self::Nothing => 0,
+            Self::Key(usage) => usage,
+            Self::Modifier(bit) => 0xe0 + bit,
+            Self::MouseButton(button) => 0xf0 + button,
+        };
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(match exact::<1>(bytes)?[0] {
+            0 => Self::Nothing,
+            usage @ 0x04..=KEY_MAX => Self::Key(usage),
+            code @ 0xe0..=0xe7 => Self::Modifier(code - 0xe0),
+            code @ 0xf0..=0xf2 => Self::MouseButton(code - 0xf0),
+            _ => return Err(DecodeError::OutOfRange),
+        })
+    }
+}
+
+/// What each button does in keyboard and mouse mode, indexed by button from A
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyMap(pub [Binding; 6]);
+
+impl KeyMap {
+    /// A for space and B to click, then enter, escape, Z and X
+    pub const DEFAULT: Self = Self([
+        Binding::Key(key::SPACE),
+        Binding::MouseButton(0),
+        Binding::Key(key::ENTER),
+        Binding::Key(key::ESCAPE),
+        Binding::Key(key::Z),
+        Binding::Key(key::X),
+    ]);
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Value for KeyMap {
+    /// A [`Binding`] for each button
+    const SIZE: usize = 6;
+
+    fn encode(&self, out: &mut [u8]) {
+        for (out, binding) in out.chunks_mut(1).zip(self.0) {
+            binding.encode(out);
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<6>(bytes)?;
+        let mut map = [Binding::Nothing; 6];
+        for (binding, byte) in map.iter_mut().zip(bytes.chunks(1)) {
+            *binding = Binding::decode(byte)?;
+        }
+        Ok(Self(map))
+    }
+}
+
+/// The [`KEYBOARD`] report
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyboardReport {
+    /// A bit for each modifier, left control first
+    pub modifiers: u8,
+    /// The keys held, in the order they were pressed, then zeros
+    pub keys: [u8; 6],
+}
+
+impl Value for KeyboardReport {
+    const SIZE: usize = KEYBOARD.size();
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = self.modifiers;
+        out[1] = 0;
+        out[2..8].copy_from_slice(&self.keys);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<{ Self::SIZE }>(bytes)?;
+        let mut keys = [0; 6];
+        keys.copy_from_slice(&bytes[2..]);
+        Ok(Self {
+            modifiers: bytes[0],
+            keys,
+        })
+    }
+}
+
+/// The [`MOUSE`] report
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MouseReport {
+    /// A bit for each button, left first
+    pub buttons: u8,
+    /// Movement right since the last report
+    pub x: i8,
+    /// Movement down since the last report
+    pub y: i8,
+}
+
+impl Value for MouseReport {
+    const SIZE: usize = MOUSE.size();
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = self.buttons;
+        out[1] = self.x as u8;
+        out[2] = self.y as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let [buttons, x, y] = exact::<{ Self::SIZE }>(bytes)?;
+        if buttons & !0b111 != 0 || x as i8 == i8::MIN || y as i8 == i8::MIN {
+            return Err(DecodeError::OutOfRange);
+        }
+        Ok(Self {
+            buttons,
+            x: x as i8,
+            y: y as i8,
+        })
+    }
+}
+
+/// A media key, in the order of the bits of the [`CONSUMER`] report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum MediaControl {
+    PlayPause = 0,
+    NextTrack = 1,
+    PreviousTrack = 2,
+    FastForward = 3,
+    Rewind = 4,
+    VolumeUp = 5,
+    VolumeDown = 6,
+}
+
+impl MediaControl {
+    /// The consumer usage for each bit
+    pub const USAGES: [u16; 7] = [
+        consumer::PLAY_PAUSE,
+        consumer::SCAN_NEXT_TRACK,
+        consumer::SCAN_PREVIOUS_TRACK,
+        consumer::FAST_FORWARD,
+        consumer::REWIND,
+        consumer::VOLUME_INCREMENT,
+        consumer::VOLUME_DECREMENT,
+    ];
+
+    pub const fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+/// The [`CONSUMER`] report
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConsumerReport {
+    /// A bit for each [`MediaControl`] held
+    pub controls: u8,
+}
+
+impl ConsumerReport {
+    pub const fn with(self, control: MediaControl) -> Self {
+        Self {
+            controls: self.controls | control.bit(),
+        }
+    }
+}
+
+impl Value for ConsumerReport {
+    const SIZE: usize = CONSUMER.size();
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = self.controls;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let [controls] = exact::<{ Self::SIZE }>(bytes)?;
+        if controls & 0x80 != 0 {
+            return Err(DecodeError::OutOfRange);
+        }
+        Ok(Self { controls })
+    }
+}
+
+/// An input report of any kind
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HidReport {
+    Keyboard(KeyboardReport),
+    Mouse(MouseReport),
+    Consumer(ConsumerReport),
+}
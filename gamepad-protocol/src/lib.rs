@@ -0,0 +1,22 @@
+//! The gamepad's GATT protocol, for the firmware and for anything talking to it.
+//!
+//! [`gatt`] lists every service and characteristic with its UUID, and [`value`] says how each
+//! characteristic's value is laid out on the wire, with functions to encode and decode it. With
+//! the `std` feature, [`text`] gives notifications a line of text each for host tools.
+//! [`advertising`] is what a central sees of the gamepad before it connects. [`hid`] is the
+//! keyboard and mouse, or media remote, the gamepad can present itself as instead.
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+pub mod advertising;
+pub mod gatt;
+pub mod hid;
+#[cfg(any(test, feature = "std"))]
+pub mod text;
+pub mod value;
+
+pub use gatt::Uuid;
+pub use value::{
+    AdvertisingPhase, AxisId, ButtonId, ButtonMapping, ConnectionParams, ConnectionStatus, Curve,
+    DecodeError, DeviceName, Gesture, IdleTimeouts, LastError, LinkStatus, Notification,
+    Orientation, Phy, ProfileName, RawInput, RecordingControl, Sample, Setting, Turbo, Value,
+};
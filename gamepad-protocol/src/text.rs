@@ -0,0 +1,216 @@
+//! The text form of a notification, one per line, for host tools to pass notifications between
+//! them and keep them in files.
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! hid.button_a 01
+//! 250 stick.x fe
+//! 260 e3d1afe4-b414-44e3-be54-0ea26c394eba 00
+//! ```
+//!
+//! An optional time in milliseconds, then the characteristic by `service.name` or UUID, then its
+//! value in hex as it went over the air. Times are in milliseconds from the start of the file.
+
+use std::{fmt, time::Duration};
+
+use crate::{
+    gatt::{Characteristic, SERVICES},
+    value::MAX_SIZE,
+    DecodeError, Notification, Uuid,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// When it happened, from the start of the file
+    pub at: Option<Duration>,
+    pub notification: Notification,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(at) = self.at {
+            write!(f, "{} ", at.as_millis())?;
+        }
+        let uuid = self.notification.characteristic();
+        // every notification is on a characteristic in the table
+        let (service, characteristic) = SERVICES
+            .iter()
+            .flat_map(|s| s.characteristics.iter().map(move |c| (s, c)))
+            .find(|(_, c)| c.uuid == uuid)
+            .unwrap();
+        write!(f, "{}.{} ", service.name, characteristic.name)?;
+        let mut bytes = [0; MAX_SIZE];
+        let len = self.notification.encode(&mut bytes);
+        bytes[..len].iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LineError {
+    /// Not `[time] characteristic value`
+    Malformed,
+    UnknownCharacteristic(String),
+    /// The characteristic exists but the gamepad never notifies it
+    NotNotified(&'static str),
+    /// The value isn't an even number of hex digits
+    Hex,
+    Decode(DecodeError),
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => f.write_str("expected `[time] characteristic value`"),
+            Self::UnknownCharacteristic(name) => write!(f, "unknown characteristic {name}"),
+            Self::NotNotified(name) => write!(f, "{name} is not notified"),
+            Self::Hex => f.write_str("value is not hex bytes"),
+            Self::Decode(error) => write!(f, "bad value: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for LineError {}
+
+/// Parse one line, `None` if it is blank or a comment
+pub fn parse(text: &str) -> Result<Option<Line>, LineError> {
+    let text = text.trim();
+    if text.is_empty() || text.starts_with('#') {
+        return Ok(None);
+    }
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let (at, characteristic, value) = match fields[..] {
+        [characteristic, value] => (None, characteristic, value),
+        [at, characteristic, value] => {
+            let ms = at.parse().map_err(|_| LineError::Malformed)?;
+            (Some(Duration::from_millis(ms)), characteristic, value)
+        }
+        _ => return Err(LineError::Malformed),
+    };
+    let characteristic = find(characteristic)?;
+    if !characteristic.properties.notify {
+        return Err(LineError::NotNotified(characteristic.name));
+    }
+    let mut bytes = [0; MAX_SIZE];
+    let len = hex(value, &mut bytes)?;
+    let notification =
+        Notification::decode(characteristic.uuid, &bytes[..len]).map_err(LineError::Decode)?;
+    Ok(Some(Line { at, notification }))
+}
+
+/// A characteristic by `service.name` or by UUID
+fn find(text: &str) -> Result<&'static Characteristic, LineError> {
+    let unknown = || LineError::UnknownCharacteristic(text.to_string());
+    if let Ok(uuid) = text.parse::<Uuid>() {
+        // notified characteristics come first where a UUID is shared
+        return SERVICES
+            .iter()
+            .flat_map(|s| s.characteristics)
+            .find(|c| c.uuid == uuid)
+            .ok_or_else(unknown);
+    }
+    let (service, name) = text.split_once('.').ok_or_else(unknown)?;
+    SERVICES
+        .iter()
+        .filter(|s| s.name == service)
+        .flat_map(|s| s.characteristics)
+        .find(|c| c.name == name)
+        .ok_or_else(unknown)
+}
+
+/// Decode hex digits into `out`, returning how many bytes there were
+fn hex(text: &str, out: &mut [u8]) -> Result<usize, LineError> {
+    let digits = text.as_bytes();
+    if !digits.len().is_multiple_of(2) || digits.len() / 2 > out.len() {
+        return Err(LineError::Hex);
+    }
+    for (byte, pair) in out.iter_mut().zip(digits.chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| LineError::Hex)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| LineError::Hex)?;
+    }
+    Ok(digits.len() / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{gatt, AxisId, ButtonId, Orientation};
+
+    use super::*;
+
+    #[test]
+    fn names_and_uuids() {
+        let pressed = Notification::Button {
+            button: ButtonId::A,
+            pressed: true,
+        };
+        assert_eq!(
+            parse("hid.button_a 01"),
+            Ok(Some(Line {
+                at: None,
+                notification: pressed
+            }))
+        );
+        assert_eq!(
+            parse(&format!("250 {} fe", gatt::stick::X)),
+            Ok(Some(Line {
+                at: Some(Duration::from_millis(250)),
+                notification: Notification::Axis {
+                    axis: AxisId::X,
+                    value: -2
+                }
+            }))
+        );
+    }
+
+    #[test]
+    fn every_notification_has_a_text_form() {
+        let mut bytes = [0; MAX_SIZE];
+        let len = Notification::Orientation(Orientation::IDENTITY).encode(&mut bytes);
+        let text: String = bytes[..len].iter().map(|b| format!("{b:02X}")).collect();
+        assert!(matches!(
+            parse(&format!("motion.orientation {text}")),
+            Ok(Some(Line {
+                notification: Notification::Orientation(_),
+                ..
+            }))
+        ));
+        assert!(parse("heading.heading 6701").is_ok());
+        assert!(parse("gesture.gesture 03").is_ok());
+    }
+
+    #[test]
+    fn formats_what_it_parses() {
+        for text in [
+            "hid.button_f 00",
+            "250 stick.x fe",
+            "1000 recording.length 2c01",
+        ] {
+            assert_eq!(parse(text).unwrap().unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn skips_blanks_and_comments() {
+        assert_eq!(parse(""), Ok(None));
+        assert_eq!(parse("   # hid.button_a 01"), Ok(None));
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert_eq!(parse("hid.button_a"), Err(LineError::Malformed));
+        assert_eq!(parse("soon hid.button_a 01"), Err(LineError::Malformed));
+        assert_eq!(
+            parse("hid.button_g 01"),
+            Err(LineError::UnknownCharacteristic("hid.button_g".into()))
+        );
+        assert_eq!(
+            parse("player.index 01"),
+            Err(LineError::NotNotified("index"))
+        );
+        assert_eq!(parse("hid.button_a 1"), Err(LineError::Hex));
+        assert_eq!(parse("hid.button_a zz"), Err(LineError::Hex));
+        assert_eq!(
+            parse("stick.y 04"),
+            Err(LineError::Decode(DecodeError::OutOfRange))
+        );
+    }
+}
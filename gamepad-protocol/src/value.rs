@@ -0,0 +1,1116 @@
+//! How characteristic values are laid out on the wire.
+//!
+//! Everything is fixed length and multi-byte integers are little endian. A `bool` is one byte,
+//! 0 or 1.
+
+use core::fmt;
+
+use crate::{
+    gatt::{self, Uuid},
+    hid::{HidMode, KeyMap},
+};
+
+/// Longest value of any characteristic
+pub const MAX_SIZE: usize = DeviceName::SIZE;
+
+/// Why some bytes aren't a valid value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    /// Too few or too many bytes
+    Length { expected: usize, found: usize },
+    /// The right length, but not a value this field can take
+    OutOfRange,
+    /// Not a characteristic that holds this kind of value
+    UnknownCharacteristic,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length { expected, found } => {
+                write!(f, "expected {expected} bytes, found {found}")
+            }
+            Self::OutOfRange => f.write_str("value out of range"),
+            Self::UnknownCharacteristic => f.write_str("unknown characteristic"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+/// A characteristic value with a fixed size encoding
+pub trait Value: Sized {
+    /// Encoded length in bytes
+    const SIZE: usize;
+
+    /// Write the value to the start of `out`, which must be at least [`Value::SIZE`] long
+    fn encode(&self, out: &mut [u8]);
+
+    /// Read a value from exactly [`Value::SIZE`] bytes
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// `bytes` as an array of `N`, or a length error
+pub(crate) fn exact<const N: usize>(bytes: &[u8]) -> Result<[u8; N], DecodeError> {
+    bytes.try_into().map_err(|_| DecodeError::Length {
+        expected: N,
+        found: bytes.len(),
+    })
+}
+
+impl Value for bool {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match exact::<1>(bytes)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(DecodeError::OutOfRange),
+        }
+    }
+}
+
+impl Value for u8 {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(exact::<1>(bytes)?[0])
+    }
+}
+
+impl Value for i8 {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(exact::<1>(bytes)?[0] as i8)
+    }
+}
+
+impl Value for u16 {
+    const SIZE: usize = 2;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[..2].copy_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(u16::from_le_bytes(exact(bytes)?))
+    }
+}
+
+/// The six gamepad buttons, A and B are on the micro:bit itself
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ButtonId {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+}
+
+impl ButtonId {
+    pub const ALL: [Self; 6] = [Self::A, Self::B, Self::C, Self::D, Self::E, Self::F];
+
+    /// The button at `index` in [`ButtonId::ALL`]
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    pub fn name(&self) -> char {
+        match self {
+            Self::A => 'A',
+            Self::B => 'B',
+            Self::C => 'C',
+            Self::D => 'D',
+            Self::E => 'E',
+            Self::F => 'F',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AxisId {
+    X,
+    Y,
+}
+
+/// Stick axis levels run from -`AXIS_MAX` to `AXIS_MAX`
+pub const AXIS_MAX: i8 = 3;
+
+/// A discrete motion event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Gesture {
+    Shake = 1,
+    SingleTap = 2,
+    DoubleTap = 3,
+    Freefall = 4,
+    FaceUp = 5,
+    FaceDown = 6,
+}
+
+impl Value for Gesture {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(match exact::<1>(bytes)?[0] {
+            1 => Self::Shake,
+            2 => Self::SingleTap,
+            3 => Self::DoubleTap,
+            4 => Self::Freefall,
+            5 => Self::FaceUp,
+            6 => Self::FaceDown,
+            _ => return Err(DecodeError::OutOfRange),
+        })
+    }
+}
+
+/// A unit quaternion rotating the board into East-North-Up, each component Q1.14 fixed point
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Orientation {
+    pub w: i16,
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+impl Orientation {
+    /// Q1.14 scale, 1.0 is this
+    pub const ONE: i16 = 1 << 14;
+
+    pub const IDENTITY: Self = Self {
+        w: Self::ONE,
+        x: 0,
+        y: 0,
+        z: 0,
+    };
+
+    /// From components in w, x, y, z order
+    pub fn from_q14([w, x, y, z]: [i16; 4]) -> Self {
+        Self { w, x, y, z }
+    }
+
+    /// Components as floats, in w, x, y, z order
+    pub fn to_f32(&self) -> [f32; 4] {
+        [self.w, self.x, self.y, self.z].map(|c| c as f32 / Self::ONE as f32)
+    }
+}
+
+impl Value for Orientation {
+    const SIZE: usize = 8;
+
+    fn encode(&self, out: &mut [u8]) {
+        for (i, c) in [self.w, self.x, self.y, self.z].into_iter().enumerate() {
+            out[2 * i..2 * i + 2].copy_from_slice(&c.to_le_bytes());
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<8>(bytes)?;
+        let c = |i: usize| i16::from_le_bytes([bytes[2 * i], bytes[2 * i + 1]]);
+        Ok(Self {
+            w: c(0),
+            x: c(1),
+            y: c(2),
+            z: c(3),
+        })
+    }
+}
+
+/// The diagnostics record of the most recent error, kept across resets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LastError {
+    /// The error code, 0 if there hasn't been an error
+    pub code: u8,
+    /// How many times in a row this code has been recorded
+    pub count: u16,
+    /// Seconds since boot when it last happened
+    pub uptime_secs: u32,
+}
+
+impl Value for LastError {
+    /// `code`, a reserved zero byte, then `count` and `uptime_secs`
+    const SIZE: usize = 8;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = self.code;
+        out[1] = 0;
+        out[2..4].copy_from_slice(&self.count.to_le_bytes());
+        out[4..8].copy_from_slice(&self.uptime_secs.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<8>(bytes)?;
+        Ok(Self {
+            code: bytes[0],
+            count: u16::from_le_bytes([bytes[2], bytes[3]]),
+            uptime_secs: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        })
+    }
+}
+
+/// How often to advertise during one phase of advertising, and for how long, with the
+/// intervals in the link layer's units
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvertisingPhase {
+    /// Shortest advertising interval, in 0.625 ms steps
+    pub interval_min: u16,
+    /// Longest advertising interval, in 0.625 ms steps
+    pub interval_max: u16,
+    /// Seconds before moving on to the next phase, or to sleep after the last
+    pub timeout_s: u16,
+}
+
+impl AdvertisingPhase {
+    /// From an interval range in microseconds, rounded down
+    pub const fn new(interval_min_us: u32, interval_max_us: u32, timeout_s: u16) -> Self {
+        Self {
+            interval_min: (interval_min_us / 625) as u16,
+            interval_max: (interval_max_us / 625) as u16,
+            timeout_s,
+        }
+    }
+
+    pub const fn interval_min_us(&self) -> u32 {
+        self.interval_min as u32 * 625
+    }
+
+    pub const fn interval_max_us(&self) -> u32 {
+        self.interval_max as u32 * 625
+    }
+}
+
+impl Value for AdvertisingPhase {
+    /// Each field as a little endian `u16`, in order
+    const SIZE: usize = 6;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0..2].copy_from_slice(&self.interval_min.to_le_bytes());
+        out[2..4].copy_from_slice(&self.interval_max.to_le_bytes());
+        out[4..6].copy_from_slice(&self.timeout_s.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<6>(bytes)?;
+        let field = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let phase = Self {
+            interval_min: field(0),
+            interval_max: field(2),
+            timeout_s: field(4),
+        };
+        if phase.interval_min > phase.interval_max {
+            return Err(DecodeError::OutOfRange);
+        }
+        Ok(phase)
+    }
+}
+
+/// How long the inputs may be left alone while a central is connected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IdleTimeouts {
+    /// Seconds before the display dims and the connection relaxes
+    pub dim_after_s: u16,
+    /// Seconds before the gamepad disconnects and goes to sleep, no sooner than it dims
+    pub sleep_after_s: u16,
+}
+
+impl Value for IdleTimeouts {
+    /// `dim_after_s` then `sleep_after_s`, each a little endian `u16`
+    const SIZE: usize = 4;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0..2].copy_from_slice(&self.dim_after_s.to_le_bytes());
+        out[2..4].copy_from_slice(&self.sleep_after_s.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let [dim0, dim1, sleep0, sleep1] = exact::<4>(bytes)?;
+        let timeouts = Self {
+            dim_after_s: u16::from_le_bytes([dim0, dim1]),
+            sleep_after_s: u16::from_le_bytes([sleep0, sleep1]),
+        };
+        if timeouts.dim_after_s > timeouts.sleep_after_s {
+            return Err(DecodeError::OutOfRange);
+        }
+        Ok(timeouts)
+    }
+}
+
+/// Connection parameters, in the link layer's units so they go to the controller unchanged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionParams {
+    /// Shortest connection interval, in 1.25 ms steps
+    pub interval_min: u16,
+    /// Longest connection interval, in 1.25 ms steps
+    pub interval_max: u16,
+    /// Connection events the peripheral may skip when it has nothing to send
+    pub latency: u16,
+    /// How long without a packet before the link is dropped, in 10 ms steps
+    pub supervision_timeout: u16,
+}
+
+impl ConnectionParams {
+    /// From an interval range in microseconds and a timeout in milliseconds, rounded down
+    pub const fn new(
+        interval_min_us: u32,
+        interval_max_us: u32,
+        latency: u16,
+        supervision_timeout_ms: u32,
+    ) -> Self {
+        Self {
+            interval_min: (interval_min_us / 1250) as u16,
+            interval_max: (interval_max_us / 1250) as u16,
+            latency,
+            supervision_timeout: (supervision_timeout_ms / 10) as u16,
+        }
+    }
+
+    pub const fn interval_min_us(&self) -> u32 {
+        self.interval_min as u32 * 1250
+    }
+
+    pub const fn interval_max_us(&self) -> u32 {
+        self.interval_max as u32 * 1250
+    }
+
+    pub const fn supervision_timeout_ms(&self) -> u32 {
+        self.supervision_timeout as u32 * 10
+    }
+}
+
+impl Value for ConnectionParams {
+    /// Each field as a little endian `u16`, in order
+    const SIZE: usize = 8;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0..2].copy_from_slice(&self.interval_min.to_le_bytes());
+        out[2..4].copy_from_slice(&self.interval_max.to_le_bytes());
+        out[4..6].copy_from_slice(&self.latency.to_le_bytes());
+        out[6..8].copy_from_slice(&self.supervision_timeout.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<8>(bytes)?;
+        let field = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let params = Self {
+            interval_min: field(0),
+            interval_max: field(2),
+            latency: field(4),
+            supervision_timeout: field(6),
+        };
+        if params.interval_min > params.interval_max {
+            return Err(DecodeError::OutOfRange);
+        }
+        Ok(params)
+    }
+}
+
+/// The connection parameters last asked of the central and whether the controller accepted
+/// the request, then the ones the link is using. The central picks its own values within the
+/// range, and may change them later without being asked; every change it makes is reflected
+/// here. The values in use are zero until the first change on a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionStatus {
+    pub requested: ConnectionParams,
+    pub accepted: bool,
+    /// Connection interval in use, in 1.25 ms steps
+    pub interval: u16,
+    /// Connection events the peripheral may skip
+    pub latency: u16,
+    /// Supervision timeout in use, in 10 ms steps
+    pub supervision_timeout: u16,
+}
+
+impl ConnectionStatus {
+    pub const fn interval_us(&self) -> u32 {
+        self.interval as u32 * 1250
+    }
+
+    pub const fn supervision_timeout_ms(&self) -> u32 {
+        self.supervision_timeout as u32 * 10
+    }
+}
+
+impl Value for ConnectionStatus {
+    /// `requested`, `accepted`, then the values in use as little endian `u16`s
+    const SIZE: usize = ConnectionParams::SIZE + 7;
+
+    fn encode(&self, out: &mut [u8]) {
+        let (requested, rest) = out.split_at_mut(ConnectionParams::SIZE);
+        self.requested.encode(requested);
+        self.accepted.encode(&mut rest[..1]);
+        self.interval.encode(&mut rest[1..3]);
+        self.latency.encode(&mut rest[3..5]);
+        self.supervision_timeout.encode(&mut rest[5..7]);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<{ Self::SIZE }>(bytes)?;
+        let (requested, rest) = bytes.split_at(ConnectionParams::SIZE);
+        Ok(Self {
+            requested: ConnectionParams::decode(requested)?,
+            accepted: bool::decode(&rest[..1])?,
+            interval: u16::decode(&rest[1..3])?,
+            latency: u16::decode(&rest[3..5])?,
+            supervision_timeout: u16::decode(&rest[5..7])?,
+        })
+    }
+}
+
+/// An LE radio PHY
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Phy {
+    /// 1 Mbit/s, which every central supports
+    Le1M = 1,
+    /// 2 Mbit/s, half the air time for the same packet
+    Le2M = 2,
+    /// Long range, never asked for by the gamepad
+    Coded = 3,
+}
+
+impl Value for Phy {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(match exact::<1>(bytes)?[0] {
+            1 => Self::Le1M,
+            2 => Self::Le2M,
+            3 => Self::Coded,
+            _ => return Err(DecodeError::OutOfRange),
+        })
+    }
+}
+
+/// What the link settled on after the gamepad asked for a larger MTU, longer packets and the
+/// 2M PHY. Anything the central doesn't support stays at its Bluetooth 4.0 default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkStatus {
+    /// ATT MTU, the largest notification is 3 bytes less
+    pub att_mtu: u16,
+    /// Longest link layer payload the gamepad sends in one packet
+    pub max_tx_octets: u16,
+    pub phy: Phy,
+}
+
+impl LinkStatus {
+    /// A link on which nothing has been negotiated
+    pub const DEFAULT: Self = Self {
+        att_mtu: 23,
+        max_tx_octets: 27,
+        phy: Phy::Le1M,
+    };
+
+    /// Whether a notification of `len` bytes goes out whole, in a single link layer packet
+    pub fn fits_one_packet(&self, len: usize) -> bool {
+        // ATT opcode and handle, then the L2CAP header
+        let pdu = len + 3;
+        pdu <= self.att_mtu as usize && pdu + 4 <= self.max_tx_octets as usize
+    }
+}
+
+impl Default for LinkStatus {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Value for LinkStatus {
+    /// `att_mtu`, `max_tx_octets`, then `phy`
+    const SIZE: usize = 5;
+
+    fn encode(&self, out: &mut [u8]) {
+        self.att_mtu.encode(&mut out[..2]);
+        self.max_tx_octets.encode(&mut out[2..4]);
+        self.phy.encode(&mut out[4..]);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<{ Self::SIZE }>(bytes)?;
+        Ok(Self {
+            att_mtu: u16::decode(&bytes[..2])?,
+            max_tx_octets: u16::decode(&bytes[2..4])?,
+            phy: Phy::decode(&bytes[4..])?,
+        })
+    }
+}
+
+/// A raw input, as read from the hardware before any filtering
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RawInput {
+    /// A button pin changed level, `low` while pressed
+    Edge { button: ButtonId, low: bool },
+    /// The stick's SAADC readings, x then y
+    Stick([i16; 2]),
+    /// An accelerometer reading in mg, x, y, z
+    Accel([i16; 3]),
+}
+
+/// A raw input and when it happened, in milliseconds since the recording started
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sample {
+    pub at_ms: u32,
+    pub input: RawInput,
+}
+
+impl Value for Sample {
+    /// `at_ms`, a kind byte, an argument byte and six bytes of data:
+    ///
+    /// | kind | argument | data |
+    /// | --- | --- | --- |
+    /// | 1, edge | button index from 0 for A | 1 if low, 0 if high, then zeros |
+    /// | 2, stick | 0 | x, y as `i16`, then zeros |
+    /// | 3, accel | 0 | x, y, z as `i16` |
+    const SIZE: usize = 12;
+
+    fn encode(&self, out: &mut [u8]) {
+        let out = &mut out[..Self::SIZE];
+        out.fill(0);
+        out[..4].copy_from_slice(&self.at_ms.to_le_bytes());
+        let words: &[i16] = match &self.input {
+            RawInput::Edge { button, low } => {
+                out[4] = 1;
+                out[5] = *button as u8;
+                out[6] = *low as u8;
+                &[]
+            }
+            RawInput::Stick(raw) => {
+                out[4] = 2;
+                raw
+            }
+            RawInput::Accel(accel) => {
+                out[4] = 3;
+                accel
+            }
+        };
+        for (i, word) in words.iter().enumerate() {
+            out[6 + 2 * i..8 + 2 * i].copy_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<12>(bytes)?;
+        let word = |i: usize| i16::from_le_bytes([bytes[6 + 2 * i], bytes[7 + 2 * i]]);
+        let input = match (bytes[4], bytes[5]) {
+            (1, button) => RawInput::Edge {
+                button: *ButtonId::ALL
+                    .get(button as usize)
+                    .ok_or(DecodeError::OutOfRange)?,
+                low: bool::decode(&bytes[6..7])?,
+            },
+            (2, 0) => RawInput::Stick([word(0), word(1)]),
+            (3, 0) => RawInput::Accel([word(0), word(1), word(2)]),
+            _ => return Err(DecodeError::OutOfRange),
+        };
+        Ok(Self {
+            at_ms: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            input,
+        })
+    }
+}
+
+/// What the input recorder is doing, written by the central to change it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum RecordingControl {
+    /// Not recording, what was recorded is kept
+    Stopped = 0,
+    /// Recording, starting over from empty
+    Recording = 1,
+    /// Stopped, and notifying every recorded sample oldest first
+    Downloading = 2,
+}
+
+impl Value for RecordingControl {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(match exact::<1>(bytes)?[0] {
+            0 => Self::Stopped,
+            1 => Self::Recording,
+            2 => Self::Downloading,
+            _ => return Err(DecodeError::OutOfRange),
+        })
+    }
+}
+
+/// How far the stick has to move for each level, from its dead zone out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Curve {
+    /// Every level is the same distance
+    Linear = 0,
+    /// Finer control near the centre
+    Quadratic = 1,
+    /// Finer still, for steering
+    Cubic = 2,
+}
+
+impl Value for Curve {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        Ok(match exact::<1>(bytes)?[0] {
+            0 => Self::Linear,
+            1 => Self::Quadratic,
+            2 => Self::Cubic,
+            _ => return Err(DecodeError::OutOfRange),
+        })
+    }
+}
+
+/// Which button each physical button is reported as, indexed by physical button. Encoded as
+/// six [`ButtonId::ALL`] indices, each used once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ButtonMapping(pub [ButtonId; 6]);
+
+impl ButtonMapping {
+    /// Every button reported as itself
+    pub const IDENTITY: Self = Self(ButtonId::ALL);
+
+    /// What `button` is reported as
+    pub fn get(&self, button: ButtonId) -> ButtonId {
+        self.0[button as usize]
+    }
+}
+
+impl Default for ButtonMapping {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Value for ButtonMapping {
+    const SIZE: usize = 6;
+
+    fn encode(&self, out: &mut [u8]) {
+        for (out, button) in out.iter_mut().zip(self.0) {
+            *out = button as u8;
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<6>(bytes)?;
+        let mut seen = [false; 6];
+        let mut mapping = ButtonId::ALL;
+        for (to, &index) in mapping.iter_mut().zip(&bytes) {
+            let button = ButtonId::from_index(index).ok_or(DecodeError::OutOfRange)?;
+            if core::mem::replace(&mut seen[index as usize], true) {
+                // two buttons reported as one would leave another unreachable
+                return Err(DecodeError::OutOfRange);
+            }
+            *to = button;
+        }
+        Ok(Self(mapping))
+    }
+}
+
+/// Buttons that fire over and over while held, as the buttons they're reported as. Encoded as a
+/// bit for each, A first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Turbo(pub u8);
+
+impl Turbo {
+    pub const NONE: Self = Self(0);
+
+    pub fn contains(&self, button: ButtonId) -> bool {
+        self.0 & 1 << button as u8 != 0
+    }
+}
+
+impl Value for Turbo {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = self.0;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let [bits] = exact::<1>(bytes)?;
+        if bits >> ButtonId::ALL.len() != 0 {
+            return Err(DecodeError::OutOfRange);
+        }
+        Ok(Self(bits))
+    }
+}
+
+/// A controller profile's name, scrolled across the display when switching to it. Printable
+/// ASCII, which is all the display can show, padded out with zeros.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProfileName {
+    len: u8,
+    bytes: [u8; Self::SIZE],
+}
+
+impl ProfileName {
+    /// `None` if `name` is empty, longer than [`Value::SIZE`] bytes or not printable ASCII
+    pub fn new(name: &str) -> Option<Self> {
+        let printable = name.bytes().all(|b| b.is_ascii_graphic() || b == b' ');
+        if name.is_empty() || name.len() > Self::SIZE || !printable {
+            return None;
+        }
+        let mut bytes = [0; Self::SIZE];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Some(Self {
+            len: name.len() as u8,
+            bytes,
+        })
+    }
+
+    /// What profile `index` is called until it's renamed, `P1` for the first
+    pub fn numbered(index: u8) -> Self {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..2].copy_from_slice(&[b'P', b'1' + index % 9]);
+        Self { len: 2, bytes }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only ever built from ASCII
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+}
+
+impl Value for ProfileName {
+    const SIZE: usize = 8;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[..Self::SIZE].copy_from_slice(&self.bytes);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<{ Self::SIZE }>(bytes)?;
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(Self::SIZE);
+        if bytes[len..].iter().any(|&b| b != 0) {
+            return Err(DecodeError::OutOfRange);
+        }
+        let name = core::str::from_utf8(&bytes[..len]).map_err(|_| DecodeError::OutOfRange)?;
+        Self::new(name).ok_or(DecodeError::OutOfRange)
+    }
+}
+
+/// The name the gamepad advertises, UTF-8 padded out with zeros
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceName {
+    len: u8,
+    bytes: [u8; Self::SIZE],
+}
+
+impl DeviceName {
+    /// `None` if `name` is empty, longer than [`Value::SIZE`] bytes or holds a zero
+    pub fn new(name: &str) -> Option<Self> {
+        if name.is_empty() || name.len() > Self::SIZE || name.contains('\0') {
+            return None;
+        }
+        let mut bytes = [0; Self::SIZE];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Some(Self {
+            len: name.len() as u8,
+            bytes,
+        })
+    }
+
+    /// `name` cut down to fit, as [`truncate`] does, if need be
+    pub fn truncated(name: &str) -> Option<Self> {
+        Self::new(truncate(name, Self::SIZE))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only ever built from a `str`
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+}
+
+/// The longest start of `name` that fits in `len` bytes without splitting a character
+pub fn truncate(name: &str, len: usize) -> &str {
+    if name.len() <= len {
+        return name;
+    }
+    let mut end = len;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+impl Value for DeviceName {
+    const SIZE: usize = 20;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[..Self::SIZE].copy_from_slice(&self.bytes);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let bytes = exact::<{ Self::SIZE }>(bytes)?;
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(Self::SIZE);
+        if bytes[len..].iter().any(|&b| b != 0) {
+            return Err(DecodeError::OutOfRange);
+        }
+        let name = core::str::from_utf8(&bytes[..len]).map_err(|_| DecodeError::OutOfRange)?;
+        Self::new(name).ok_or(DecodeError::OutOfRange)
+    }
+}
+
+/// A setting a central writes to the config service, decoded and checked against its range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Setting {
+    Name(DeviceName),
+    Deadzone(u16),
+    Curve(Curve),
+    DebounceMs(u8),
+    ReportRateHz(u8),
+    Brightness(u8),
+    Volume(u8),
+    Mapping(ButtonMapping),
+    HidMode(HidMode),
+    KeyMap(KeyMap),
+    Turbo(Turbo),
+    ProfileName(ProfileName),
+    /// The profile in use, from 0
+    Profile(u8),
+    FastAdvertising(AdvertisingPhase),
+    SlowAdvertising(AdvertisingPhase),
+    IdleTimeouts(IdleTimeouts),
+    BatchRateHz(u8),
+}
+
+impl Setting {
+    /// The characteristic this is written to
+    pub fn characteristic(&self) -> Uuid {
+        match self {
+            Self::Name(_) => gatt::config::NAME,
+            Self::Deadzone(_) => gatt::config::DEADZONE,
+            Self::Curve(_) => gatt::config::CURVE,
+            Self::DebounceMs(_) => gatt::config::DEBOUNCE,
+            Self::ReportRateHz(_) => gatt::config::REPORT_RATE,
+            Self::Brightness(_) => gatt::config::BRIGHTNESS,
+            Self::Volume(_) => gatt::config::VOLUME,
+            Self::Mapping(_) => gatt::config::MAPPING,
+            Self::HidMode(_) => gatt::config::HID_MODE,
+            Self::KeyMap(_) => gatt::config::KEY_MAP,
+            Self::Turbo(_) => gatt::config::TURBO,
+            Self::ProfileName(_) => gatt::config::PROFILE_NAME,
+            Self::Profile(_) => gatt::config::PROFILE,
+            Self::FastAdvertising(_) => gatt::config::FAST_ADVERTISING,
+            Self::SlowAdvertising(_) => gatt::config::SLOW_ADVERTISING,
+            Self::IdleTimeouts(_) => gatt::config::IDLE_TIMEOUTS,
+            Self::BatchRateHz(_) => gatt::config::BATCH_RATE,
+        }
+    }
+
+    /// Write the value to `out`, returning how many bytes it took
+    pub fn encode(&self, out: &mut [u8; MAX_SIZE]) -> usize {
+        match self {
+            Self::Name(name) => encode(name, out),
+            Self::Deadzone(deadzone) => encode(deadzone, out),
+            Self::Curve(curve) => encode(curve, out),
+            Self::DebounceMs(ms) => encode(ms, out),
+            Self::ReportRateHz(hz) => encode(hz, out),
+            Self::Brightness(level) => encode(level, out),
+            Self::Volume(level) => encode(level, out),
+            Self::Mapping(mapping) => encode(mapping, out),
+            Self::HidMode(mode) => encode(mode, out),
+            Self::KeyMap(map) => encode(map, out),
+            Self::Turbo(turbo) => encode(turbo, out),
+            Self::ProfileName(name) => encode(name, out),
+            Self::Profile(index) => encode(index, out),
+            Self::FastAdvertising(phase) | Self::SlowAdvertising(phase) => encode(phase, out),
+            Self::IdleTimeouts(timeouts) => encode(timeouts, out),
+            Self::BatchRateHz(hz) => encode(hz, out),
+        }
+    }
+
+    /// Decode a write of `bytes` to `characteristic`
+    pub fn decode(characteristic: Uuid, bytes: &[u8]) -> Result<Self, DecodeError> {
+        use gatt::config;
+
+        fn within<T: PartialOrd>(
+            value: T,
+            range: core::ops::RangeInclusive<T>,
+        ) -> Result<T, DecodeError> {
+            match range.contains(&value) {
+                true => Ok(value),
+                false => Err(DecodeError::OutOfRange),
+            }
+        }
+        fn phase(bytes: &[u8]) -> Result<AdvertisingPhase, DecodeError> {
+            let phase = AdvertisingPhase::decode(bytes)?;
+            within(phase.interval_min, config::ADVERTISING_INTERVAL_RANGE)?;
+            within(phase.interval_max, config::ADVERTISING_INTERVAL_RANGE)?;
+            within(phase.timeout_s, config::ADVERTISING_TIMEOUT_RANGE)?;
+            Ok(phase)
+        }
+        Ok(match characteristic {
+            config::NAME => Self::Name(DeviceName::decode(bytes)?),
+            config::DEADZONE => {
+                Self::Deadzone(within(u16::decode(bytes)?, config::DEADZONE_RANGE)?)
+            }
+            config::CURVE => Self::Curve(Curve::decode(bytes)?),
+            config::DEBOUNCE => {
+                Self::DebounceMs(within(u8::decode(bytes)?, config::DEBOUNCE_RANGE)?)
+            }
+            config::REPORT_RATE => {
+                Self::ReportRateHz(within(u8::decode(bytes)?, config::REPORT_RATE_RANGE)?)
+            }
+            config::BRIGHTNESS => {
+                Self::Brightness(within(u8::decode(bytes)?, config::LEVEL_RANGE)?)
+            }
+            config::VOLUME => Self::Volume(within(u8::decode(bytes)?, config::LEVEL_RANGE)?),
+            config::MAPPING => Self::Mapping(ButtonMapping::decode(bytes)?),
+            config::HID_MODE => Self::HidMode(HidMode::decode(bytes)?),
+            config::KEY_MAP => Self::KeyMap(KeyMap::decode(bytes)?),
+            config::TURBO => Self::Turbo(Turbo::decode(bytes)?),
+            config::PROFILE_NAME => Self::ProfileName(ProfileName::decode(bytes)?),
+            config::PROFILE => Self::Profile(within(u8::decode(bytes)?, config::PROFILE_RANGE)?),
+            config::FAST_ADVERTISING => Self::FastAdvertising(phase(bytes)?),
+            config::SLOW_ADVERTISING => Self::SlowAdvertising(phase(bytes)?),
+            config::IDLE_TIMEOUTS => {
+                let timeouts = IdleTimeouts::decode(bytes)?;
+                within(timeouts.dim_after_s, config::IDLE_TIMEOUT_RANGE)?;
+                within(timeouts.sleep_after_s, config::IDLE_TIMEOUT_RANGE)?;
+                Self::IdleTimeouts(timeouts)
+            }
+            config::BATCH_RATE => {
+                Self::BatchRateHz(within(u8::decode(bytes)?, config::BATCH_RATE_RANGE)?)
+            }
+            _ => return Err(DecodeError::UnknownCharacteristic),
+        })
+    }
+}
+
+/// A value the gamepad notifies, decoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Notification {
+    Button {
+        button: ButtonId,
+        pressed: bool,
+    },
+    Axis {
+        axis: AxisId,
+        value: i8,
+    },
+    Heading(u16),
+    Gesture(Gesture),
+    Orientation(Orientation),
+    /// How many samples the input recorder holds
+    RecordingLength(u16),
+    RecordingSample(Sample),
+}
+
+impl Notification {
+    /// The characteristic this is notified on
+    pub fn characteristic(&self) -> Uuid {
+        match self {
+            Self::Button { button, .. } => gatt::button_characteristic(*button),
+            Self::Axis { axis, .. } => gatt::axis_characteristic(*axis),
+            Self::Heading(_) => gatt::heading::HEADING,
+            Self::Gesture(_) => gatt::gesture::GESTURE,
+            Self::Orientation(_) => gatt::motion::ORIENTATION,
+            Self::RecordingLength(_) => gatt::recording::LENGTH,
+            Self::RecordingSample(_) => gatt::recording::SAMPLE,
+        }
+    }
+
+    /// Write the value to `out`, returning how many bytes it took
+    pub fn encode(&self, out: &mut [u8; MAX_SIZE]) -> usize {
+        match self {
+            Self::Button { pressed, .. } => encode(pressed, out),
+            Self::Axis { value, .. } => encode(value, out),
+            Self::Heading(heading) => encode(heading, out),
+            Self::Gesture(gesture) => encode(gesture, out),
+            Self::Orientation(orientation) => encode(orientation, out),
+            Self::RecordingLength(length) => encode(length, out),
+            Self::RecordingSample(sample) => encode(sample, out),
+        }
+    }
+
+    /// Decode a notification of `bytes` on `characteristic`
+    pub fn decode(characteristic: Uuid, bytes: &[u8]) -> Result<Self, DecodeError> {
+        if let Some(button) = ButtonId::ALL
+            .into_iter()
+            .find(|b| gatt::button_characteristic(*b) == characteristic)
+        {
+            return Ok(Self::Button {
+                button,
+                pressed: bool::decode(bytes)?,
+            });
+        }
+        // stick x shares its UUID with the player index, which is never notified
+        for axis in [AxisId::X, AxisId::Y] {
+            if gatt::axis_characteristic(axis) == characteristic {
+                let value = i8::decode(bytes)?;
+                if !(-AXIS_MAX..=AXIS_MAX).contains(&value) {
+                    return Err(DecodeError::OutOfRange);
+                }
+                return Ok(Self::Axis { axis, value });
+            }
+        }
+        match characteristic {
+            gatt::heading::HEADING => match u16::decode(bytes)? {
+                heading @ 0..=359 => Ok(Self::Heading(heading)),
+                _ => Err(DecodeError::OutOfRange),
+            },
+            gatt::gesture::GESTURE => Ok(Self::Gesture(Gesture::decode(bytes)?)),
+            gatt::motion::ORIENTATION => Ok(Self::Orientation(Orientation::decode(bytes)?)),
+            gatt::recording::LENGTH => Ok(Self::RecordingLength(u16::decode(bytes)?)),
+            gatt::recording::SAMPLE => Ok(Self::RecordingSample(Sample::decode(bytes)?)),
+            _ => Err(DecodeError::UnknownCharacteristic),
+        }
+    }
+}
+
+fn encode<V: Value>(value: &V, out: &mut [u8; MAX_SIZE]) -> usize {
+    value.encode(out);
+    V::SIZE
+}
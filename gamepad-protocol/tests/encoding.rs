@@ -0,0 +1,655 @@
+//! Every value round trips, and encodes to exactly the bytes clients already depend on.
+
+use gamepad_protocol::{
+    advertising::{self, ad_type, AdvertisingData, Board, Manufacturer, TooLong},
+    gatt::{self, SERVICES},
+    hid::{
+        self, Binding, ConsumerReport, HidMode, KeyMap, KeyboardReport, MediaControl, MouseReport,
+    },
+    value::{truncate, DecodeError, MAX_SIZE},
+    AdvertisingPhase, AxisId, ButtonId, ButtonMapping, ConnectionParams, ConnectionStatus, Curve,
+    DeviceName, Gesture, IdleTimeouts, LastError, LinkStatus, Notification, Orientation, Phy,
+    ProfileName, RawInput, RecordingControl, Sample, Setting, Turbo, Value,
+};
+
+fn bytes<V: Value>(value: &V) -> Vec<u8> {
+    let mut out = [0; MAX_SIZE];
+    value.encode(&mut out);
+    out[..V::SIZE].to_vec()
+}
+
+fn round_trip<V: Value + PartialEq + std::fmt::Debug>(value: V) {
+    assert_eq!(V::decode(&bytes(&value)), Ok(value));
+}
+
+#[test]
+fn primitives() {
+    for value in [false, true] {
+        round_trip(value);
+    }
+    for value in [0u8, 1, 127, 255] {
+        round_trip(value);
+    }
+    for value in [-128i8, -3, 0, 3, 127] {
+        round_trip(value);
+    }
+    for value in [0u16, 1, 359, 0xffff] {
+        round_trip(value);
+    }
+}
+
+#[test]
+fn primitive_golden_bytes() {
+    assert_eq!(bytes(&true), [0x01]);
+    assert_eq!(bytes(&false), [0x00]);
+    assert_eq!(bytes(&-3i8), [0xfd]);
+    assert_eq!(bytes(&359u16), [0x67, 0x01]);
+}
+
+#[test]
+fn bool_is_strict() {
+    assert_eq!(bool::decode(&[2]), Err(DecodeError::OutOfRange));
+    assert_eq!(
+        bool::decode(&[]),
+        Err(DecodeError::Length {
+            expected: 1,
+            found: 0
+        })
+    );
+}
+
+#[test]
+fn gestures() {
+    for (gesture, code) in [
+        (Gesture::Shake, 1),
+        (Gesture::SingleTap, 2),
+        (Gesture::DoubleTap, 3),
+        (Gesture::Freefall, 4),
+        (Gesture::FaceUp, 5),
+        (Gesture::FaceDown, 6),
+    ] {
+        assert_eq!(bytes(&gesture), [code]);
+        round_trip(gesture);
+    }
+    assert_eq!(Gesture::decode(&[0]), Err(DecodeError::OutOfRange));
+    assert_eq!(Gesture::decode(&[7]), Err(DecodeError::OutOfRange));
+}
+
+#[test]
+fn orientation() {
+    assert_eq!(
+        bytes(&Orientation::IDENTITY),
+        [0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+    );
+    let turned = Orientation::from_q14([11585, 0, 0, -11585]);
+    assert_eq!(
+        bytes(&turned),
+        [0x41, 0x2d, 0x00, 0x00, 0x00, 0x00, 0xbf, 0xd2]
+    );
+    round_trip(turned);
+    let [w, _, _, z] = turned.to_f32();
+    let half_turn = std::f32::consts::FRAC_1_SQRT_2;
+    assert!((w - half_turn).abs() < 1e-3 && (z + half_turn).abs() < 1e-3);
+    assert!(matches!(
+        Orientation::decode(&[0; 7]),
+        Err(DecodeError::Length {
+            expected: 8,
+            found: 7
+        })
+    ));
+}
+
+#[test]
+fn last_error() {
+    let record = LastError {
+        code: 3,
+        count: 3,
+        uptime_secs: 0x0102_0304,
+    };
+    assert_eq!(bytes(&record), [3, 0, 3, 0, 4, 3, 2, 1]);
+    round_trip(record);
+    assert_eq!(LastError::decode(&[0; 8]).unwrap().code, 0);
+}
+
+#[test]
+fn connection_status() {
+    let requested = ConnectionParams::new(7_500, 15_000, 0, 4_000);
+    assert_eq!(
+        requested,
+        ConnectionParams {
+            interval_min: 6,
+            interval_max: 12,
+            latency: 0,
+            supervision_timeout: 400,
+        }
+    );
+    assert_eq!(requested.interval_max_us(), 15_000);
+    assert_eq!(requested.supervision_timeout_ms(), 4_000);
+    // the central settled on 11.25 ms and later a 5 s timeout
+    let status = ConnectionStatus {
+        requested,
+        accepted: true,
+        interval: 9,
+        latency: 0,
+        supervision_timeout: 500,
+    };
+    assert_eq!(status.interval_us(), 11_250);
+    assert_eq!(status.supervision_timeout_ms(), 5_000);
+    assert_eq!(
+        bytes(&status),
+        [6, 0, 12, 0, 0, 0, 0x90, 0x01, 1, 9, 0, 0, 0, 0xf4, 0x01]
+    );
+    round_trip(status);
+    // the range is backwards
+    assert_eq!(
+        ConnectionParams::decode(&[12, 0, 6, 0, 0, 0, 0x90, 0x01]),
+        Err(DecodeError::OutOfRange)
+    );
+}
+
+#[test]
+fn advertising_phase() {
+    let fast = AdvertisingPhase::new(20_000, 30_000, 30);
+    assert_eq!(
+        fast,
+        AdvertisingPhase {
+            interval_min: 32,
+            interval_max: 48,
+            timeout_s: 30,
+        }
+    );
+    assert_eq!(fast.interval_max_us(), 30_000);
+    assert_eq!(bytes(&fast), [32, 0, 48, 0, 30, 0]);
+    round_trip(fast);
+    assert_eq!(
+        AdvertisingPhase::decode(&[48, 0, 32, 0, 30, 0]),
+        Err(DecodeError::OutOfRange)
+    );
+}
+
+#[test]
+fn link_status() {
+    let negotiated = LinkStatus {
+        att_mtu: 247,
+        max_tx_octets: 251,
+        phy: Phy::Le2M,
+    };
+    assert_eq!(bytes(&negotiated), [247, 0, 251, 0, 2]);
+    round_trip(negotiated);
+    assert_eq!(
+        LinkStatus::decode(&[23, 0, 27, 0, 1]),
+        Ok(LinkStatus::DEFAULT)
+    );
+    assert_eq!(
+        LinkStatus::decode(&[23, 0, 27, 0, 4]),
+        Err(DecodeError::OutOfRange)
+    );
+    // 20 bytes is the most a Bluetooth 4.0 link carries in one packet
+    assert!(LinkStatus::DEFAULT.fits_one_packet(20));
+    assert!(!LinkStatus::DEFAULT.fits_one_packet(21));
+    assert!(negotiated.fits_one_packet(244));
+    assert!(!negotiated.fits_one_packet(245));
+    // a big MTU without longer packets still splits across several
+    let fragmented = LinkStatus {
+        max_tx_octets: 27,
+        ..negotiated
+    };
+    assert!(!fragmented.fits_one_packet(21));
+}
+
+#[test]
+fn samples() {
+    let edge = Sample {
+        at_ms: 0x0102_0304,
+        input: RawInput::Edge {
+            button: ButtonId::D,
+            low: true,
+        },
+    };
+    assert_eq!(bytes(&edge), [4, 3, 2, 1, 1, 3, 1, 0, 0, 0, 0, 0]);
+    let stick = Sample {
+        at_ms: 20,
+        input: RawInput::Stick([1870, -1]),
+    };
+    assert_eq!(
+        bytes(&stick),
+        [20, 0, 0, 0, 2, 0, 0x4e, 0x07, 0xff, 0xff, 0, 0]
+    );
+    let accel = Sample {
+        at_ms: 40,
+        input: RawInput::Accel([-1000, 0, 1000]),
+    };
+    assert_eq!(
+        bytes(&accel),
+        [40, 0, 0, 0, 3, 0, 0x18, 0xfc, 0, 0, 0xe8, 0x03]
+    );
+    for sample in [edge, stick, accel] {
+        round_trip(sample);
+    }
+    // no button G, and no such kind
+    let mut bad = bytes(&edge);
+    bad[5] = 6;
+    assert_eq!(Sample::decode(&bad), Err(DecodeError::OutOfRange));
+    bad[4] = 4;
+    assert_eq!(Sample::decode(&bad), Err(DecodeError::OutOfRange));
+}
+
+#[test]
+fn recording_control() {
+    for (control, code) in [
+        (RecordingControl::Stopped, 0),
+        (RecordingControl::Recording, 1),
+        (RecordingControl::Downloading, 2),
+    ] {
+        assert_eq!(bytes(&control), [code]);
+        round_trip(control);
+    }
+    assert_eq!(RecordingControl::decode(&[3]), Err(DecodeError::OutOfRange));
+}
+
+#[test]
+fn device_names() {
+    let name = DeviceName::new("Pad ☃").unwrap();
+    assert_eq!(name.as_str(), "Pad ☃");
+    round_trip(name);
+    let mut expected = b"Pad \xe2\x98\x83".to_vec();
+    expected.resize(DeviceName::SIZE, 0);
+    assert_eq!(bytes(&name), expected);
+    round_trip(DeviceName::new(&"x".repeat(DeviceName::SIZE)).unwrap());
+
+    assert_eq!(DeviceName::new(""), None);
+    assert_eq!(DeviceName::new(&"x".repeat(DeviceName::SIZE + 1)), None);
+    let mut invalid = [0; DeviceName::SIZE];
+    assert_eq!(DeviceName::decode(&invalid), Err(DecodeError::OutOfRange));
+    invalid[..3].copy_from_slice(&[b'a', 0xe2, 0x98]);
+    assert_eq!(DeviceName::decode(&invalid), Err(DecodeError::OutOfRange));
+    // nothing after the padding starts
+    invalid[..3].copy_from_slice(b"a\0b");
+    assert_eq!(DeviceName::decode(&invalid), Err(DecodeError::OutOfRange));
+}
+
+#[test]
+fn profile_names() {
+    let name = ProfileName::new("FPS 2").unwrap();
+    assert_eq!(bytes(&name), b"FPS 2\0\0\0");
+    round_trip(name);
+    assert_eq!(ProfileName::numbered(0).as_str(), "P1");
+    assert_eq!(ProfileName::numbered(3).as_str(), "P4");
+    assert_eq!(ProfileName::new("Pad ☃"), None);
+    assert_eq!(ProfileName::new("tab\t"), None);
+    assert_eq!(ProfileName::new(&"x".repeat(ProfileName::SIZE + 1)), None);
+}
+
+#[test]
+fn turbo_buttons() {
+    let turbo = Turbo(0b00_0101);
+    assert!(turbo.contains(ButtonId::A));
+    assert!(!turbo.contains(ButtonId::B));
+    assert!(turbo.contains(ButtonId::C));
+    round_trip(turbo);
+    assert_eq!(Turbo::decode(&[0x40]), Err(DecodeError::OutOfRange));
+}
+
+#[test]
+fn names_are_truncated_between_characters() {
+    assert_eq!(truncate("Pad", 22), "Pad");
+    assert_eq!(truncate("Pad ☃", 7), "Pad ☃");
+    // the snowman is three bytes, so any cut inside it drops all of it
+    assert_eq!(truncate("Pad ☃", 6), "Pad ");
+    assert_eq!(truncate("Pad ☃", 5), "Pad ");
+    assert_eq!(truncate("☃", 2), "");
+    let long = "Gamepad ☃☃☃☃☃☃☃☃";
+    assert_eq!(
+        DeviceName::truncated(long).unwrap().as_str(),
+        "Gamepad ☃☃☃☃"
+    );
+    assert_eq!(DeviceName::truncated(""), None);
+}
+
+#[test]
+fn button_mappings() {
+    round_trip(ButtonMapping::IDENTITY);
+    assert_eq!(bytes(&ButtonMapping::IDENTITY), [0, 1, 2, 3, 4, 5]);
+    let swapped = ButtonMapping::decode(&[1, 0, 2, 3, 4, 5]).unwrap();
+    assert_eq!(swapped.get(ButtonId::A), ButtonId::B);
+    assert_eq!(swapped.get(ButtonId::C), ButtonId::C);
+    for invalid in [[0, 0, 2, 3, 4, 5], [0, 1, 2, 3, 4, 6]] {
+        assert_eq!(
+            ButtonMapping::decode(&invalid),
+            Err(DecodeError::OutOfRange)
+        );
+    }
+}
+
+#[test]
+fn settings() {
+    let settings = [
+        Setting::Name(DeviceName::new("Gamepad 2").unwrap()),
+        Setting::Deadzone(120),
+        Setting::Curve(Curve::Cubic),
+        Setting::DebounceMs(20),
+        Setting::ReportRateHz(100),
+        Setting::Brightness(0),
+        Setting::Volume(10),
+        Setting::Mapping(ButtonMapping::IDENTITY),
+        Setting::HidMode(HidMode::KeyboardMouse),
+        Setting::KeyMap(KeyMap::DEFAULT),
+        Setting::Turbo(Turbo(0b10_0001)),
+        Setting::ProfileName(ProfileName::new("RACING").unwrap()),
+        Setting::Profile(3),
+        Setting::FastAdvertising(AdvertisingPhase::new(20_000, 30_000, 30)),
+        Setting::SlowAdvertising(AdvertisingPhase::new(1_022_500, 1_022_500, 300)),
+        Setting::IdleTimeouts(IdleTimeouts {
+            dim_after_s: 60,
+            sleep_after_s: 600,
+        }),
+        Setting::BatchRateHz(133),
+    ];
+    let config = gatt::service(gatt::config::SERVICE).unwrap();
+    for setting in settings {
+        let characteristic = config.characteristic(setting.characteristic()).unwrap();
+        let mut out = [0; MAX_SIZE];
+        let len = setting.encode(&mut out);
+        assert_eq!(len, characteristic.size, "{}", characteristic.name);
+        assert_eq!(
+            Setting::decode(characteristic.uuid, &out[..len]),
+            Ok(setting)
+        );
+    }
+    assert_eq!(bytes(&Curve::Quadratic), [1]);
+}
+
+#[test]
+fn rejects_settings_out_of_range() {
+    use gatt::config;
+
+    for (characteristic, bytes) in [
+        (config::DEADZONE, &[0xa8, 0x03][..]),
+        (config::CURVE, &[3]),
+        (config::DEBOUNCE, &[4]),
+        (config::DEBOUNCE, &[101]),
+        (config::REPORT_RATE, &[9]),
+        (config::BRIGHTNESS, &[11]),
+        (config::VOLUME, &[255]),
+        (config::HID_MODE, &[3]),
+        (config::KEY_MAP, &[0x2c, 0, 0, 0, 0, 0x66]),
+        (config::TURBO, &[0b100_0000]),
+        (config::PROFILE_NAME, &[0; 8]),
+        (config::PROFILE_NAME, b"P1\0\0\0\0\0\n"),
+        (config::PROFILE, &[4]),
+        // faster than the link layer allows, min above max, and no time at all
+        (config::FAST_ADVERTISING, &[0x1f, 0, 0x30, 0, 30, 0]),
+        (config::FAST_ADVERTISING, &[0x30, 0, 0x20, 0, 30, 0]),
+        (config::SLOW_ADVERTISING, &[0x00, 0x40, 0x01, 0x40, 30, 0]),
+        (config::SLOW_ADVERTISING, &[0x20, 0, 0x30, 0, 0, 0]),
+        // sleeping before dimming, and never dimming
+        (config::IDLE_TIMEOUTS, &[60, 0, 30, 0]),
+        (config::IDLE_TIMEOUTS, &[0, 0, 30, 0]),
+        (config::BATCH_RATE, &[9]),
+        (config::BATCH_RATE, &[134]),
+    ] {
+        assert_eq!(
+            Setting::decode(characteristic, bytes),
+            Err(DecodeError::OutOfRange),
+            "{characteristic} {bytes:?}"
+        );
+    }
+    assert_eq!(
+        Setting::decode(gatt::heading::HEADING, &[0, 0]),
+        Err(DecodeError::UnknownCharacteristic)
+    );
+}
+
+#[test]
+fn report_map_describes_the_reports() {
+    #[rustfmt::skip]
+    let expected = [
+        0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0x85, 0x01, // keyboard, report 1
+        0x05, 0x07, 0x19, 0xe0, 0x29, 0xe7, 0x15, 0x00, 0x25, 0x01, // modifiers
+        0x75, 0x01, 0x95, 0x08, 0x81, 0x02,
+        0x75, 0x08, 0x95, 0x01, 0x81, 0x01, // reserved byte
+        0x05, 0x07, 0x19, 0x00, 0x29, 0x65, 0x15, 0x00, 0x25, 0x65, // keys
+        0x75, 0x08, 0x95, 0x06, 0x81, 0x00,
+        0xc0,
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x85, 0x02, // mouse, report 2
+        0x09, 0x01, 0xa1, 0x00, // pointer
+        0x05, 0x09, 0x19, 0x01, 0x29, 0x03, 0x15, 0x00, 0x25, 0x01, // buttons
+        0x75, 0x01, 0x95, 0x03, 0x81, 0x02,
+        0x75, 0x05, 0x95, 0x01, 0x81, 0x01, // padding
+        0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x15, 0x81, 0x25, 0x7f, // x, y
+        0x75, 0x08, 0x95, 0x02, 0x81, 0x06,
+        0xc0, 0xc0,
+        0x05, 0x0c, 0x09, 0x01, 0xa1, 0x01, 0x85, 0x03, // consumer control, report 3
+        0x05, 0x0c, 0x09, 0xcd, 0x09, 0xb5, 0x09, 0xb6, 0x09, 0xb3, // media keys
+        0x09, 0xb4, 0x09, 0xe9, 0x09, 0xea, 0x15, 0x00, 0x25, 0x01,
+        0x75, 0x01, 0x95, 0x07, 0x81, 0x02,
+        0x75, 0x01, 0x95, 0x01, 0x81, 0x01, // padding
+        0xc0,
+    ];
+    assert_eq!(hid::REPORT_MAP, expected);
+    assert_eq!(hid::KEYBOARD.size(), KeyboardReport::SIZE);
+    assert_eq!(hid::MOUSE.size(), MouseReport::SIZE);
+    assert_eq!(hid::CONSUMER.size(), ConsumerReport::SIZE);
+    // Bluetooth SIG vendor 0xffff, product 1, version 1.0.0
+    assert_eq!(hid::PNP_ID, [0x01, 0xff, 0xff, 0x01, 0x00, 0x00, 0x01]);
+}
+
+#[test]
+fn hid_reports() {
+    let keyboard = KeyboardReport {
+        modifiers: 0b10,
+        keys: [0x2c, 0x28, 0, 0, 0, 0],
+    };
+    assert_eq!(bytes(&keyboard), [0x02, 0, 0x2c, 0x28, 0, 0, 0, 0]);
+    round_trip(keyboard);
+    let mouse = MouseReport {
+        buttons: 0b001,
+        x: -4,
+        y: 127,
+    };
+    assert_eq!(bytes(&mouse), [0x01, 0xfc, 0x7f]);
+    round_trip(mouse);
+    assert_eq!(
+        MouseReport::decode(&[0, 0x80, 0]),
+        Err(DecodeError::OutOfRange)
+    );
+    let consumer = ConsumerReport::default()
+        .with(MediaControl::PlayPause)
+        .with(MediaControl::VolumeDown);
+    assert_eq!(bytes(&consumer), [0b100_0001]);
+    round_trip(consumer);
+    assert_eq!(
+        ConsumerReport::decode(&[0x80]),
+        Err(DecodeError::OutOfRange)
+    );
+}
+
+#[test]
+fn key_maps() {
+    round_trip(KeyMap::DEFAULT);
+    let map = KeyMap([
+        Binding::Nothing,
+        Binding::Key(0x04),
+        Binding::Modifier(1),
+        Binding::MouseButton(2),
+        Binding::Key(hid::KEY_MAX),
+        Binding::Modifier(7),
+    ]);
+    assert_eq!(bytes(&map), [0, 0x04, 0xe1, 0xf2, 0x65, 0xe7]);
+    round_trip(map);
+    for invalid in [0x01, 0x66, 0xe8, 0xf3] {
+        assert_eq!(Binding::decode(&[invalid]), Err(DecodeError::OutOfRange));
+    }
+    round_trip(HidMode::KeyboardMouse);
+    round_trip(HidMode::MediaRemote);
+    assert_eq!(HidMode::decode(&[3]), Err(DecodeError::OutOfRange));
+    assert_eq!(HidMode::Gamepad.next().next().next(), HidMode::Gamepad);
+}
+
+fn notifications() -> Vec<Notification> {
+    let mut all: Vec<Notification> = ButtonId::ALL
+        .into_iter()
+        .flat_map(|button| [false, true].map(|pressed| Notification::Button { button, pressed }))
+        .collect();
+    for axis in [AxisId::X, AxisId::Y] {
+        all.extend((-3..=3).map(|value| Notification::Axis { axis, value }));
+    }
+    all.extend([
+        Notification::Heading(0),
+        Notification::Heading(359),
+        Notification::Gesture(Gesture::DoubleTap),
+        Notification::Orientation(Orientation::IDENTITY),
+        Notification::RecordingLength(1024),
+        Notification::RecordingSample(Sample {
+            at_ms: 9_999,
+            input: RawInput::Stick([0, 3740]),
+        }),
+    ]);
+    all
+}
+
+#[test]
+fn notifications_round_trip() {
+    for notification in notifications() {
+        let mut out = [0; MAX_SIZE];
+        let len = notification.encode(&mut out);
+        let characteristic = notification.characteristic();
+        assert_eq!(
+            Notification::decode(characteristic, &out[..len]),
+            Ok(notification)
+        );
+        // and it is sent on a characteristic in the table, with the size given there
+        let described = SERVICES
+            .iter()
+            .flat_map(|s| s.characteristics)
+            .find(|c| c.uuid == characteristic)
+            .unwrap();
+        assert!(described.properties.notify);
+        assert_eq!(described.size, len);
+    }
+}
+
+#[test]
+fn notification_golden_bytes() {
+    let mut out = [0; MAX_SIZE];
+    let button = Notification::Button {
+        button: ButtonId::C,
+        pressed: true,
+    };
+    assert_eq!(button.characteristic(), gatt::button::C);
+    let len = button.encode(&mut out);
+    assert_eq!(out[..len], [0x01]);
+    let axis = Notification::Axis {
+        axis: AxisId::Y,
+        value: -2,
+    };
+    assert_eq!(axis.characteristic(), gatt::stick::Y);
+    let len = axis.encode(&mut out);
+    assert_eq!(out[..len], [0xfe]);
+}
+
+#[test]
+fn rejects_invalid_notifications() {
+    assert_eq!(
+        Notification::decode(gatt::stick::X, &[4]),
+        Err(DecodeError::OutOfRange)
+    );
+    assert_eq!(
+        Notification::decode(gatt::heading::HEADING, &360u16.to_le_bytes()),
+        Err(DecodeError::OutOfRange)
+    );
+    assert_eq!(
+        Notification::decode(gatt::motion::RATE, &[10]),
+        Err(DecodeError::UnknownCharacteristic)
+    );
+}
+
+#[test]
+fn advertisement_fits_with_room_to_spare() {
+    let manufacturer = Manufacturer {
+        board: Board::MicrobitV2,
+        player: 2,
+        battery: None,
+    };
+    let adv = advertising::advertisement(HidMode::Gamepad, 0, &manufacturer).unwrap();
+    assert_eq!(
+        adv.as_bytes(),
+        [
+            2, 0x01, 0x06, // flags
+            3, 0x19, 0xc4, 0x03, // gamepad appearance
+            2, 0x0a, 0x00, // 0 dBm
+            6, 0xff, 0xff, 0xff, 1, 2, 0xff, // manufacturer data
+        ]
+    );
+    let found = AdvertisingData::find(adv.as_bytes(), ad_type::MANUFACTURER_DATA).unwrap();
+    assert_eq!(found[..2], advertising::COMPANY_ID.to_le_bytes());
+    assert_eq!(Manufacturer::decode(&found[2..]), Ok(manufacturer));
+    assert_eq!(
+        AdvertisingData::find(adv.as_bytes(), ad_type::COMPLETE_LOCAL_NAME),
+        None
+    );
+    assert_eq!(
+        AdvertisingData::find(adv.as_bytes(), ad_type::COMPLETE_SERVICE_UUIDS_16),
+        None
+    );
+    let keyboard = advertising::advertisement(HidMode::KeyboardMouse, 0, &manufacturer).unwrap();
+    assert_eq!(
+        AdvertisingData::find(keyboard.as_bytes(), ad_type::APPEARANCE),
+        Some(&advertising::APPEARANCE_KEYBOARD.to_le_bytes()[..])
+    );
+    let remote = advertising::advertisement(HidMode::MediaRemote, 0, &manufacturer).unwrap();
+    assert_eq!(
+        AdvertisingData::find(remote.as_bytes(), ad_type::APPEARANCE),
+        Some(&advertising::APPEARANCE_REMOTE_CONTROL.to_le_bytes()[..])
+    );
+    for hid in [keyboard, remote] {
+        assert_eq!(
+            AdvertisingData::find(hid.as_bytes(), ad_type::COMPLETE_SERVICE_UUIDS_16),
+            Some(&advertising::HID_SERVICE.to_le_bytes()[..])
+        );
+    }
+
+    let longest = "x".repeat(DeviceName::SIZE);
+    let scan = advertising::scan_response(&longest).unwrap();
+    assert_eq!(
+        AdvertisingData::find(scan.as_bytes(), ad_type::COMPLETE_LOCAL_NAME),
+        Some(longest.as_bytes())
+    );
+}
+
+#[test]
+fn advertising_data_that_does_not_fit_is_refused() {
+    let name = "x".repeat(advertising::MAX_LEN - 2);
+    assert!(advertising::scan_response(&name).is_ok());
+    let name = "x".repeat(advertising::MAX_LEN - 1);
+    assert_eq!(
+        advertising::scan_response(&name),
+        Err(TooLong {
+            ad_type: ad_type::COMPLETE_LOCAL_NAME,
+            needed: advertising::MAX_LEN + 1,
+            left: advertising::MAX_LEN,
+        })
+    );
+    let adv = AdvertisingData::new().flags(advertising::FLAGS).unwrap();
+    assert_eq!(
+        adv.complete_name(&"x".repeat(28)).unwrap_err().left,
+        advertising::MAX_LEN - 3
+    );
+}
+
+#[test]
+fn manufacturer_data() {
+    round_trip(Manufacturer {
+        board: Board::MicrobitV2,
+        player: 0,
+        battery: Some(100),
+    });
+    assert_eq!(
+        Manufacturer::decode(&[0, 0, 0xff]),
+        Err(DecodeError::OutOfRange)
+    );
+    assert_eq!(
+        Manufacturer::decode(&[1, 0, 101]),
+        Err(DecodeError::OutOfRange)
+    );
+}
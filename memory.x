@@ -1,6 +1,8 @@
 MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
-  FLASH : ORIGIN = 0x00000000, LENGTH = 512K
+  FLASH : ORIGIN = 0x00000000, LENGTH = 496K
+  /* The last four pages hold the config store, see src/io/flash.rs */
+  CONFIG : ORIGIN = 0x0007C000, LENGTH = 16K
   RAM : ORIGIN = 0x20000000, LENGTH = 128K
 }
@@ -0,0 +1,134 @@
+//! Replays a recording downloaded from the gamepad through the firmware's input filters, printing
+//! what the central would have been sent.
+//!
+//! The recording is a file of `recording.sample` notifications in the text form from
+//! `gamepad_protocol::text`. The output is in the same form, so it can be diffed against what a
+//! user's central logged or played into `gamepad-bridge`.
+
+use std::{
+    fs,
+    io::{self, Write},
+    process::ExitCode,
+    time::Duration,
+};
+
+use gamepad_core::recording::replay;
+use gamepad_protocol::{
+    text::{self, Line},
+    Notification, RawInput, Sample,
+};
+
+const USAGE: &str = "usage: gamepad-replay RECORDING [--raw]
+
+  --raw  show each raw sample as a comment before what it led to";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (path, raw) = match &args[..] {
+        [path] => (path, false),
+        [path, flag] if flag == "--raw" => (path, true),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(path, raw) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("gamepad-replay: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(path: &str, raw: bool) -> io::Result<()> {
+    let samples = read(&fs::read_to_string(path)?, path);
+    let mut out = io::stdout().lock();
+    let mut result = Ok(());
+    let mut shown = 0;
+    replay(samples.iter().copied(), |at_ms, notification| {
+        if result.is_err() {
+            return;
+        }
+        result = (|| {
+            if raw {
+                // the samples up to this one, which is what caused it
+                for sample in samples[shown..].iter().take_while(|s| s.at_ms <= at_ms) {
+                    writeln!(out, "# {}", describe(sample))?;
+                    shown += 1;
+                }
+            }
+            let line = Line {
+                at: Some(Duration::from_millis(at_ms.into())),
+                notification,
+            };
+            writeln!(out, "{line}")
+        })();
+    });
+    result
+}
+
+/// The recorded samples in a file, reporting lines that aren't samples or are out of order
+fn read(text: &str, name: &str) -> Vec<Sample> {
+    let mut samples: Vec<Sample> = Vec::new();
+    let mut expected = None;
+    for (number, line) in text.lines().enumerate() {
+        match text::parse(line) {
+            Ok(Some(Line {
+                notification: Notification::RecordingSample(sample),
+                ..
+            })) => {
+                if samples.last().is_some_and(|last| last.at_ms > sample.at_ms) {
+                    eprintln!("{name}:{}: sample out of order", number + 1);
+                }
+                samples.push(sample);
+            }
+            Ok(Some(Line {
+                notification: Notification::RecordingLength(length),
+                ..
+            })) => expected = Some(length as usize),
+            Ok(Some(_)) => eprintln!("{name}:{}: not a recording sample", number + 1),
+            Ok(None) => {}
+            Err(error) => eprintln!("{name}:{}: {error}", number + 1),
+        }
+    }
+    if let Some(expected) = expected.filter(|expected| *expected != samples.len()) {
+        eprintln!(
+            "{name}: the recording held {expected} samples but {} were downloaded",
+            samples.len()
+        );
+    }
+    samples
+}
+
+fn describe(sample: &Sample) -> String {
+    let at = sample.at_ms;
+    match sample.input {
+        RawInput::Edge { button, low } => {
+            let level = if low { "low" } else { "high" };
+            format!("{at} button {} {level}", button.name())
+        }
+        RawInput::Stick([x, y]) => format!("{at} stick {x} {y}"),
+        RawInput::Accel([x, y, z]) => format!("{at} accel {x} {y} {z}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_samples_and_skips_the_rest() {
+        let text = "\
+            recording.length 0200\n\
+            # a comment\n\
+            recording.sample 640000000100010000000000\n\
+            hid.button_a 01\n\
+            recording.sample c80000000100000000000000\n";
+        let samples = read(text, "test");
+        assert_eq!(
+            samples.iter().map(describe).collect::<Vec<_>>(),
+            ["100 button A low", "200 button A high"]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! The simulated board's outputs: the same display and audio action queues the firmware
+//! drains into the LED matrix and speaker, drained into the terminal instead.
+
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Timer};
+use gamepad_core::{
+    audio::{AudioAction, Note},
+    display::{Bitmap, DisplayAction},
+    hal::{Display5x5, HidOutput, Link, ReportSink, ToneOutput},
+    hid::HidReport,
+    input::{AxisId, ButtonId, Report},
+    scheduler::ReportScheduler,
+};
+use gamepad_protocol::ConnectionParams;
+
+use crate::terminal;
+
+pub static DISPLAY_CHANNEL: Channel<CriticalSectionRawMutex, DisplayAction, 64> = Channel::new();
+pub static AUDIO_CHANNEL: Channel<CriticalSectionRawMutex, AudioAction, 64> = Channel::new();
+
+/// Roughly how long the micro:bit takes to scroll each character past
+const SCROLL_PER_CHAR: Duration = Duration::from_millis(600);
+
+#[derive(Clone, Copy)]
+pub struct SimDisplay;
+
+impl Display5x5 for SimDisplay {
+    async fn apply(&self, action: DisplayAction) {
+        DISPLAY_CHANNEL.send(action).await;
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SimSpeaker;
+
+impl ToneOutput for SimSpeaker {
+    async fn apply(&self, action: AudioAction) {
+        AUDIO_CHANNEL.send(action).await;
+    }
+}
+
+/// Set while the simulated link is broken, so every notification fails
+pub static LINK_LOST: AtomicBool = AtomicBool::new(false);
+
+/// The notification couldn't be sent
+#[derive(Debug)]
+pub struct LinkLost;
+
+/// Prints the GATT notifications the firmware would send for each report, keeping each value in
+/// its characteristic first for centrals that read instead, as the attribute table does
+#[derive(Default)]
+pub struct PrintSink {
+    buttons: Cell<[bool; 6]>,
+    axes: Cell<[i8; 2]>,
+}
+
+impl PrintSink {
+    /// Release every button and centre the stick, as the firmware does when a central connects
+    pub fn reset(&self) {
+        self.buttons.take();
+        self.axes.take();
+    }
+
+    /// What a central reading the button and stick characteristics gets
+    pub fn read(&self) -> String {
+        format!(
+            "buttons {:?} stick {:?}",
+            self.buttons.get(),
+            self.axes.get()
+        )
+    }
+}
+
+impl ReportSink for PrintSink {
+    type Error = LinkLost;
+
+    async fn report(&self, report: Report) -> Result<(), Self::Error> {
+        match report {
+            Report::Button { button, pressed } => {
+                let mut buttons = self.buttons.get();
+                buttons[button as usize] = pressed;
+                self.buttons.set(buttons);
+            }
+            Report::Axis { axis, value } => {
+                let mut axes = self.axes.get();
+                axes[axis as usize] = value;
+                self.axes.set(axes);
+            }
+        }
+        if LINK_LOST.load(Ordering::SeqCst) {
+            terminal::log(format!("notify failed: {report:?}"));
+            return Err(LinkLost);
+        }
+        let line = match report {
+            Report::Button { button, pressed } => {
+                let characteristic = match button {
+                    ButtonId::A => "button_a",
+                    ButtonId::B => "button_b",
+                    ButtonId::C => "button_c",
+                    ButtonId::D => "button_d",
+                    ButtonId::E => "button_e",
+                    ButtonId::F => "button_f",
+                };
+                format!("notify hid.{characteristic} = {pressed}")
+            }
+            Report::Axis { axis, value } => {
+                let characteristic = match axis {
+                    AxisId::X => "x",
+                    AxisId::Y => "y",
+                };
+                format!("notify stick.{characteristic} = {value}")
+            }
+        };
+        terminal::log(line);
+        Ok(())
+    }
+}
+
+/// Prints the HID reports the firmware would notify in keyboard and mouse or media remote mode
+pub struct PrintHid;
+
+impl HidOutput for PrintHid {
+    type Error = LinkLost;
+
+    async fn send(&self, report: HidReport) -> Result<(), Self::Error> {
+        if LINK_LOST.load(Ordering::SeqCst) {
+            terminal::log(format!("notify failed: {report:?}"));
+            return Err(LinkLost);
+        }
+        let line = match report {
+            HidReport::Keyboard(report) => format!(
+                "notify hid_device.keyboard = modifiers {:#04x}, keys {:02x?}",
+                report.modifiers, report.keys
+            ),
+            HidReport::Mouse(report) => format!(
+                "notify hid_device.mouse = buttons {:#05b}, move {}, {}",
+                report.buttons, report.x, report.y
+            ),
+            HidReport::Consumer(report) => format!(
+                "notify hid_device.consumer = controls {:#09b}",
+                report.controls
+            ),
+        };
+        terminal::log(line);
+        Ok(())
+    }
+}
+
+/// Prints the connection parameters the firmware would ask for, and paces reports to the
+/// longest interval asked for, as a central settling on it would have the firmware do
+pub struct SimLink<'a>(pub &'a ReportScheduler);
+
+impl Link for SimLink<'_> {
+    async fn request_params(&self, params: ConnectionParams) {
+        self.0
+            .follow(Duration::from_micros(params.interval_max_us().into()));
+        terminal::log(format!(
+            "request connection interval {}-{} us, latency {}",
+            params.interval_min_us(),
+            params.interval_max_us(),
+            params.latency
+        ));
+    }
+}
+
+/// Drain the display queue like the firmware's display driver task
+pub async fn display_driver() {
+    let mut brightness = 0;
+    loop {
+        match DISPLAY_CHANNEL.receive().await {
+            DisplayAction::SetBrightness(level) => brightness = level.level(),
+            DisplayAction::Clear => terminal::show(Bitmap::empty(), ""),
+            DisplayAction::Scroll(text) => scroll(text).await,
+            DisplayAction::ScrollName(name) => scroll(name.as_str()).await,
+            DisplayAction::SetFrame { frame, duration } => {
+                let caption = format!("{frame:?} at brightness {brightness}");
+                terminal::show(frame.to_bitmap(), caption);
+                let Some(duration) = duration else {
+                    // held until something else is shown
+                    continue;
+                };
+                // like the LED matrix, shown for the whole duration then dark
+                Timer::after(duration).await;
+                terminal::show(Bitmap::empty(), "");
+            }
+        }
+    }
+}
+
+/// Show `text` as scrolling, for as long as the matrix would take
+async fn scroll(text: &str) {
+    terminal::show(Bitmap::empty(), format!("scrolling \"{text}\""));
+    Timer::after(SCROLL_PER_CHAR * text.chars().count() as u32).await;
+    terminal::show(Bitmap::empty(), "");
+}
+
+/// Drain the audio queue like the firmware's audio driver task
+pub async fn audio_driver() {
+    loop {
+        match AUDIO_CHANNEL.receive().await {
+            AudioAction::PlayNote(note) => play(&note).await,
+            AudioAction::PlayTune(tune) => {
+                terminal::log(format!("tune {tune:?}"));
+                for note in tune.notes() {
+                    play(note).await;
+                }
+            }
+            AudioAction::SetVolume(volume) => {
+                terminal::log(format!("volume {}", volume.level()));
+            }
+        }
+    }
+}
+
+async fn play(note: &Note) {
+    match note.frequency {
+        0 => terminal::log(format!("  rest {} ms", note.duration_ms)),
+        hz => terminal::log(format!("  tone {hz} Hz {} ms", note.duration_ms)),
+    }
+    Timer::after(Duration::from_millis(note.duration_ms as u64)).await;
+}
@@ -0,0 +1,306 @@
+//! Runs the gamepad's controller state machine, input pipeline and display and audio queues on
+//! a desktop, with the keyboard standing in for the buttons and stick and the terminal for the
+//! LED matrix, speaker and BLE link.
+
+mod hardware;
+mod terminal;
+
+use std::{
+    cell::{Cell, RefCell},
+    convert::Infallible,
+    sync::atomic::Ordering,
+};
+
+use embassy_futures::{
+    block_on,
+    join::join3,
+    select::{select, select3, Either, Either3},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Timer};
+use gamepad_core::{
+    advertising::AdvertisingConfig,
+    config::Settings,
+    controller::{perform, Controller, Event, State},
+    display::{Bitmap, Brightness, DisplayFrame},
+    error::{ErrorCode, Recovery},
+    hal::{DigitalInput, Display5x5},
+    hid::{HidMode, KeyboardMouse, MediaRemote},
+    input::{analog_stick_task, buttons_task, GamepadInputs, STICK_OFFSET},
+    mock::{MockInput, MockSampler},
+    power::{idle_watch, Activity, ActivitySink, PowerConfig},
+    profile::{self, Chord, ChordSink, Profile, PROFILE_COUNT},
+    scheduler::ReportScheduler,
+};
+
+use crate::hardware::{
+    audio_driver, display_driver, PrintHid, PrintSink, SimDisplay, SimLink, SimSpeaker, LINK_LOST,
+};
+
+/// How long to show an error before trying again, as on the board
+const ERROR_RECOVERY: Duration = Duration::from_secs(3);
+/// How long the pretend figure-eight calibration takes
+const CALIBRATION: Duration = Duration::from_millis(2500);
+
+/// Keys that stand in for things the simulator has no hardware for
+#[derive(Clone, Copy, Debug)]
+pub enum Command {
+    /// A central connects, or the connected one disconnects
+    ToggleConnection,
+    /// Notifications start failing, as if the link dropped without a disconnect
+    LoseLink,
+    Shake,
+    Calibrate,
+    Idle,
+    Fault,
+    /// The central reads the button and stick characteristics
+    Read,
+    /// Move on to the next of gamepad, keyboard and mouse, and media remote, as holding A at
+    /// power on does
+    SwitchMode,
+    /// The central switches to the next profile, as writing the profile characteristic does
+    NextProfile,
+}
+
+/// The profiles not in use, which the firmware keeps in flash
+type Profiles = RefCell<[Option<Profile>; PROFILE_COUNT as usize]>;
+
+pub static COMMANDS: Channel<CriticalSectionRawMutex, Command, 8> = Channel::new();
+
+fn main() -> std::io::Result<()> {
+    let buttons: [MockInput; 6] = Default::default();
+    let stick = MockSampler::new([STICK_OFFSET; 2]);
+    terminal::enter()?;
+    terminal::spawn_keyboard(buttons.clone(), stick.clone());
+    let [a, b, c, d, e, f] = buttons;
+    let inputs = GamepadInputs::new(a, b, c, d, e, f);
+    block_on(join3(run(inputs, stick), display_driver(), audio_driver()));
+    terminal::exit()
+}
+
+/// The firmware's main loop, with the BLE stack and motion sensor replaced by key commands
+async fn run(mut buttons: GamepadInputs<MockInput>, mut stick: MockSampler<2>) {
+    let display = SimDisplay;
+    let speaker = SimSpeaker;
+    let settings = Cell::new(Settings::default());
+    let power = PowerConfig::default();
+    let activity = Activity::new();
+    let notifier = PrintSink::default();
+    let profiles: Profiles = RefCell::new([None; PROFILE_COUNT as usize]);
+    display.set_brightness(Brightness::MAX).await;
+    let mut controller = Controller::new();
+    perform(controller.start(), &display, &speaker).await;
+
+    let mut event = Event::Booted;
+    loop {
+        terminal::log(format!("[main] {event:?} in {:?}", controller.state()));
+        if perform(controller.handle(event), &display, &speaker).await {
+            terminal::log("central disconnected");
+        }
+        terminal::set_state(format!("{:?}", controller.state()));
+        event = match controller.state() {
+            State::Booting => Event::Booted,
+            State::Advertising(phase) => {
+                LINK_LOST.store(false, Ordering::SeqCst);
+                let commands = async {
+                    loop {
+                        match COMMANDS.receive().await {
+                            Command::ToggleConnection => break Event::Connected,
+                            Command::Idle => break Event::Idle,
+                            Command::Fault => break Event::Fault(ErrorCode::Advertising),
+                            Command::SwitchMode => switch_mode(&settings),
+                            _ => {}
+                        }
+                    }
+                };
+                let advertising = AdvertisingConfig::from(&settings.get());
+                let timeout = Timer::after(advertising.timing(phase).timeout);
+                match select(commands, timeout).await {
+                    Either::First(event) => event,
+                    Either::Second(()) => Event::AdvertisingTimeout,
+                }
+            }
+            State::Connected => {
+                notifier.reset();
+                let scheduler = ReportScheduler::new(settings.get().batch_rate_hz.into());
+                let link = SimLink(&scheduler);
+                // as on the board, the mode in effect when the central connected holds until
+                // it disconnects
+                let mode = settings.get().hid_mode;
+                let remote = MediaRemote::new(&PrintHid);
+                let sink = ActivitySink {
+                    sink: &scheduler,
+                    activity: &activity,
+                };
+                let chord = Chord::new();
+                let chorded = ChordSink {
+                    sink: &sink,
+                    chord: &chord,
+                };
+                let inputs = async {
+                    let buttons = buttons_task(&mut buttons, &display, &chorded, &settings);
+                    let analog = analog_stick_task(&mut stick, &display, &sink, &settings);
+                    let switches = switch_on_chord(&chord, &settings, &profiles, &display);
+                    match select3(buttons, analog, switches).await {
+                        Either3::First(Err(never)) | Either3::Second(Err(never)) => match never {},
+                        Either3::First(Ok(())) | Either3::Second(Ok(())) => Event::Disconnected,
+                        Either3::Third(never) => match never {},
+                    }
+                };
+                let commands = async {
+                    loop {
+                        let idle = idle_watch(&activity, &power, &display, &link, &settings);
+                        let command = match select(COMMANDS.receive(), idle).await {
+                            Either::First(command) => command,
+                            Either::Second(()) => break Event::Idle,
+                        };
+                        match command {
+                            Command::ToggleConnection => break Event::Disconnected,
+                            Command::LoseLink => LINK_LOST.store(true, Ordering::SeqCst),
+                            // a shake plays or pauses a media remote rather than letting go
+                            Command::Shake if mode == HidMode::MediaRemote => {
+                                if let Err(e) = remote.play_pause().await {
+                                    terminal::log(format!("[error] {e:?}"));
+                                    break Event::NotifyFailed;
+                                }
+                            }
+                            Command::Shake => break Event::Shaken,
+                            Command::Calibrate => break Event::CalibrationRequested,
+                            Command::Fault => break Event::Fault(ErrorCode::Attribute),
+                            Command::Idle => break Event::Idle,
+                            Command::Read => terminal::log(format!("[read] {}", notifier.read())),
+                            Command::SwitchMode => switch_mode(&settings),
+                            Command::NextProfile => {
+                                let next = profile::next(settings.get().profile);
+                                switch_profile(&settings, &profiles, &display, next).await;
+                            }
+                        }
+                    }
+                };
+                let keyboard_mouse = KeyboardMouse::new(&PrintHid, &settings);
+                let delivery = async {
+                    match mode {
+                        HidMode::Gamepad => scheduler.run(&notifier).await,
+                        HidMode::KeyboardMouse => {
+                            let keys = scheduler.run(&keyboard_mouse);
+                            match select(keys, keyboard_mouse.pointer()).await {
+                                Either::First(result) | Either::Second(result) => result,
+                            }
+                        }
+                        HidMode::MediaRemote => {
+                            let keys = scheduler.run(&remote);
+                            match select(keys, remote.volume()).await {
+                                Either::First(result) | Either::Second(result) => result,
+                            }
+                        }
+                    }
+                };
+                let event = match select3(inputs, commands, delivery).await {
+                    Either3::First(event) | Either3::Second(event) => event,
+                    Either3::Third(Err(e)) => {
+                        terminal::log(format!("[error] {e:?}"));
+                        Event::NotifyFailed
+                    }
+                    Either3::Third(Ok(never)) => match never {},
+                };
+                // undo any dimming by the idle watch
+                display.set_brightness(settings.get().brightness).await;
+                event
+            }
+            State::Calibrating => calibrate(&display).await,
+            State::Sleeping => {
+                let [a, b, ..] = &mut buttons.buttons;
+                select(a.input.wait_for_low(), b.input.wait_for_low()).await;
+                Event::Wake
+            }
+            State::Error(_) => {
+                Timer::after(ERROR_RECOVERY).await;
+                match controller.recovery() {
+                    Some(Recovery::Reset) => terminal::log("[error] the board would reset"),
+                    Some(Recovery::RestartStack) => {
+                        terminal::log("[error] the BLE stack would restart")
+                    }
+                    Some(Recovery::RestartAdvertising) | None => {}
+                }
+                Event::Recovered
+            }
+        };
+    }
+}
+
+/// Move on to the next HID mode, from the next connection
+fn switch_mode(settings: &Cell<Settings>) {
+    let mut switched = settings.get();
+    switched.hid_mode = switched.hid_mode.next();
+    settings.set(switched);
+    terminal::log(format!("[main] switched to {:?}", switched.hid_mode));
+}
+
+/// Take up profile `to` and scroll its name, keeping the one in use to switch back to
+async fn switch_profile(
+    settings: &Cell<Settings>,
+    profiles: &Profiles,
+    display: &SimDisplay,
+    to: u8,
+) {
+    let mut switched = settings.get();
+    if to != switched.profile {
+        let from = switched.profile;
+        let saved = profiles.borrow_mut()[to as usize].take();
+        let left = profile::swap(&mut switched, to, saved);
+        profiles.borrow_mut()[from as usize] = Some(left);
+        settings.set(switched);
+    }
+    terminal::log(format!(
+        "[main] profile {to} {}",
+        switched.profile_name.as_str()
+    ));
+    display.scroll_name(switched.profile_name).await;
+}
+
+/// Move on to the next profile each time the chord is held, as on the board
+async fn switch_on_chord(
+    chord: &Chord,
+    settings: &Cell<Settings>,
+    profiles: &Profiles,
+    display: &SimDisplay,
+) -> Infallible {
+    loop {
+        chord.wait().await;
+        let next = profile::next(settings.get().profile);
+        switch_profile(settings, profiles, display, next).await;
+    }
+}
+
+/// Fill the matrix as the firmware does while calibrating, unless the central goes away
+async fn calibrate(display: &SimDisplay) -> Event {
+    let steps = 25;
+    let progress = async {
+        for step in 1..=steps {
+            let mut frame = Bitmap::empty();
+            for led in 0..step {
+                frame.set(led % 5, led / 5);
+            }
+            display
+                .display(
+                    DisplayFrame::DisplayFrame(frame),
+                    CALIBRATION / steps as u32,
+                )
+                .await;
+        }
+        display
+            .display(DisplayFrame::Smile, Duration::from_secs(1))
+            .await;
+    };
+    let disconnect = async {
+        loop {
+            if let Command::ToggleConnection = COMMANDS.receive().await {
+                break;
+            }
+        }
+    };
+    match select(progress, disconnect).await {
+        Either::First(()) => Event::CalibrationFinished,
+        Either::Second(()) => Event::Disconnected,
+    }
+}
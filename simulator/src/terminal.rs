@@ -0,0 +1,191 @@
+//! Drawing the simulated board in the terminal and reading the keyboard.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    terminal::{self, ClearType},
+};
+use gamepad_core::{
+    display::Bitmap,
+    input::{STICK_DIVIDER, STICK_OFFSET},
+    mock::{MockInput, MockSampler},
+};
+
+use crate::{Command, COMMANDS};
+
+/// Lines of notifications and tones kept on screen
+const LOG_LINES: usize = 12;
+/// How long a tap on a button key holds the button down, terminals don't report key releases
+const TAP: Duration = Duration::from_millis(150);
+
+const HELP: &str = "buttons a-f (shift holds, again releases)  stick arrows, space centres\r\n\
+     n connect/disconnect  l lose link  x shake  k calibrate  z idle  e fault  q quit";
+
+struct Screen {
+    frame: Bitmap,
+    caption: String,
+    state: String,
+    log: VecDeque<String>,
+}
+
+static SCREEN: Mutex<Screen> = Mutex::new(Screen {
+    frame: Bitmap::empty(),
+    caption: String::new(),
+    state: String::new(),
+    log: VecDeque::new(),
+});
+
+/// Put the terminal in raw mode for single key input
+pub fn enter() -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+    redraw(&SCREEN.lock().unwrap());
+    Ok(())
+}
+
+/// Give the terminal back as it was and end the process
+pub fn exit() -> ! {
+    let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    std::process::exit(0)
+}
+
+/// Light the LED matrix, with a note of anything it can't show such as scrolling text
+pub fn show(frame: Bitmap, caption: impl Into<String>) {
+    let mut screen = SCREEN.lock().unwrap();
+    screen.frame = frame;
+    screen.caption = caption.into();
+    redraw(&screen);
+}
+
+pub fn set_state(state: impl Into<String>) {
+    let mut screen = SCREEN.lock().unwrap();
+    screen.state = state.into();
+    redraw(&screen);
+}
+
+/// Add a line to the scrolling log under the matrix
+pub fn log(line: impl Into<String>) {
+    let mut screen = SCREEN.lock().unwrap();
+    if screen.log.len() == LOG_LINES {
+        screen.log.pop_front();
+    }
+    screen.log.push_back(line.into());
+    redraw(&screen);
+}
+
+fn redraw(screen: &Screen) {
+    let mut out = io::stdout().lock();
+    let _ = queue!(out, cursor::MoveTo(0, 0), terminal::Clear(ClearType::All));
+    let _ = write!(
+        out,
+        "micro:bit gamepad simulator    {}\r\n\r\n",
+        screen.state
+    );
+    for y in 0..5 {
+        let row: String = (0..5)
+            .map(|x| {
+                if screen.frame.is_set(x, y) {
+                    " #"
+                } else {
+                    " ."
+                }
+            })
+            .collect();
+        let caption = if y == 2 { screen.caption.as_str() } else { "" };
+        let _ = write!(out, "   {row}    {caption}\r\n");
+    }
+    let _ = write!(out, "\r\n{HELP}\r\n\r\n");
+    for line in &screen.log {
+        let _ = write!(out, "{line}\r\n");
+    }
+    let _ = out.flush();
+}
+
+/// Read keys on a thread of their own, pressing the mock buttons, moving the mock stick and
+/// queueing everything else as a [`Command`]
+pub fn spawn_keyboard(buttons: [MockInput; 6], stick: MockSampler<2>) {
+    thread::spawn(move || {
+        let mut levels = [0i16; 2];
+        let mut held = [false; 6];
+        loop {
+            let Ok(Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press,
+                ..
+            })) = event::read()
+            else {
+                continue;
+            };
+            match code {
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => exit(),
+                KeyCode::Char('q') => exit(),
+                KeyCode::Char(key @ ('a'..='f' | 'A'..='F')) => {
+                    let index = (key.to_ascii_lowercase() as u8 - b'a') as usize;
+                    let button = buttons[index].clone();
+                    if key.is_ascii_uppercase() {
+                        held[index] = !held[index];
+                        if held[index] {
+                            button.press();
+                        } else {
+                            button.release();
+                        }
+                    } else if !held[index] {
+                        button.press();
+                        thread::spawn(move || {
+                            thread::sleep(TAP);
+                            button.release();
+                        });
+                    }
+                }
+                KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::Char(' ') => {
+                    match code {
+                        KeyCode::Left => levels[0] -= 1,
+                        KeyCode::Right => levels[0] += 1,
+                        // display rows count downwards
+                        KeyCode::Up => levels[1] -= 1,
+                        KeyCode::Down => levels[1] += 1,
+                        _ => levels = [0, 0],
+                    }
+                    levels = levels.map(|level| level.clamp(-3, 3));
+                    stick.set(levels.map(raw_for_level));
+                }
+                KeyCode::Char(key) => {
+                    let command = match key {
+                        'n' => Command::ToggleConnection,
+                        'l' => Command::LoseLink,
+                        'x' => Command::Shake,
+                        'k' => Command::Calibrate,
+                        'z' => Command::Idle,
+                        'e' => Command::Fault,
+                        'r' => Command::Read,
+                        'm' => Command::SwitchMode,
+                        'p' => Command::NextProfile,
+                        _ => continue,
+                    };
+                    let _ = COMMANDS.try_send(command);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+/// A stick reading in the middle of the band that quantises to `level`, the axis is inverted
+fn raw_for_level(level: i16) -> i16 {
+    STICK_OFFSET - level * STICK_DIVIDER - level.signum() * STICK_DIVIDER / 2
+}
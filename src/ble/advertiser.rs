@@ -1,63 +1,117 @@
 use defmt::info;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, signal::Signal};
+use gamepad_core::{advertising::PhaseTiming, hid::HidMode};
+use gamepad_protocol::{
+    advertising::{advertisement, scan_response, AdvertisingData, Board, Manufacturer, TooLong},
+    DeviceName,
+};
 use trouble_host::prelude::*;
 
+use crate::error::{self, Error};
+
+/// The softdevice controller's transmit power, which is left at its default
+const TX_POWER_DBM: i8 = 0;
+
+/// Ask whoever is advertising to start over with what is advertised now
+pub static READVERTISE: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// What goes in the advertisement and scan response
+#[derive(Clone, Copy)]
+pub struct Advertised {
+    pub name: DeviceName,
+    pub player: u8,
+    pub mode: HidMode,
+}
+
 /// BLE advertiser
 pub struct AdvertiserBuilder<'d, C: Controller> {
-    /// Name of the device
-    name: &'d str,
+    advertised: Advertised,
     peripheral: Peripheral<'d, C>,
 }
 
 pub struct Advertiser<'d, C: Controller> {
-    advertiser_data: [u8; 31],
-    scan_data: [u8; 4],
+    advertiser_data: AdvertisingData,
+    scan_data: AdvertisingData,
     peripheral: Peripheral<'d, C>,
 }
 
 /// A BLE advertiser
 impl<'d, C: Controller> AdvertiserBuilder<'d, C> {
     /// Create a new advertiser builder
-    pub fn new(name: &'d str, peripheral: Peripheral<'d, C>) -> Self {
-        Self { name, peripheral }
-    }
-    /// Build the advertiser
-    pub fn build(self) -> Result<Advertiser<'d, C>, Error> {
-        let name: &str;
-        if self.name.len() > 22 {
-            name = &self.name[..22];
-            info!("Name truncated to {}", name);
-        } else {
-            name = self.name;
+    pub fn new(advertised: Advertised, peripheral: Peripheral<'d, C>) -> Self {
+        Self {
+            advertised,
+            peripheral,
         }
-        let mut advertiser_data = [0; 31];
-        AdStructure::encode_slice(
-            &[
-                AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
-                AdStructure::ServiceUuids16(&[Uuid::Uuid16([0x0f, 0x18])]),
-                AdStructure::CompleteLocalName(name.as_bytes()),
-            ],
-            &mut advertiser_data[..],
-        )?;
-        #[rustfmt::skip]
-        let scan_data: [u8;4] = [0; 4];
-        Ok(Advertiser {
-            advertiser_data,
-            scan_data,
+    }
+
+    /// Build the advertiser, or say which part of the advertising data didn't fit
+    pub fn build(self) -> Result<Advertiser<'d, C>, TooLong> {
+        let mut advertiser = Advertiser {
+            advertiser_data: AdvertisingData::new(),
+            scan_data: AdvertisingData::new(),
             peripheral: self.peripheral,
-        })
+        };
+        advertiser.update(&self.advertised)?;
+        Ok(advertiser)
     }
 }
 
 impl<'d, C: Controller> Advertiser<'d, C> {
-    /// Advertise and connect to a device with the given name
-    pub async fn advertise(&mut self) -> Result<Connection<'d>, BleHostError<C::Error>> {
+    /// Advertise the name and player in `advertised`, with the appearance for its mode, from
+    /// the next call to [`Advertiser::advertise`]. The appearance, HID service and manufacturer
+    /// data go in the advertisement and the name in the scan response.
+    pub fn update(&mut self, advertised: &Advertised) -> Result<(), TooLong> {
+        let manufacturer = Manufacturer {
+            board: Board::MicrobitV2,
+            player: advertised.player,
+            battery: None,
+        };
+        self.advertiser_data = advertisement(advertised.mode, TX_POWER_DBM, &manufacturer)?;
+        self.scan_data = scan_response(advertised.name.as_str())?;
+        Ok(())
+    }
+
+    /// Advertise what `current` returns at the intervals in `timing` until a central connects,
+    /// starting over with what it returns then whenever [`READVERTISE`] is signalled. Data that
+    /// doesn't fit is recorded, and what was advertised before is kept.
+    pub async fn advertise_current(
+        &mut self,
+        timing: &PhaseTiming,
+        current: impl Fn() -> Advertised,
+    ) -> Result<Connection<'d>, BleHostError<C::Error>> {
+        loop {
+            READVERTISE.reset();
+            if let Err(e) = self.update(&current()) {
+                error::record(&Error::AdvertisingData(e));
+            }
+            if let Either::First(result) = select(self.advertise(timing), READVERTISE.wait()).await
+            {
+                return result;
+            }
+            info!("[advertiser] advertising data changed");
+        }
+    }
+
+    /// Advertise at the intervals in `timing` until a central connects. The caller enforces the
+    /// timeout by dropping this.
+    pub async fn advertise(
+        &mut self,
+        timing: &PhaseTiming,
+    ) -> Result<Connection<'d>, BleHostError<C::Error>> {
+        let params = AdvertisementParameters {
+            interval_min: timing.interval_min,
+            interval_max: timing.interval_max,
+            ..Default::default()
+        };
         let mut advertiser = self
             .peripheral
             .advertise(
-                &Default::default(),
+                &params,
                 Advertisement::ConnectableScannableUndirected {
-                    adv_data: &self.advertiser_data[..],
-                    scan_data: &self.scan_data[..],
+                    adv_data: self.advertiser_data.as_bytes(),
+                    scan_data: self.scan_data.as_bytes(),
                 },
             )
             .await?;
@@ -0,0 +1,114 @@
+//! Serving more than one central, see [`gamepad_core::centrals`]. Every notification is sent
+//! to each central in turn, and only goes out to those that enabled it.
+
+use core::convert::Infallible;
+
+use defmt::{info, warn};
+use embassy_futures::select::select_array;
+use gamepad_core::{advertising::PhaseTiming, centrals::Role};
+use microbit_bsp::ble::SoftdeviceError;
+use trouble_host::prelude::*;
+use trouble_host::types::gatt_traits::GattValue;
+
+use crate::error::{self, Error};
+
+use super::{
+    advertiser::{Advertised, Advertiser},
+    config::LiveSettings,
+    gatt::gatt_server_task,
+    BleCentrals, BleController, BleServer, COMPANIONS_MAX,
+};
+
+/// Make `value` the current value of `characteristic`, so a read returns it, and notify it to
+/// every connected central that subscribed. A central that didn't subscribe, or can't be
+/// reached, gets the latest value when it next reads. Only failing to store it is an error.
+pub async fn notify_all<T: GattValue>(
+    server: &BleServer<'_>,
+    centrals: &BleCentrals,
+    characteristic: &Characteristic<T>,
+    value: &T,
+) -> Result<(), BleHostError<SoftdeviceError>> {
+    notify_where(server, centrals, characteristic, value, |_| true).await
+}
+
+/// [`notify_all`], leaving out the centrals `admit` turns down
+pub async fn notify_where<T: GattValue>(
+    server: &BleServer<'_>,
+    centrals: &BleCentrals,
+    characteristic: &Characteristic<T>,
+    value: &T,
+    admit: impl Fn(&Connection<'_>) -> bool,
+) -> Result<(), BleHostError<SoftdeviceError>> {
+    server.set(characteristic, value)?;
+    for (role, conn) in centrals.connected() {
+        if !admit(&conn) || !subscribed(server, &conn, characteristic) {
+            continue;
+        }
+        if let Err(e) = server.notify(characteristic, &conn, value).await {
+            // the link is probably going, which the central's GATT server task will see
+            warn!("[centrals] couldn't notify {:?}: {:?}", role, e);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `conn` enabled notifications on `characteristic`. The host keeps every
+/// connection's CCCDs, so each central has its own subscriptions.
+fn subscribed<T: GattValue>(
+    server: &BleServer<'_>,
+    conn: &Connection<'_>,
+    characteristic: &Characteristic<T>,
+) -> bool {
+    characteristic
+        .cccd_handle
+        .is_some_and(|cccd| server.should_notify(conn, cccd))
+}
+
+/// Run the GATT server for every companion as it joins, until dropped
+pub async fn serve_companions(
+    server: &BleServer<'_>,
+    centrals: &BleCentrals,
+    settings: &LiveSettings<'_>,
+) -> Infallible {
+    let slots: [_; COMPANIONS_MAX] =
+        core::array::from_fn(|slot| serve_slot(server, centrals, settings, slot));
+    select_array(slots).await.0
+}
+
+async fn serve_slot(
+    server: &BleServer<'_>,
+    centrals: &BleCentrals,
+    settings: &LiveSettings<'_>,
+    slot: usize,
+) -> Infallible {
+    loop {
+        let conn = centrals.companion(slot).await;
+        gatt_server_task(server, &conn, false, None, settings).await;
+        info!("[centrals] companion {} left", slot);
+        centrals.leave(slot);
+    }
+}
+
+/// Advertise what `current` returns at `timing` whenever a companion could join, until dropped.
+/// A failure to advertise is recorded and stops further companions joining, without troubling
+/// the primary.
+pub async fn admit_companions(
+    advertiser: &mut Advertiser<'static, BleController>,
+    centrals: &BleCentrals,
+    timing: &PhaseTiming,
+    current: impl Fn() -> Advertised,
+) -> Infallible {
+    loop {
+        centrals.wait_for_room().await;
+        match advertiser.advertise_current(timing, &current).await {
+            Ok(conn) => match centrals.join(conn) {
+                Ok(slot) => info!("[centrals] companion {} joined", slot),
+                Err(conn) => conn.disconnect(),
+            },
+            Err(e) => {
+                error::record(&Error::Advertising(e));
+                return core::future::pending().await;
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use core::{cell::Cell, convert::Infallible};
+
+use defmt::{info, warn};
+use gamepad_core::{
+    config::Settings,
+    hal::{Display5x5, ToneOutput},
+    profile::{self, Chord},
+};
+use gamepad_protocol::{
+    gatt::config,
+    hid::{HidMode, KeyMap},
+    AdvertisingPhase, ButtonMapping, DeviceName, IdleTimeouts, ProfileName, Setting, Value,
+};
+use trouble_host::prelude::*;
+
+use crate::{
+    config as saved,
+    error::{self, Error},
+    io::{audio::AsyncAudio, display::AsyncDisplay},
+};
+
+use super::{
+    advertiser::{Advertised, READVERTISE},
+    gap::{self, publish_name},
+    uuid, BleServer,
+};
+
+/// Tunable settings, saved in flash and applied as soon as they are written. Each write is
+/// checked against the ranges in `gamepad_protocol::gatt::config` and refused if it's outside.
+#[gatt_service(uuid = uuid(config::SERVICE))]
+pub struct ConfigService {
+    /// See [`DeviceName`], advertised straight away and mirrored in the GAP Device Name
+    #[characteristic(uuid = uuid(config::NAME), read, write, on_write = valid_name)]
+    pub name: [u8; DeviceName::SIZE],
+    /// Raw stick counts either side of the centre that read as centred
+    #[characteristic(uuid = uuid(config::DEADZONE), read, write, on_write = valid_deadzone)]
+    pub deadzone: u16,
+    /// See [`gamepad_protocol::Curve`]
+    #[characteristic(uuid = uuid(config::CURVE), read, write, on_write = valid_curve)]
+    pub curve: u8,
+    /// Milliseconds a button is left to settle after it changes
+    #[characteristic(uuid = uuid(config::DEBOUNCE), read, write, on_write = valid_debounce)]
+    pub debounce: u8,
+    /// Stick samples per second
+    #[characteristic(uuid = uuid(config::REPORT_RATE), read, write, on_write = valid_report_rate)]
+    pub report_rate: u8,
+    #[characteristic(uuid = uuid(config::BRIGHTNESS), read, write, on_write = valid_brightness)]
+    pub brightness: u8,
+    #[characteristic(uuid = uuid(config::VOLUME), read, write, on_write = valid_volume)]
+    pub volume: u8,
+    /// See [`ButtonMapping`]
+    #[characteristic(uuid = uuid(config::MAPPING), read, write, on_write = valid_mapping)]
+    pub mapping: [u8; ButtonMapping::SIZE],
+    /// See [`HidMode`], the board restarts to take up a change
+    #[characteristic(uuid = uuid(config::HID_MODE), read, write, on_write = valid_hid_mode)]
+    pub hid_mode: u8,
+    /// See [`KeyMap`]
+    #[characteristic(uuid = uuid(config::KEY_MAP), read, write, on_write = valid_key_map)]
+    pub key_map: [u8; KeyMap::SIZE],
+    /// See [`gamepad_protocol::Turbo`]
+    #[characteristic(uuid = uuid(config::TURBO), read, write, on_write = valid_turbo)]
+    pub turbo: u8,
+    /// See [`ProfileName`], of the profile in use
+    #[characteristic(uuid = uuid(config::PROFILE_NAME), read, write, on_write = valid_profile_name)]
+    pub profile_name: [u8; ProfileName::SIZE],
+    /// The profile in use, write another to switch to it
+    #[characteristic(uuid = uuid(config::PROFILE), read, write, on_write = valid_profile)]
+    pub profile: u8,
+    /// See [`AdvertisingPhase`], taken up the next time the fast phase starts
+    #[characteristic(uuid = uuid(config::FAST_ADVERTISING), read, write, on_write = valid_fast_advertising)]
+    pub fast_advertising: [u8; AdvertisingPhase::SIZE],
+    /// See [`AdvertisingPhase`], taken up the next time the slow phase starts or a companion
+    /// could join
+    #[characteristic(uuid = uuid(config::SLOW_ADVERTISING), read, write, on_write = valid_slow_advertising)]
+    pub slow_advertising: [u8; AdvertisingPhase::SIZE],
+    /// See [`IdleTimeouts`], taken up within a second
+    #[characteristic(uuid = uuid(config::IDLE_TIMEOUTS), read, write, on_write = valid_idle_timeouts)]
+    pub idle_timeouts: [u8; IdleTimeouts::SIZE],
+    /// Most batches of input reports a second, taken up the next time a central connects
+    #[characteristic(uuid = uuid(config::BATCH_RATE), read, write, on_write = valid_batch_rate)]
+    pub batch_rate: u8,
+}
+
+/// Refuse the write unless it decodes as a setting for `characteristic`
+fn validate(characteristic: gamepad_protocol::Uuid, value: &[u8]) -> Result<(), ()> {
+    match Setting::decode(characteristic, value) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            warn!(
+                "[config] refused {:?} for {:?}: {:?}",
+                value, characteristic, e
+            );
+            Err(())
+        }
+    }
+}
+
+fn valid_name(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::NAME, value)
+}
+
+fn valid_deadzone(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::DEADZONE, value)
+}
+
+fn valid_curve(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::CURVE, value)
+}
+
+fn valid_debounce(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::DEBOUNCE, value)
+}
+
+fn valid_report_rate(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::REPORT_RATE, value)
+}
+
+fn valid_brightness(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::BRIGHTNESS, value)
+}
+
+fn valid_volume(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::VOLUME, value)
+}
+
+fn valid_mapping(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::MAPPING, value)
+}
+
+fn valid_hid_mode(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::HID_MODE, value)
+}
+
+fn valid_key_map(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::KEY_MAP, value)
+}
+
+fn valid_turbo(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::TURBO, value)
+}
+
+fn valid_profile_name(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::PROFILE_NAME, value)
+}
+
+fn valid_profile(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::PROFILE, value)
+}
+
+fn valid_fast_advertising(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::FAST_ADVERTISING, value)
+}
+
+fn valid_slow_advertising(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::SLOW_ADVERTISING, value)
+}
+
+fn valid_idle_timeouts(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::IDLE_TIMEOUTS, value)
+}
+
+fn valid_batch_rate(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    validate(config::BATCH_RATE, value)
+}
+
+/// The settings in effect, and what has to hear about a change for it to take effect
+pub struct LiveSettings<'a> {
+    pub settings: &'a Cell<Settings>,
+    pub display: &'a AsyncDisplay,
+    pub speaker: &'a AsyncAudio,
+    /// The HID mode the attribute table was built for. A change to the setting is saved and
+    /// the board restarts to take it up, see [`LiveSettings::restart_for_mode`].
+    pub mode: HidMode,
+}
+
+impl LiveSettings<'_> {
+    /// What to advertise now, with the player the last central chose
+    pub fn advertised(&self, server: &BleServer<'_>) -> Advertised {
+        let settings = self.settings.get();
+        Advertised {
+            name: settings.name,
+            player: server.get(&server.player.index).unwrap_or_default(),
+            mode: self.mode,
+        }
+    }
+
+    /// Make the current settings readable
+    pub fn publish(&self, server: &BleServer<'_>) -> Result<(), Error> {
+        let settings = self.settings.get();
+        let service = &server.config;
+        let mut name = [0; DeviceName::SIZE];
+        settings.name.encode(&mut name);
+        server.set(&service.name, &name)?;
+        publish_name(server, &settings.name)?;
+        server.set(&service.deadzone, &settings.deadzone)?;
+        server.set(&service.curve, &(settings.curve as u8))?;
+        server.set(&service.debounce, &settings.debounce_ms)?;
+        server.set(&service.report_rate, &settings.report_rate_hz)?;
+        server.set(&service.brightness, &settings.brightness.level())?;
+        server.set(&service.volume, &settings.volume.level())?;
+        let mut mapping = [0; ButtonMapping::SIZE];
+        settings.mapping.encode(&mut mapping);
+        server.set(&service.mapping, &mapping)?;
+        server.set(&service.hid_mode, &(settings.hid_mode as u8))?;
+        let mut key_map = [0; KeyMap::SIZE];
+        settings.key_map.encode(&mut key_map);
+        server.set(&service.key_map, &key_map)?;
+        server.set(&service.turbo, &settings.turbo.0)?;
+        let mut profile_name = [0; ProfileName::SIZE];
+        settings.profile_name.encode(&mut profile_name);
+        server.set(&service.profile_name, &profile_name)?;
+        server.set(&service.profile, &settings.profile)?;
+        let mut phase = [0; AdvertisingPhase::SIZE];
+        settings.fast_advertising.encode(&mut phase);
+        server.set(&service.fast_advertising, &phase)?;
+        settings.slow_advertising.encode(&mut phase);
+        server.set(&service.slow_advertising, &phase)?;
+        let mut idle = [0; IdleTimeouts::SIZE];
+        settings.idle.encode(&mut idle);
+        server.set(&service.idle_timeouts, &idle)?;
+        server.set(&service.batch_rate, &settings.batch_rate_hz)?;
+        Ok(())
+    }
+
+    /// Take up profile `to`, scroll its name and make its settings readable, restarting if it
+    /// has a different HID mode
+    pub async fn switch_profile(&self, server: &BleServer<'_>, to: u8) {
+        saved::switch_profile(self.settings, to).await;
+        let settings = self.settings.get();
+        info!("[config] profile {} {:?}", to, settings.profile_name);
+        self.restart_for_mode();
+        self.display.scroll_name(settings.profile_name).await;
+        if let Err(e) = self.publish(server) {
+            error::record(&e);
+        }
+    }
+
+    /// Restart if the HID mode in the settings isn't the one the attribute table was built for.
+    /// The services can't be added or removed while running, and hosts only look for them
+    /// again on reconnecting, so the board boots into the saved mode and advertises it afresh.
+    /// Everything must be saved first.
+    fn restart_for_mode(&self) {
+        let mode = self.settings.get().hid_mode;
+        if mode != self.mode {
+            info!("[config] restarting as {:?}", mode);
+            error::reset();
+        }
+    }
+
+    /// Move on to the next profile each time the chord is held
+    pub async fn switch_on_chord(&self, server: &BleServer<'_>, chord: &Chord) -> Infallible {
+        loop {
+            chord.wait().await;
+            let next = profile::next(self.settings.get().profile);
+            self.switch_profile(server, next).await;
+        }
+    }
+
+    /// Apply and save what was written at `handle`, if it's a config characteristic
+    pub async fn written(&self, server: &BleServer<'_>, handle: u16) {
+        let Some(setting) = read_setting(server, handle) else {
+            return;
+        };
+        info!("[config] {:?}", setting);
+        if let Setting::Profile(to) = setting {
+            // the rest of the profile comes with it
+            self.switch_profile(server, to).await;
+            return;
+        }
+        let mut settings = self.settings.get();
+        settings.apply(setting);
+        self.settings.set(settings);
+        match setting {
+            Setting::Name(_) => {
+                // written through one characteristic, read back through both
+                if let Err(e) = self.publish(server) {
+                    error::record(&e);
+                }
+                READVERTISE.signal(());
+            }
+            Setting::Brightness(_) => self.display.set_brightness(settings.brightness).await,
+            Setting::Volume(_) => self.speaker.set_volume(settings.volume).await,
+            // the input tasks pick up the rest as they go, also for the profile in use
+            _ => {}
+        }
+        saved::save_setting(&setting).await;
+        if let Setting::HidMode(_) = setting {
+            self.restart_for_mode();
+        }
+    }
+}
+
+/// The setting just written at `handle`, already checked when it was written
+fn read_setting(server: &BleServer<'_>, handle: u16) -> Option<Setting> {
+    if handle == server.gap.device_name.handle {
+        let name = server.get(&server.gap.device_name).ok()?;
+        return gap::device_name(&name).map(Setting::Name);
+    }
+    let service = &server.config;
+    let mut bytes = [0; gamepad_protocol::value::MAX_SIZE];
+    let (characteristic, len) = if handle == service.name.handle {
+        bytes[..DeviceName::SIZE].copy_from_slice(&server.get(&service.name).ok()?);
+        (config::NAME, DeviceName::SIZE)
+    } else if handle == service.deadzone.handle {
+        server.get(&service.deadzone).ok()?.encode(&mut bytes);
+        (config::DEADZONE, u16::SIZE)
+    } else if handle == service.mapping.handle {
+        bytes[..ButtonMapping::SIZE].copy_from_slice(&server.get(&service.mapping).ok()?);
+        (config::MAPPING, ButtonMapping::SIZE)
+    } else if handle == service.key_map.handle {
+        bytes[..KeyMap::SIZE].copy_from_slice(&server.get(&service.key_map).ok()?);
+        (config::KEY_MAP, KeyMap::SIZE)
+    } else if handle == service.profile_name.handle {
+        bytes[..ProfileName::SIZE].copy_from_slice(&server.get(&service.profile_name).ok()?);
+        (config::PROFILE_NAME, ProfileName::SIZE)
+    } else if let Some((characteristic, phase)) = [
+        (config::FAST_ADVERTISING, &service.fast_advertising),
+        (config::SLOW_ADVERTISING, &service.slow_advertising),
+    ]
+    .into_iter()
+    .find(|(_, c)| c.handle == handle)
+    {
+        bytes[..AdvertisingPhase::SIZE].copy_from_slice(&server.get(phase).ok()?);
+        (characteristic, AdvertisingPhase::SIZE)
+    } else if handle == service.idle_timeouts.handle {
+        bytes[..IdleTimeouts::SIZE].copy_from_slice(&server.get(&service.idle_timeouts).ok()?);
+        (config::IDLE_TIMEOUTS, IdleTimeouts::SIZE)
+    } else {
+        let (characteristic, value) = [
+            (config::CURVE, &service.curve),
+            (config::DEBOUNCE, &service.debounce),
+            (config::REPORT_RATE, &service.report_rate),
+            (config::BRIGHTNESS, &service.brightness),
+            (config::VOLUME, &service.volume),
+            (config::HID_MODE, &service.hid_mode),
+            (config::TURBO, &service.turbo),
+            (config::PROFILE, &service.profile),
+            (config::BATCH_RATE, &service.batch_rate),
+        ]
+        .into_iter()
+        .find(|(_, c)| c.handle == handle)?;
+        bytes[0] = server.get(value).ok()?;
+        (characteristic, u8::SIZE)
+    };
+    Setting::decode(characteristic, &bytes[..len]).ok()
+}
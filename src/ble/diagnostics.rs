@@ -0,0 +1,195 @@
+use defmt::{info, warn};
+use embassy_time::{Duration, Timer};
+use gamepad_core::{error::ErrorRecord, hal::Link};
+use gamepad_protocol::{gatt, ConnectionParams, ConnectionStatus, LinkStatus, Phy, Value};
+use trouble_host::prelude::*;
+
+use crate::error::{self, Error};
+
+use super::{uuid, BleController, BleServer, L2CAP_MTU};
+
+/// Air time of the longest data length extension packet on the 1M PHY, which the controller
+/// needs along with the length
+const MAX_TX_TIME_US: u16 = 2120;
+
+/// Time for the central to exchange MTUs
+const LINK_SETTLE: Duration = Duration::from_millis(500);
+
+/// Health of the controller, for support and debugging
+#[gatt_service(uuid = uuid(gatt::diagnostics::SERVICE))]
+pub struct DiagnosticsService {
+    /// The last error recorded, kept across resets, see [`ErrorRecord::to_bytes`].
+    /// All zeros if there hasn't been one.
+    #[characteristic(uuid = uuid(gatt::diagnostics::LAST_ERROR), read)]
+    pub last_error: [u8; ErrorRecord::SIZE],
+    /// See [`ConnectionStatus`]
+    #[characteristic(uuid = uuid(gatt::diagnostics::CONNECTION), read)]
+    pub connection: [u8; ConnectionStatus::SIZE],
+    /// See [`LinkStatus`]
+    #[characteristic(uuid = uuid(gatt::diagnostics::LINK), read)]
+    pub link: [u8; LinkStatus::SIZE],
+}
+
+/// What the `connection` characteristic shows before a central has been asked for anything
+const NOT_CONNECTED: ConnectionStatus = ConnectionStatus {
+    requested: ConnectionParams {
+        interval_min: 0,
+        interval_max: 0,
+        latency: 0,
+        supervision_timeout: 0,
+    },
+    accepted: false,
+    interval: 0,
+    latency: 0,
+    supervision_timeout: 0,
+};
+
+/// Make the latest error record readable
+pub fn publish_last_error(server: &BleServer<'_>) -> Result<(), Error> {
+    let bytes = error::last_error().map_or([0; ErrorRecord::SIZE], |record| record.to_bytes());
+    server.set(&server.diagnostics.last_error, &bytes)?;
+    Ok(())
+}
+
+/// Clear what the last primary central's link settled on, for a new one to fill in
+pub fn reset_link_status(server: &BleServer<'_>) -> Result<(), Error> {
+    let mut bytes = [0; ConnectionStatus::SIZE];
+    NOT_CONNECTED.encode(&mut bytes);
+    server.set(&server.diagnostics.connection, &bytes)?;
+    let mut bytes = [0; LinkStatus::SIZE];
+    LinkStatus::DEFAULT.encode(&mut bytes);
+    server.set(&server.diagnostics.link, &bytes)?;
+    Ok(())
+}
+
+/// The connection interval the primary central's link settled on, zero until it has
+pub fn connection_interval(server: &BleServer<'_>) -> Duration {
+    let status = server
+        .get(&server.diagnostics.connection)
+        .ok()
+        .and_then(|bytes| ConnectionStatus::decode(&bytes).ok())
+        .unwrap_or(NOT_CONNECTED);
+    Duration::from_micros(status.interval_us().into())
+}
+
+/// Change what the `connection` characteristic shows
+fn update_connection(
+    server: &BleServer<'_>,
+    update: impl FnOnce(&mut ConnectionStatus),
+) -> Result<(), Error> {
+    let mut bytes = server.get(&server.diagnostics.connection)?;
+    let mut status = ConnectionStatus::decode(&bytes).unwrap_or(NOT_CONNECTED);
+    update(&mut status);
+    status.encode(&mut bytes);
+    server.set(&server.diagnostics.connection, &bytes)?;
+    Ok(())
+}
+
+/// Show the parameters the primary central's link changed to, whether the gamepad asked for
+/// them or not
+pub fn connection_params_updated(
+    server: &BleServer<'_>,
+    interval: Duration,
+    latency: u16,
+    supervision_timeout: Duration,
+) -> Result<(), Error> {
+    update_connection(server, |status| {
+        status.interval = (interval.as_micros() / 1250) as u16;
+        status.latency = latency;
+        status.supervision_timeout = (supervision_timeout.as_millis() / 10) as u16;
+        info!("[link] connection parameters now {:?}", status);
+    })
+}
+
+/// Change what the `link` characteristic shows
+fn update_link(server: &BleServer<'_>, update: impl FnOnce(&mut LinkStatus)) -> Result<(), Error> {
+    let mut bytes = server.get(&server.diagnostics.link)?;
+    let mut status = LinkStatus::decode(&bytes).unwrap_or_default();
+    update(&mut status);
+    info!("[link] now {:?}", status);
+    status.encode(&mut bytes);
+    server.set(&server.diagnostics.link, &bytes)?;
+    Ok(())
+}
+
+/// Show the PHY the primary central's link now sends on, from its PHY Update Complete event
+pub fn phy_updated(server: &BleServer<'_>, tx_phy: PhyKind) -> Result<(), Error> {
+    let phy = match tx_phy {
+        PhyKind::Le1M => Phy::Le1M,
+        PhyKind::Le2M => Phy::Le2M,
+        PhyKind::LeCoded | PhyKind::LeCodedS2 => Phy::Coded,
+    };
+    update_link(server, |status| status.phy = phy)
+}
+
+/// Show the longest packets the primary central's link now carries, from its Data Length
+/// Change event
+pub fn data_length_updated(server: &BleServer<'_>, max_tx_octets: u16) -> Result<(), Error> {
+    update_link(server, |status| status.max_tx_octets = max_tx_octets)
+}
+
+/// Asks the connected central for connection parameters, and shows the outcome in the
+/// diagnostics service
+pub struct GattLink<'a> {
+    pub server: &'a BleServer<'a>,
+    pub conn: &'a Connection<'a>,
+    pub stack: Stack<'a, BleController>,
+}
+
+impl GattLink<'_> {
+    /// Ask for the 2M PHY and the longest packets, then publish the MTU. The central starts
+    /// the MTU exchange, and the host answers with the largest MTU [`L2CAP_MTU`] allows. The
+    /// PHY and packet length are published by [`phy_updated`] and [`data_length_updated`] as
+    /// the controller reports them, so anything the central turns down stays at its default.
+    pub async fn negotiate(&self) {
+        if let Err(e) = self.conn.set_phy(self.stack, PhyKind::Le2M).await {
+            warn!("[link] couldn't ask for the 2M PHY: {:?}", e);
+        }
+        let octets = L2CAP_MTU as u16;
+        if let Err(e) = self
+            .conn
+            .update_data_length(self.stack, octets, MAX_TX_TIME_US)
+            .await
+        {
+            warn!("[link] couldn't ask for longer packets: {:?}", e);
+        }
+        Timer::after(LINK_SETTLE).await;
+        let att_mtu = self.conn.att_mtu();
+        if let Err(e) = update_link(self.server, |status| status.att_mtu = att_mtu) {
+            error::record(&e);
+        }
+    }
+}
+
+impl Link for GattLink<'_> {
+    async fn request_params(&self, params: ConnectionParams) {
+        let request = ConnectParams {
+            min_connection_interval: Duration::from_micros(params.interval_min_us().into()),
+            max_connection_interval: Duration::from_micros(params.interval_max_us().into()),
+            max_latency: params.latency,
+            event_length: Duration::from_secs(0),
+            supervision_timeout: Duration::from_millis(params.supervision_timeout_ms().into()),
+        };
+        let accepted = match self
+            .conn
+            .update_connection_params(self.stack, request)
+            .await
+        {
+            Ok(()) => {
+                info!("[link] asked for {:?}", params);
+                true
+            }
+            Err(e) => {
+                warn!("[link] couldn't ask for {:?}: {:?}", params, e);
+                false
+            }
+        };
+        let requested = update_connection(self.server, |status| {
+            status.requested = params;
+            status.accepted = accepted;
+        });
+        if let Err(e) = requested {
+            error::record(&e);
+        }
+    }
+}
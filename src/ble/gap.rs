@@ -0,0 +1,45 @@
+//! The GAP service. trouble's own keeps the name it was built with and refuses writes, so this
+//! one is built instead, with a Device Name a central can write to rename the controller.
+
+use defmt::warn;
+use gamepad_protocol::{gatt::gap, DeviceName};
+use heapless::Vec;
+use trouble_host::prelude::*;
+
+use crate::error::Error;
+
+use super::{uuid, BleServer};
+
+#[gatt_service(uuid = uuid(gap::SERVICE))]
+pub struct GapService {
+    /// UTF-8, the same name as the config service's, and a write to either renames the controller
+    #[characteristic(uuid = uuid(gap::DEVICE_NAME), read, write, on_write = valid_device_name)]
+    pub device_name: Vec<u8, { gap::NAME_MAX }>,
+    /// Set once from the HID mode the table was built for
+    #[characteristic(uuid = uuid(gap::APPEARANCE), read)]
+    pub appearance: u16,
+}
+
+/// The name in `bytes`, cut short to fit a [`DeviceName`], if it's a name at all
+pub fn device_name(bytes: &[u8]) -> Option<DeviceName> {
+    DeviceName::truncated(core::str::from_utf8(bytes).ok()?)
+}
+
+fn valid_device_name(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
+    match device_name(value) {
+        Some(_) => Ok(()),
+        None => {
+            warn!("[gap] refused device name {:?}", value);
+            Err(())
+        }
+    }
+}
+
+const _: () = assert!(gap::NAME_MAX >= DeviceName::SIZE);
+
+/// Make `name` what centrals read from the Device Name characteristic
+pub fn publish_name(server: &BleServer<'_>, name: &DeviceName) -> Result<(), Error> {
+    let bytes = Vec::from_slice(name.as_str().as_bytes()).unwrap_or_default();
+    server.set(&server.gap.device_name, &bytes)?;
+    Ok(())
+}
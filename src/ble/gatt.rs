@@ -1,20 +1,30 @@
-use super::advertiser::{Advertiser, AdvertiserBuilder};
-use super::{ble_task, mpsl_task, BleResources};
-use super::{hid::*, BleServer};
-use super::{stick::*, BleController};
+//! The GATT server. UUIDs and value encodings come from `gamepad-protocol`, which publishes
+//! them to clients, and every service here and in the service modules is declared with its
+//! constants.
+
+use super::advertiser::{Advertised, Advertiser, AdvertiserBuilder, READVERTISE};
+use super::{ble_task, BleResources};
+use super::{
+    config::*, diagnostics::*, gap::*, hid::*, hid_device::*, motion::*, recording::*, BleServer,
+};
+use super::{stick::*, uuid, BleController};
 use defmt::info;
 use embassy_executor::Spawner;
 use embassy_futures::select::select;
 use embassy_futures::select::Either;
-use microbit_bsp::ble::{MultiprotocolServiceLayer, SoftdeviceError};
+use gamepad_core::{config::Key, scheduler::ReportScheduler};
+use gamepad_protocol::gatt;
 use static_cell::StaticCell;
 use trouble_host::prelude::*;
 use trouble_host::types::gatt_traits::GattValue;
 
+use crate::config;
+use crate::error::{self, Error};
+
 /// Allow a central to decide which player this controller belongs to
-#[gatt_service(uuid = "8f701cf1-b1df-42a1-bb5f-6a1028c793b0")]
+#[gatt_service(uuid = uuid(gatt::player::SERVICE))]
 pub struct Player {
-    #[characteristic(uuid = "e3d1afe4-b414-44e3-be54-0ea26c394eba", read, write, on_write = on_write)]
+    #[characteristic(uuid = uuid(gatt::player::INDEX), read, write, on_write = on_write)]
     index: u8,
 }
 
@@ -25,24 +35,37 @@ fn on_write(_: &Connection<'_>, value: &[u8]) -> Result<(), ()> {
     Ok(())
 }
 
-#[gatt_server(attribute_data_size = 100)]
+#[gatt_server(attribute_data_size = 544)]
 pub struct Server {
-    // pub bas: BatteryService,
+    pub gap: GapService,
     pub hid: ButtonService,
     pub stick: StickService,
     pub player: Player,
+    pub heading: HeadingService,
+    pub gesture: GestureService,
+    pub motion: MotionService,
+    pub diagnostics: DiagnosticsService,
+    pub recording: RecordingService,
+    pub config: ConfigService,
 }
 
 impl Server<'static, 'static, BleController> {
+    /// Start the host and GATT server, with the name and appearance in `advertised`. The
+    /// appearance stays that of the HID mode the table was built for, see [`GapService`], and
+    /// the HID services are only in the table, and returned, in the HID modes.
     pub fn start_gatt(
-        name: &'static str,
+        advertised: Advertised,
         spawner: Spawner,
         controller: BleController,
-        mpsl: &'static MultiprotocolServiceLayer<'static>,
-    ) -> Result<(&'static Self, Advertiser<'static, BleController>), BleHostError<SoftdeviceError>>
-    {
-        spawner.must_spawn(mpsl_task(mpsl));
-
+    ) -> Result<
+        (
+            &'static Self,
+            Option<&'static HidServices>,
+            Advertiser<'static, BleController>,
+            Stack<'static, BleController>,
+        ),
+        Error,
+    > {
         let address = Address::random([0x42, 0x5A, 0xE3, 0x1E, 0x83, 0xE7]);
         info!("Our address = {:?}", address);
 
@@ -53,28 +76,43 @@ impl Server<'static, 'static, BleController> {
         let (stack, peripheral, _, runner) = trouble_host::new(controller, resources)
             .set_random_address(address)
             .build();
+        let mut table = AttributeTable::new();
+        let hid = advertised.mode.uses_hid_service().then(|| {
+            static HID: StaticCell<HidServices> = StaticCell::new();
+            &*HID.init(HidServices::new(&mut table))
+        });
         let server = {
             static SERVER: StaticCell<BleServer<'_>> = StaticCell::new();
-            SERVER.init(
-                Server::new_with_config(
-                    stack,
-                    GapConfig::Peripheral(PeripheralConfig {
-                        name,
-                        appearance: &appearance::GAMEPAD,
-                    }),
-                )
-                .expect("Error creating Gatt Server"),
-            )
+            SERVER.init(Server::new(stack, table))
         };
+        if let Some(hid) = hid {
+            hid.publish(server)?;
+        }
+        publish_name(server, &advertised.name)?;
+        server.set(&server.gap.appearance, &advertised.mode.appearance())?;
+        server.set(&server.motion.rate, &DEFAULT_ORIENTATION_RATE_HZ)?;
+        publish_last_error(server)?;
         info!("Starting Gatt Server");
         spawner.must_spawn(ble_task(runner));
-        let advertiser = AdvertiserBuilder::new(name, peripheral).build()?;
-        Ok((server, advertiser))
+        let advertiser = AdvertiserBuilder::new(advertised, peripheral)
+            .build()
+            .map_err(Error::AdvertisingData)?;
+        Ok((server, hid, advertiser, stack))
     }
 }
 
-/// A BLE GATT server
-pub async fn gatt_server_task(server: &BleServer<'_>, conn: &Connection<'_>) {
+/// A BLE GATT server. Changes to the link of the `primary` central are shown in the
+/// diagnostics service, and `scheduler` is paced to the connection interval it settles on.
+pub async fn gatt_server_task(
+    server: &BleServer<'_>,
+    conn: &Connection<'static>,
+    primary: bool,
+    scheduler: Option<&ReportScheduler>,
+    settings: &LiveSettings<'_>,
+) {
+    if settings.mode.uses_hid_service() && !encrypted(conn) {
+        request_encryption(conn);
+    }
     loop {
         if let Either::First(event) = select(conn.next(), server.run()).await {
             match event {
@@ -82,6 +120,40 @@ pub async fn gatt_server_task(server: &BleServer<'_>, conn: &Connection<'_>) {
                     info!("[gatt] Disconnected: {:?}", reason);
                     break;
                 }
+                ConnectionEvent::ConnectionParamsUpdated {
+                    conn_interval,
+                    peripheral_latency,
+                    supervision_timeout,
+                } => {
+                    if let Some(scheduler) = scheduler {
+                        scheduler.follow(conn_interval);
+                    }
+                    if primary {
+                        let updated = connection_params_updated(
+                            server,
+                            conn_interval,
+                            peripheral_latency,
+                            supervision_timeout,
+                        );
+                        if let Err(e) = updated {
+                            error::record(&e);
+                        }
+                    }
+                }
+                ConnectionEvent::PhyUpdated { tx_phy, .. } => {
+                    if primary {
+                        if let Err(e) = phy_updated(server, tx_phy) {
+                            error::record(&e);
+                        }
+                    }
+                }
+                ConnectionEvent::DataLengthUpdated { max_tx_octets, .. } => {
+                    if primary {
+                        if let Err(e) = data_length_updated(server, max_tx_octets) {
+                            error::record(&e);
+                        }
+                    }
+                }
                 ConnectionEvent::Gatt { event, .. } => match event {
                     GattEvent::Read { value_handle } => {
                         if value_handle == server.player.index.handle {
@@ -99,6 +171,30 @@ pub async fn gatt_server_task(server: &BleServer<'_>, conn: &Connection<'_>) {
                                 "[gatt] Write Event to Player Index Characteristic: {:?}",
                                 value
                             );
+                            if let Ok(index) = value {
+                                READVERTISE.signal(());
+                                config::save(Key::PlayerIndex, &index).await;
+                            }
+                        } else if value_handle == server.heading.steering.handle {
+                            let value = server.get(&server.heading.steering);
+                            info!("[gatt] Write Event to Steering Characteristic: {:?}", value);
+                        } else if value_handle == server.heading.calibrate.handle {
+                            if server.get(&server.heading.calibrate).unwrap_or(false) {
+                                info!("[gatt] Compass calibration requested");
+                                CALIBRATION_REQUESTED.signal(());
+                            }
+                        } else if value_handle == server.motion.rate.handle {
+                            let value = server.get(&server.motion.rate);
+                            info!(
+                                "[gatt] Write Event to Orientation Rate Characteristic: {:?}",
+                                value
+                            );
+                        } else if value_handle == server.recording.control.handle {
+                            if let Err(e) = control_written(server, conn) {
+                                error::record(&e);
+                            }
+                        } else {
+                            settings.written(server, value_handle).await;
                         }
                     }
                 },
@@ -0,0 +1,140 @@
+//! The standard HID service, for keyboard and mouse and media remote mode, with the Device
+//! Information and Battery services hosts look for alongside it. None of them are in the
+//! attribute table in gamepad mode, so hosts don't take the gamepad for a keyboard. The report
+//! map and report layouts come from `gamepad_protocol::hid`, see [`gamepad_core::hid`] for
+//! what the buttons and stick do with them.
+
+use defmt::warn;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use gamepad_core::hal::HidOutput;
+use gamepad_protocol::{
+    gatt,
+    hid::{self, ConsumerReport, HidReport, KeyboardReport, MouseReport},
+    Value,
+};
+use microbit_bsp::ble::SoftdeviceError;
+use trouble_host::prelude::*;
+
+use crate::error::Error;
+
+use super::{centrals::notify_where, uuid, BleCentrals, BleServer};
+
+/// Input reports are told apart by a Report Reference descriptor, their report ID then 1 for
+/// input. They only go out over an encrypted link, see [`request_encryption`].
+#[gatt_service(uuid = uuid(gatt::hid_device::SERVICE))]
+pub struct HidDeviceService {
+    #[characteristic(uuid = uuid(gatt::hid_device::INFORMATION), read)]
+    pub information: [u8; 4],
+    #[characteristic(uuid = uuid(gatt::hid_device::REPORT_MAP), read)]
+    pub report_map: [u8; hid::REPORT_MAP_LEN],
+    /// Suspend and exit suspend, which the gamepad has no use for
+    #[characteristic(uuid = uuid(gatt::hid_device::CONTROL_POINT), write_without_response)]
+    pub control_point: u8,
+    #[characteristic(uuid = uuid(gatt::hid_device::REPORT), read, notify)]
+    #[descriptor(uuid = uuid(gatt::hid_device::REPORT_REFERENCE), read, value = [hid::KEYBOARD.id, 1])]
+    pub keyboard: [u8; KeyboardReport::SIZE],
+    #[characteristic(uuid = uuid(gatt::hid_device::REPORT), read, notify)]
+    #[descriptor(uuid = uuid(gatt::hid_device::REPORT_REFERENCE), read, value = [hid::MOUSE.id, 1])]
+    pub mouse: [u8; MouseReport::SIZE],
+    #[characteristic(uuid = uuid(gatt::hid_device::REPORT), read, notify)]
+    #[descriptor(uuid = uuid(gatt::hid_device::REPORT_REFERENCE), read, value = [hid::CONSUMER.id, 1])]
+    pub consumer: [u8; ConsumerReport::SIZE],
+}
+
+#[gatt_service(uuid = uuid(gatt::device_information::SERVICE))]
+pub struct DeviceInformationService {
+    #[characteristic(uuid = uuid(gatt::device_information::PNP_ID), read)]
+    pub pnp_id: [u8; hid::PNP_ID.len()],
+}
+
+#[gatt_service(uuid = uuid(gatt::battery::SERVICE))]
+pub struct BatteryService {
+    /// Always full, the board can't measure its supply
+    #[characteristic(uuid = uuid(gatt::battery::LEVEL), read)]
+    pub level: u8,
+}
+
+/// The services that make the gamepad a HID device, added to the attribute table ahead of the
+/// rest in the HID modes
+pub struct HidServices {
+    pub hid_device: HidDeviceService,
+    pub device_information: DeviceInformationService,
+    pub battery: BatteryService,
+}
+
+impl HidServices {
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self {
+            hid_device: HidDeviceService::new(table),
+            device_information: DeviceInformationService::new(table),
+            battery: BatteryService::new(table),
+        }
+    }
+
+    /// Fill in the information, report map, PnP ID and battery level, which never change
+    pub fn publish(&self, server: &BleServer<'_>) -> Result<(), Error> {
+        server.set(&self.hid_device.information, &hid::INFORMATION)?;
+        server.set(&self.hid_device.report_map, &hid::REPORT_MAP)?;
+        server.set(&self.device_information.pnp_id, &hid::PNP_ID)?;
+        server.set(&self.battery.level, &100)?;
+        Ok(())
+    }
+}
+
+/// Ask `conn` to pair, as hosts only take HID reports over an encrypted link. The central
+/// picks how, and reports wait until it has.
+pub fn request_encryption(conn: &Connection<'_>) {
+    if let Err(e) = conn.request_security() {
+        warn!("[hid] couldn't ask to pair: {:?}", e);
+    }
+}
+
+/// Whether reports can go to `conn`
+pub fn encrypted(conn: &Connection<'_>) -> bool {
+    conn.security_level().is_ok_and(|level| level.encrypted())
+}
+
+/// Sends keyboard, mouse and consumer reports as notifications on their report characteristics,
+/// to every connected central whose link is encrypted
+pub struct GattHidOutput<'a> {
+    pub server: &'a BleServer<'static>,
+    /// Missing in gamepad mode, when nothing sends HID reports
+    pub services: Option<&'a HidServices>,
+    pub centrals: &'a BleCentrals,
+}
+
+impl HidOutput for GattHidOutput<'_> {
+    type Error = BleHostError<SoftdeviceError>;
+
+    async fn send(&self, report: HidReport) -> Result<(), Self::Error> {
+        let Self {
+            server,
+            services,
+            centrals,
+        } = self;
+        let Some(HidServices {
+            hid_device: service,
+            ..
+        }) = services
+        else {
+            return Ok(());
+        };
+        match report {
+            HidReport::Keyboard(report) => {
+                let mut bytes = [0; KeyboardReport::SIZE];
+                report.encode(&mut bytes);
+                notify_where(server, centrals, &service.keyboard, &bytes, encrypted).await
+            }
+            HidReport::Mouse(report) => {
+                let mut bytes = [0; MouseReport::SIZE];
+                report.encode(&mut bytes);
+                notify_where(server, centrals, &service.mouse, &bytes, encrypted).await
+            }
+            HidReport::Consumer(report) => {
+                let mut bytes = [0; ConsumerReport::SIZE];
+                report.encode(&mut bytes);
+                notify_where(server, centrals, &service.consumer, &bytes, encrypted).await
+            }
+        }
+    }
+}
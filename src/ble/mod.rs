@@ -1,33 +1,75 @@
 pub mod advertiser;
+pub mod centrals;
+pub mod config;
+pub mod diagnostics;
+pub mod gap;
 pub mod gatt;
 pub mod hid;
+pub mod hid_device;
+pub mod motion;
+pub mod recording;
 pub mod stick;
 
+use defmt::info;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+use gamepad_core::centrals::Centrals;
 use microbit_bsp::ble::{MultiprotocolServiceLayer, SoftdeviceController};
 use trouble_host::prelude::*;
 
-/// Size of L2CAP packets (ATT MTU is this - 4)
+use crate::error::{self, Error};
+
+/// Size of L2CAP packets (ATT MTU is this - 4), also the longest link layer payload asked for
 const L2CAP_MTU: usize = 251;
 
-/// Max number of connections
-const CONNECTIONS_MAX: usize = 1;
+/// Max number of connections, the primary central and its companions
+const CONNECTIONS_MAX: usize = 2;
+
+/// Centrals that can join while the primary is connected
+pub const COMPANIONS_MAX: usize = CONNECTIONS_MAX - 1;
 
 /// Max number of L2CAP channels.
-const L2CAP_CHANNELS_MAX: usize = 2; // Signal + att
+const L2CAP_CHANNELS_MAX: usize = 2 * CONNECTIONS_MAX; // Signal + att for each connection
 
 pub type BleServer<'d> = gatt::Server<'d, 'd, SoftdeviceController<'d>>;
 
 pub type BleController = SoftdeviceController<'static>;
 
+pub type BleCentrals = Centrals<Connection<'static>, COMPANIONS_MAX>;
+
 pub type BleResources =
     HostResources<BleController, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX, L2CAP_MTU>;
 
+/// A UUID from `gamepad_protocol::gatt` as trouble takes it, in the short form for those the
+/// Bluetooth SIG assigns. Every service declares its UUIDs through this, so they can't drift
+/// from what clients are told.
+pub const fn uuid(uuid: gamepad_protocol::Uuid) -> Uuid {
+    match uuid.to_u16() {
+        Some(short) => Uuid::new_short(short),
+        None => Uuid::new_long(uuid.to_le_bytes()),
+    }
+}
+
 #[embassy_executor::task]
 pub async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) -> ! {
     mpsl.run().await;
 }
 
+/// Ask the BLE host runner to start over
+pub static RESTART_STACK: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Runs the BLE host, restarting it if it fails or is asked to
 #[embassy_executor::task]
 async fn ble_task(mut runner: Runner<'static, BleController>) {
-    runner.run().await.expect("Error in BLE task");
+    loop {
+        match select(runner.run(), RESTART_STACK.wait()).await {
+            Either::First(Ok(())) => info!("[ble] runner finished"),
+            Either::First(Err(e)) => {
+                error::record(&Error::Runner(e));
+            }
+            Either::Second(()) => info!("[ble] restarting runner"),
+        }
+        Timer::after(Duration::from_secs(1)).await;
+    }
 }
@@ -0,0 +1,189 @@
+use defmt::info;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, signal::Signal};
+use embassy_time::{Duration, Instant, Timer};
+use gamepad_core::{
+    config::Key,
+    controller::Event,
+    display::DisplayFrame,
+    fusion::Fusion,
+    gesture::{Gesture, GestureDetector, SAMPLE_RATE_HZ},
+    hal::Display5x5,
+    hid::{KeyboardMouse, MediaRemote},
+};
+use gamepad_protocol::{gatt, Orientation, RawInput, Value};
+use microbit_bsp::ble::SoftdeviceError;
+use trouble_host::prelude::*;
+
+use crate::config;
+use crate::io::{
+    display::AsyncDisplay,
+    motion::{
+        compass::{heading_delta, heading_to_axis, CalibrationRoutine, Compass},
+        MotionSensor,
+    },
+    recording,
+};
+
+use super::{centrals::notify_all, hid_device::GattHidOutput, uuid, BleCentrals, BleServer};
+
+/// Compass heading, optionally steering an axis of its own
+#[gatt_service(uuid = uuid(gatt::heading::SERVICE))]
+pub struct HeadingService {
+    /// Heading in degrees, 0..=359
+    #[characteristic(uuid = uuid(gatt::heading::HEADING), read, notify)]
+    pub heading: u16,
+    /// When set, the heading relative to where the controller pointed when enabled drives `axis`
+    #[characteristic(uuid = uuid(gatt::heading::STEERING), read, write)]
+    pub steering: bool,
+    /// The steering level, apart from the stick so neither overwrites the other
+    #[characteristic(uuid = uuid(gatt::heading::AXIS), read, notify)]
+    pub axis: i8,
+    /// Write true to start the figure-eight calibration routine
+    #[characteristic(uuid = uuid(gatt::heading::CALIBRATE), read, write)]
+    pub calibrate: bool,
+}
+
+/// Discrete motion events, see [`Gesture`] for the values
+#[gatt_service(uuid = uuid(gatt::gesture::SERVICE))]
+pub struct GestureService {
+    #[characteristic(uuid = uuid(gatt::gesture::GESTURE), read, notify)]
+    pub gesture: u8,
+}
+
+/// Fused orientation for pointing and VR style input
+#[gatt_service(uuid = uuid(gatt::motion::SERVICE))]
+pub struct MotionService {
+    /// See [`Orientation`] for the encoding
+    #[characteristic(uuid = uuid(gatt::motion::ORIENTATION), read, notify)]
+    pub orientation: [u8; Orientation::SIZE],
+    /// Orientation notifications per second, 0 to disable
+    #[characteristic(uuid = uuid(gatt::motion::RATE), read, write)]
+    pub rate: u8,
+}
+
+/// Signalled when a central writes true to the calibrate characteristic, which is set back to
+/// false once the routine is over
+pub static CALIBRATION_REQUESTED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Orientation notification rate until a central asks for something else
+pub const DEFAULT_ORIENTATION_RATE_HZ: u8 = 10;
+
+/// How quickly the orientation follows the sensors, in seconds
+const FUSION_TIME_CONSTANT: f32 = 0.25;
+
+/// Give up on calibration if the user hasn't covered enough range in this time
+const CALIBRATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Guide the user through the figure-eight calibration, showing progress on the LED matrix
+pub async fn calibrate(sensor: &mut MotionSensor, compass: &mut Compass, display: &AsyncDisplay) {
+    info!("[motion] compass calibration started");
+    let mut routine = CalibrationRoutine::default();
+    let deadline = Instant::now() + CALIBRATION_TIMEOUT;
+    while !routine.is_complete() {
+        if Instant::now() > deadline {
+            info!("[motion] compass calibration timed out");
+            display
+                .display(DisplayFrame::Sad, Duration::from_secs(1))
+                .await;
+            return;
+        }
+        if let Some(sample) = sensor.sample() {
+            routine.add(sample.mag);
+        }
+        display
+            .display(
+                DisplayFrame::DisplayFrame(routine.progress_frame()),
+                Duration::from_millis(20),
+            )
+            .await;
+        Timer::after(Duration::from_millis(20)).await;
+    }
+    let calibration = routine.finish();
+    info!("[motion] compass calibrated: {:?}", calibration);
+    compass.set_calibration(calibration);
+    config::set(Key::CompassCalibration, &calibration.to_bytes()).await;
+    display
+        .display(DisplayFrame::Smile, Duration::from_secs(1))
+        .await;
+}
+
+/// Report motion until something happens that the controller needs to act on. A shake plays
+/// or pauses `remote` when there is one, rather than letting go of the host, and tilting the
+/// board aims `pointer` when there is one.
+pub async fn motion_task(
+    server: &BleServer<'_>,
+    centrals: &BleCentrals,
+    sensor: &mut MotionSensor,
+    compass: &Compass,
+    remote: Option<&MediaRemote<'_, GattHidOutput<'_>>>,
+    pointer: Option<&KeyboardMouse<'_, GattHidOutput<'_>>>,
+) -> Result<Event, BleHostError<SoftdeviceError>> {
+    let period = Duration::from_hz(SAMPLE_RATE_HZ as u64);
+    info!("motion service online");
+    let mut gestures = GestureDetector::new();
+    let mut fusion = Fusion::new(SAMPLE_RATE_HZ, FUSION_TIME_CONSTANT);
+    let mut since_orientation = 0;
+    let mut last_heading: Option<u16> = None;
+    let mut steering_reference: Option<u16> = None;
+    let mut last_axis = 0;
+    loop {
+        if let Some(sample) = sensor.sample() {
+            let accel = sample
+                .accel
+                .map(|a| a.clamp(i16::MIN.into(), i16::MAX.into()) as i16);
+            recording::record(RawInput::Accel(accel));
+            if let Some(pointer) = pointer {
+                pointer.tilt(sample.accel);
+            }
+            if let Some(gesture) = gestures.update(sample.accel) {
+                info!("[motion] gesture {:?}", gesture);
+                notify_all(server, centrals, &server.gesture.gesture, &(gesture as u8)).await?;
+                match (gesture, remote) {
+                    (Gesture::Shake, Some(remote)) => remote.play_pause().await?,
+                    // shake to drop this host and advertise for a new one
+                    (Gesture::Shake, None) => return Ok(Event::Shaken),
+                    _ => {}
+                }
+            }
+            let orientation =
+                fusion.update(sample.accel.map(|a| a as f32), compass.field(sample.mag));
+            let rate = server
+                .get(&server.motion.rate)
+                .unwrap_or(0)
+                .min(SAMPLE_RATE_HZ as u8);
+            since_orientation += 1;
+            if rate > 0 && since_orientation >= SAMPLE_RATE_HZ / rate as u32 {
+                since_orientation = 0;
+                let mut bytes = [0; Orientation::SIZE];
+                Orientation::from_q14(orientation.to_q14()).encode(&mut bytes);
+                notify_all(server, centrals, &server.motion.orientation, &bytes).await?;
+            }
+            let heading = compass.heading(&sample);
+            // ignore single degree jitter
+            let moved = last_heading.map_or(true, |last| heading_delta(heading, last).abs() > 1);
+            if moved {
+                last_heading = Some(heading);
+                notify_all(server, centrals, &server.heading.heading, &heading).await?;
+            }
+            if server.get(&server.heading.steering).unwrap_or(false) {
+                let reference = *steering_reference.get_or_insert(heading);
+                let axis = heading_to_axis(heading, reference);
+                if axis != last_axis {
+                    last_axis = axis;
+                    notify_all(server, centrals, &server.heading.axis, &axis).await?;
+                }
+            } else {
+                steering_reference = None;
+                if last_axis != 0 {
+                    last_axis = 0;
+                    notify_all(server, centrals, &server.heading.axis, &0).await?;
+                }
+            }
+        }
+        if let Either::Second(()) = select(Timer::after(period), CALIBRATION_REQUESTED.wait()).await
+        {
+            return Ok(Event::CalibrationRequested);
+        }
+    }
+}
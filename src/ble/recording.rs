@@ -0,0 +1,106 @@
+use defmt::{info, warn};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, signal::Signal};
+use gamepad_protocol::{gatt, RecordingControl, Sample, Value};
+use microbit_bsp::ble::SoftdeviceError;
+use trouble_host::prelude::*;
+
+use crate::{
+    error::{self, Error},
+    io::recording,
+};
+
+use super::{centrals::notify_where, uuid, BleCentrals, BleServer};
+
+/// Raw inputs kept in RAM, for reproducing problems on a host
+#[gatt_service(uuid = uuid(gatt::recording::SERVICE))]
+pub struct RecordingService {
+    /// See [`RecordingControl`], write 1 to start recording and 2 to download
+    #[characteristic(uuid = uuid(gatt::recording::CONTROL), read, write)]
+    pub control: u8,
+    /// How many samples are held
+    #[characteristic(uuid = uuid(gatt::recording::LENGTH), read, notify)]
+    pub length: u16,
+    /// Each held sample in turn while downloading, see [`Sample`] for the encoding
+    #[characteristic(uuid = uuid(gatt::recording::SAMPLE), read, notify)]
+    pub sample: [u8; Sample::SIZE],
+}
+
+/// Samples copied out of the recorder at a time, so recording isn't held up for long
+const CHUNK: usize = 32;
+
+/// The central that asked for the recording, for [`download_task`] to send it to
+static DOWNLOAD: Signal<ThreadModeRawMutex, Connection<'static>> = Signal::new();
+
+/// Do what the central wrote to the control characteristic
+pub fn control_written(server: &BleServer<'_>, conn: &Connection<'static>) -> Result<(), Error> {
+    let control = server.get(&server.recording.control)?;
+    let state = match RecordingControl::decode(&[control]) {
+        Ok(RecordingControl::Recording) => {
+            info!("[recording] started");
+            recording::start();
+            RecordingControl::Recording
+        }
+        Ok(RecordingControl::Stopped) => {
+            info!("[recording] stopped");
+            recording::stop();
+            RecordingControl::Stopped
+        }
+        // stopped again by the download task once it's sent
+        Ok(RecordingControl::Downloading) => {
+            recording::stop();
+            DOWNLOAD.signal(conn.clone());
+            RecordingControl::Downloading
+        }
+        Err(_) => {
+            warn!("[recording] unknown control value {}", control);
+            recording::stop();
+            RecordingControl::Stopped
+        }
+    };
+    server.set(&server.recording.control, &(state as u8))?;
+    Ok(())
+}
+
+/// Send the recording to each central that asks for it. This runs apart from the GATT server
+/// tasks, so they carry on handling writes while the samples go out.
+#[embassy_executor::task]
+pub async fn download_task(server: &'static BleServer<'static>, centrals: &'static BleCentrals) {
+    loop {
+        let conn = DOWNLOAD.wait().await;
+        if let Err(e) = download(server, centrals, &conn).await {
+            error::record(&Error::Notify(e));
+        }
+        let stopped = RecordingControl::Stopped as u8;
+        if let Err(e) = server.set(&server.recording.control, &stopped) {
+            error::record(&e.into());
+        }
+    }
+}
+
+/// Notify the length, then every sample oldest first, to `conn` if it subscribed
+async fn download(
+    server: &BleServer<'_>,
+    centrals: &BleCentrals,
+    conn: &Connection<'_>,
+) -> Result<(), BleHostError<SoftdeviceError>> {
+    let asked = |central: &Connection<'_>| central.handle() == conn.handle();
+    let length = recording::len();
+    info!("[recording] downloading {} samples", length);
+    let service = &server.recording;
+    notify_where(server, centrals, &service.length, &(length as u16), asked).await?;
+    let mut bytes = [0; Sample::SIZE];
+    let mut sent = 0;
+    while sent < length {
+        let chunk = recording::chunk::<CHUNK>(sent);
+        // cleared by a new recording meanwhile
+        if chunk.is_empty() {
+            break;
+        }
+        for sample in &chunk {
+            sample.encode(&mut bytes);
+            notify_where(server, centrals, &service.sample, &bytes, asked).await?;
+        }
+        sent += chunk.len();
+    }
+    Ok(())
+}
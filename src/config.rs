@@ -0,0 +1,110 @@
+//! Settings saved in the config store, for whichever task changes them. Failures are recorded
+//! and otherwise ignored: the controller works without saved settings, just forgetfully.
+
+use core::cell::Cell;
+
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use gamepad_core::{
+    config::{migration, Key, Settings, Store, MAX_VALUE, SCHEMA_VERSION},
+    profile,
+};
+use gamepad_protocol::{Setting, Value};
+use heapless::Vec;
+use microbit_bsp::ble::MultiprotocolServiceLayer;
+
+use crate::{
+    error::{self, Error},
+    io::flash::ConfigFlash,
+};
+
+/// `None` until mounted, or if the flash couldn't be read
+static STORE: Mutex<ThreadModeRawMutex, Option<Store<ConfigFlash>>> = Mutex::new(None);
+
+/// Open the store, wiping it first if `factory_reset`. The MPSL task must be running, as it
+/// grants the flash its timeslots.
+pub async fn init(mpsl: &'static MultiprotocolServiceLayer<'static>, factory_reset: bool) {
+    let mounted = async {
+        let mut store = Store::mount(ConfigFlash::take(mpsl), SCHEMA_VERSION, migration()).await?;
+        if factory_reset {
+            store.factory_reset().await?;
+        }
+        Ok(store)
+    };
+    match mounted.await {
+        Ok(store) => *STORE.lock().await = Some(store),
+        Err(e) => {
+            error::record(&Error::Config(e));
+        }
+    }
+}
+
+/// The raw bytes saved under `key`
+pub async fn get(key: Key) -> Option<Vec<u8, MAX_VALUE>> {
+    let mut store = STORE.lock().await;
+    match store.as_mut()?.get(key).await {
+        Ok(value) => value,
+        Err(e) => {
+            error::record(&Error::Config(e));
+            None
+        }
+    }
+}
+
+pub async fn set(key: Key, value: &[u8]) {
+    if let Some(store) = STORE.lock().await.as_mut() {
+        if let Err(e) = store.set(key, value).await {
+            error::record(&Error::Config(e));
+        }
+    }
+}
+
+/// The value saved under `key`, if there is one
+pub async fn load<V: Value>(key: Key) -> Option<V> {
+    V::decode(&get(key).await?).ok()
+}
+
+pub async fn save<V: Value>(key: Key, value: &V) {
+    let mut bytes = [0; MAX_VALUE];
+    value.encode(&mut bytes[..V::SIZE]);
+    set(key, &bytes[..V::SIZE]).await;
+}
+
+/// The saved tunables, or the defaults if there aren't any
+pub async fn settings() -> Settings {
+    let mut store = STORE.lock().await;
+    let Some(store) = store.as_mut() else {
+        return Settings::default();
+    };
+    match Settings::load(store).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            error::record(&Error::Config(e));
+            Settings::default()
+        }
+    }
+}
+
+pub async fn save_setting(setting: &Setting) {
+    if let Some(store) = STORE.lock().await.as_mut() {
+        if let Err(e) = Settings::save(store, setting).await {
+            error::record(&Error::Config(e));
+        }
+    }
+}
+
+/// Take up profile `to` in `settings`, saving the one in use for switching back. Without a
+/// store the other profiles start out fresh each time.
+pub async fn switch_profile(settings: &Cell<Settings>, to: u8) {
+    let mut switched = settings.get();
+    match STORE.lock().await.as_mut() {
+        Some(store) => {
+            if let Err(e) = profile::switch(store, &mut switched, to).await {
+                error::record(&Error::Config(e));
+            }
+        }
+        None => {
+            profile::swap(&mut switched, to, None);
+        }
+    }
+    settings.set(switched);
+}
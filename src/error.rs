@@ -0,0 +1,134 @@
+use core::{
+    mem::MaybeUninit,
+    ptr::{addr_of, addr_of_mut},
+};
+
+use defmt::warn;
+use embassy_time::{Duration, Instant, Timer};
+use gamepad_core::{
+    config::StoreError,
+    controller::Indicator,
+    error::{ErrorCode, ErrorRecord},
+    hal::Display5x5,
+};
+use gamepad_protocol::advertising::TooLong;
+use microbit_bsp::ble::SoftdeviceError;
+use nrf_mpsl::FlashError;
+use trouble_host::BleHostError;
+
+use crate::io::{display::AsyncDisplay, motion::MotionError};
+
+/// Everything that can go wrong in the firmware
+pub enum Error {
+    /// The softdevice controller could not be brought up
+    BleInit,
+    Runner(BleHostError<SoftdeviceError>),
+    Advertising(BleHostError<SoftdeviceError>),
+    /// Something meant for the advertisement or scan response didn't fit
+    AdvertisingData(TooLong),
+    Notify(BleHostError<SoftdeviceError>),
+    Attribute(trouble_host::Error),
+    Motion(MotionError),
+    Config(StoreError<FlashError>),
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::BleInit => ErrorCode::BleInit,
+            Error::Runner(_) => ErrorCode::BleRunner,
+            Error::Advertising(_) | Error::AdvertisingData(_) => ErrorCode::Advertising,
+            Error::Notify(_) => ErrorCode::Notify,
+            Error::Attribute(_) => ErrorCode::Attribute,
+            Error::Motion(_) => ErrorCode::MotionSensor,
+            Error::Config(_) => ErrorCode::Config,
+        }
+    }
+}
+
+impl defmt::Format for Error {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Error::BleInit => defmt::write!(f, "BLE controller failed to initialize"),
+            Error::Runner(e) => defmt::write!(f, "BLE runner: {:?}", e),
+            Error::Advertising(e) => defmt::write!(f, "advertising: {:?}", e),
+            Error::AdvertisingData(e) => defmt::write!(f, "advertising data: {:?}", e),
+            Error::Notify(e) => defmt::write!(f, "notify: {:?}", e),
+            Error::Attribute(e) => defmt::write!(f, "attribute: {:?}", e),
+            Error::Motion(_) => defmt::write!(f, "motion sensor not responding"),
+            Error::Config(e) => defmt::write!(f, "config store: {:?}", e),
+        }
+    }
+}
+
+impl From<trouble_host::Error> for Error {
+    fn from(e: trouble_host::Error) -> Self {
+        Error::Attribute(e)
+    }
+}
+
+impl From<MotionError> for Error {
+    fn from(e: MotionError) -> Self {
+        Error::Motion(e)
+    }
+}
+
+/// Marks [`LAST_ERROR`] as holding a record rather than power-on garbage
+const MAGIC: u32 = 0x4552_5221;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Persisted {
+    magic: u32,
+    record: [u8; ErrorRecord::SIZE],
+}
+
+/// Left alone by the startup code, so it survives a reset but not a power cycle.
+/// Only touched from thread mode tasks, which never preempt each other.
+#[link_section = ".uninit.LAST_ERROR"]
+static mut LAST_ERROR: MaybeUninit<Persisted> = MaybeUninit::uninit();
+
+/// The most recently recorded error, from this boot or before the last reset
+pub fn last_error() -> Option<ErrorRecord> {
+    // SAFETY: any bit pattern is a valid `Persisted`, the magic tells us if it's meaningful
+    let persisted = unsafe { addr_of!(LAST_ERROR).read_volatile().assume_init() };
+    (persisted.magic == MAGIC)
+        .then(|| ErrorRecord::from_bytes(&persisted.record))
+        .flatten()
+}
+
+/// Log an error and remember it as the last one
+pub fn record(error: &Error) -> ErrorCode {
+    let code = error.code();
+    warn!("[error] {:?} ({:?})", error, code);
+    let count = match last_error() {
+        Some(last) if last.code == code => last.count.saturating_add(1),
+        _ => 1,
+    };
+    let record = ErrorRecord {
+        code,
+        count,
+        uptime_secs: Instant::now().as_secs() as u32,
+    };
+    let persisted = Persisted {
+        magic: MAGIC,
+        record: record.to_bytes(),
+    };
+    // SAFETY: see `LAST_ERROR`
+    unsafe { addr_of_mut!(LAST_ERROR).write_volatile(MaybeUninit::new(persisted)) };
+    code
+}
+
+/// Reset the whole chip, keeping the last error record
+pub fn reset() -> ! {
+    warn!("[error] resetting");
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Record an error there is nothing to recover with, show it, then reset
+pub async fn fatal(error: Error, display: &AsyncDisplay) -> ! {
+    let code = record(&error);
+    display.indicate(Indicator::Error(code)).await;
+    Timer::after(Duration::from_secs(5)).await;
+    reset()
+}
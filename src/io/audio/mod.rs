@@ -4,28 +4,19 @@ use embassy_sync::{
     blocking_mutex::raw::ThreadModeRawMutex,
     channel::{Channel, Sender},
 };
-use microbit_bsp::speaker::{self, Note, Pitch};
-use microbit_bsp::{
-    embassy_nrf::{
-        peripherals::{P0_00, PWM0},
-        pwm::SimplePwm,
-    },
-    speaker::NamedPitch::*,
+use embassy_time::{Duration, Timer};
+use gamepad_core::{
+    audio::{AudioAction, Note, Volume},
+    hal::ToneOutput,
+};
+use microbit_bsp::embassy_nrf::{
+    peripherals::{P0_00, PWM0},
+    pwm::{Prescaler, SimplePwm},
 };
 
 pub static AUDIO_CHANNEL: Channel<ThreadModeRawMutex, AudioAction, 64> = Channel::new();
 
-#[allow(unused)]
-pub enum AudioAction {
-    PlayNote(Note),
-    PlayTune(Tune),
-}
-
-pub enum Tune {
-    Connect,
-    Disconnect,
-}
-
+#[derive(Clone, Copy)]
 pub struct AsyncAudio {
     sender: Sender<'static, ThreadModeRawMutex, AudioAction, 64>,
 }
@@ -39,14 +30,33 @@ impl AsyncAudio {
             sender: AUDIO_CHANNEL.sender(),
         }
     }
-    /// Play a note on the speaker
-    #[allow(unused)]
-    pub async fn play_note(&self, note: Note) {
-        self.sender.send(AudioAction::PlayNote(note)).await;
+}
+
+impl ToneOutput for AsyncAudio {
+    async fn apply(&self, action: AudioAction) {
+        self.sender.send(action).await;
     }
-    /// Play a sequence of notes on the speaker
-    pub async fn play_tune(&self, tune: Tune) {
-        self.sender.send(AudioAction::PlayTune(tune)).await;
+}
+
+/// The speaker, driven with a square wave whose duty cycle sets the volume
+struct Speaker {
+    pwm: SimplePwm<'static, PWM0>,
+    volume: Volume,
+}
+
+impl Speaker {
+    async fn play(&mut self, note: &Note) {
+        if note.frequency > 0 && self.volume > Volume::MIN {
+            self.pwm.set_prescaler(Prescaler::Div4);
+            self.pwm.set_period(note.frequency);
+            // half the period high is as loud as it gets
+            let duty = u32::from(self.pwm.max_duty()) * u32::from(self.volume.level())
+                / (2 * u32::from(Volume::MAX.level()));
+            self.pwm.set_duty(0, duty as u16);
+            self.pwm.enable();
+        }
+        Timer::after(Duration::from_millis(note.duration_ms.into())).await;
+        self.pwm.disable();
     }
 }
 
@@ -54,23 +64,21 @@ impl AsyncAudio {
 #[embassy_executor::task]
 async fn audio_driver_task(pwm0: PWM0, speaker: P0_00) {
     info!("Audio driver task started");
-    let pwm = SimplePwm::new_1ch(pwm0, speaker);
-    let mut speaker = speaker::PwmSpeaker::new(pwm);
+    let mut speaker = Speaker {
+        pwm: SimplePwm::new_1ch(pwm0, speaker),
+        volume: Volume::MAX,
+    };
     loop {
         match AUDIO_CHANNEL.receive().await {
             AudioAction::PlayNote(note) => {
                 speaker.play(&note).await;
             }
-            AudioAction::PlayTune(tune) => match tune {
-                Tune::Connect => {
-                    speaker.play(&Note(Pitch::Named(C4), 200)).await;
-                    speaker.play(&Note(Pitch::Named(G4), 200)).await;
-                }
-                Tune::Disconnect => {
-                    speaker.play(&Note(Pitch::Named(G4), 200)).await;
-                    speaker.play(&Note(Pitch::Named(C4), 200)).await;
+            AudioAction::PlayTune(tune) => {
+                for note in tune.notes() {
+                    speaker.play(note).await;
                 }
-            },
+            }
+            AudioAction::SetVolume(volume) => speaker.volume = volume,
         }
     }
 }
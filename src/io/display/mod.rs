@@ -1,20 +1,31 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use defmt::info;
 use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
 use embassy_sync::{
     blocking_mutex::raw::ThreadModeRawMutex,
     channel::{Channel, Sender},
+    signal::Signal,
+};
+use embassy_time::Duration;
+use gamepad_core::{
+    display::{Bitmap, Brightness, DisplayAction},
+    hal::Display5x5,
 };
-use embassy_time::{Duration, Timer};
 use microbit_bsp::{
-    display::{fonts::*, Brightness, Frame},
+    display::{self, fonts::frame_5x5, Frame},
     LedMatrix,
 };
 
-use self::bitmap::{ARROW_DOWN, ARROW_UP};
-pub mod bitmap;
-
 pub static DISPLAY_CHANNEL: Channel<ThreadModeRawMutex, DisplayAction, 64> = Channel::new();
 
+/// Actions sent to the driver, and those it has finished with
+static QUEUED: AtomicU32 = AtomicU32::new(0);
+static DONE: AtomicU32 = AtomicU32::new(0);
+/// Signalled each time the driver finishes an action
+static FINISHED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
 type DisplayQueue = Sender<'static, ThreadModeRawMutex, DisplayAction, 64>;
 
 #[derive(Clone, Copy)]
@@ -31,133 +42,83 @@ impl AsyncDisplay {
         }
     }
 
-    pub async fn set_brightness(&self, brightness: Brightness) {
-        self.sender
-            .send(DisplayAction::SetBrightness(brightness))
-            .await;
-    }
-
-    #[allow(unused)]
-    pub async fn clear(&self) {
-        self.sender.send(DisplayAction::Clear).await;
-    }
-
-    pub async fn scroll(&self, text: &'static str) {
-        self.sender.send(DisplayAction::Scroll(text)).await;
+    /// Wait until the driver has finished every action sent so far, so the matrix shows what
+    /// the last one left on it
+    pub async fn flushed(&self) {
+        let queued = QUEUED.load(Ordering::Relaxed);
+        while DONE.load(Ordering::Relaxed) < queued {
+            FINISHED.wait().await;
+        }
     }
+}
 
-    /// Non-blocking display
-    pub async fn display(&self, frame: DisplayFrame, duration: Duration) {
-        self.sender
-            .send(DisplayAction::SetFrame {
-                frame,
-                duration: Some(duration),
-            })
-            .await;
-    }
-    /// Blocking display
-    pub async fn display_blocking(&self, frame: DisplayFrame, duration: Duration) {
-        self.sender
-            .send(DisplayAction::SetFrame {
-                frame,
-                duration: Some(duration),
-            })
-            .await;
-        Timer::after(duration).await;
+impl Display5x5 for AsyncDisplay {
+    async fn apply(&self, action: DisplayAction) {
+        self.sender.send(action).await;
+        QUEUED.fetch_add(1, Ordering::Relaxed);
     }
 }
 
-#[allow(unused)]
-pub enum DisplayFrame {
-    DisplayFrame(Frame<5, 5>),
-    /// Display a single pixel at the given coordinates, where (0,0) is the center of the display.
-    Coord {
-        x: i8,
-        y: i8,
-    },
-    Letter(char),
-    Heart,
-    Smile,
-    Sad,
-    QuestionMark,
-    Left,
-    Right,
-    Up,
-    Down,
+fn to_frame(bitmap: Bitmap) -> Frame<5, 5> {
+    frame_5x5(&bitmap.rows())
 }
 
-impl DisplayFrame {
-    fn to_frame(&self) -> Frame<5, 5> {
-        match self {
-            DisplayFrame::DisplayFrame(frame) => frame.clone(),
-            DisplayFrame::Heart => bitmap::HEART,
-            DisplayFrame::Smile => bitmap::SMILE,
-            DisplayFrame::Sad => bitmap::SAD,
-            DisplayFrame::QuestionMark => bitmap::QUESTION_MARK,
-            DisplayFrame::Left => ARROW_LEFT,
-            DisplayFrame::Right => ARROW_RIGHT,
-            DisplayFrame::Up => ARROW_UP,
-            DisplayFrame::Down => ARROW_DOWN,
-            DisplayFrame::Coord { x, y } => {
-                let mut frame = Frame::empty();
-                // convert from cartesian coordinates to display coordinates
-                let x = (x + 2).clamp(0, 4);
-                let y = (y + 2).clamp(0, 4);
-                frame.set(x as usize, y as usize);
-                frame
-            }
-            DisplayFrame::Letter(c) => {
-                // temporary
-                match c {
-                    'A' => bitmap::SMILE,
-                    'B' => bitmap::SAD,
-                    'C' => ARROW_LEFT,
-                    'D' => ARROW_UP,
-                    'E' => ARROW_DOWN,
-                    'F' => ARROW_RIGHT,
-                    _ => bitmap::QUESTION_MARK,
-                }
-            }
-        }
-    }
+fn to_brightness(brightness: Brightness) -> display::Brightness {
+    display::Brightness::new(brightness.level())
 }
 
-#[allow(unused)]
-pub enum DisplayAction {
-    /// Set the brightness of the display.
-    SetBrightness(Brightness),
-    /// Display a frame.
-    SetFrame {
-        frame: DisplayFrame,
-        duration: Option<Duration>,
-    },
-    Clear,
-    Scroll(&'static str),
-}
+/// How long a held frame is shown for between checks of the queue
+const HOLD_SLICE: Duration = Duration::from_secs(1);
 
 /// A task to update the display asynchronously, will wait for new inputs to be sent to it from a queue.
 #[embassy_executor::task]
 async fn display_driver_task(mut display: LedMatrix) {
     info!("Display driver task started");
+    // a frame shown without a duration stays up until something else is shown
+    let mut held: Option<Frame<5, 5>> = None;
     loop {
-        match DISPLAY_CHANNEL.receive().await {
+        let action = match &held {
+            Some(frame) => {
+                match select(
+                    display.display(frame.clone(), HOLD_SLICE),
+                    DISPLAY_CHANNEL.receive(),
+                )
+                .await
+                {
+                    Either::First(()) => continue,
+                    Either::Second(action) => action,
+                }
+            }
+            None => DISPLAY_CHANNEL.receive().await,
+        };
+        match action {
             DisplayAction::SetBrightness(brightness) => {
-                display.set_brightness(brightness);
+                display.set_brightness(to_brightness(brightness));
             }
             DisplayAction::Clear => {
+                held = None;
                 display.clear();
             }
             DisplayAction::Scroll(text) => {
+                held = None;
                 display.scroll(text).await;
             }
-            DisplayAction::SetFrame { frame, duration } => match duration {
-                Some(duration) => {
-                    display.display(frame.to_frame(), duration).await;
-                }
-                None => {
-                    unimplemented!("Displaying a frame without a duration is not yet implemented")
+            DisplayAction::ScrollName(name) => {
+                held = None;
+                display.scroll(name.as_str()).await;
+            }
+            DisplayAction::SetFrame { frame, duration } => {
+                let frame = to_frame(frame.to_bitmap());
+                match duration {
+                    Some(duration) => {
+                        held = None;
+                        display.display(frame, duration).await;
+                    }
+                    None => held = Some(frame),
                 }
-            },
+            }
         }
+        DONE.fetch_add(1, Ordering::Relaxed);
+        FINISHED.signal(());
     }
 }
@@ -0,0 +1,53 @@
+//! The flash region reserved for the config store by `memory.x`.
+
+use embedded_storage_async::nor_flash::{NorFlash, ReadNorFlash};
+use gamepad_core::hal::Flash;
+use microbit_bsp::{ble::MultiprotocolServiceLayer, embassy_nrf::peripherals::NVMC};
+use nrf_mpsl::FlashError;
+
+/// Start of the `CONFIG` region in `memory.x`
+const START: u32 = 0x7_c000;
+/// Length of the `CONFIG` region in pages
+const PAGES: u32 = 4;
+
+/// The config region, written and erased in timeslots granted by the MPSL so the flash
+/// controller never stalls the CPU while the radio needs it. Each operation waits for its
+/// timeslot, so the store only writes when a setting changes.
+pub struct ConfigFlash {
+    flash: nrf_mpsl::Flash<'static>,
+}
+
+impl ConfigFlash {
+    pub fn take(mpsl: &'static MultiprotocolServiceLayer<'static>) -> Self {
+        // SAFETY: the board support crate doesn't hand out the flash controller, and this is
+        // its only user
+        let nvmc = unsafe { NVMC::steal() };
+        Self {
+            flash: nrf_mpsl::Flash::take(mpsl, nvmc),
+        }
+    }
+}
+
+impl Flash for ConfigFlash {
+    type Error = FlashError;
+
+    const PAGE_SIZE: u32 = <nrf_mpsl::Flash as NorFlash>::ERASE_SIZE as u32;
+    const WRITE_SIZE: u32 = <nrf_mpsl::Flash as NorFlash>::WRITE_SIZE as u32;
+
+    fn pages(&self) -> u32 {
+        PAGES
+    }
+
+    async fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.flash.read(START + offset, buf).await
+    }
+
+    async fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error> {
+        self.flash.write(START + offset, data).await
+    }
+
+    async fn erase(&mut self, offset: u32) -> Result<(), Self::Error> {
+        let from = START + offset;
+        self.flash.erase(from, from + Self::PAGE_SIZE).await
+    }
+}
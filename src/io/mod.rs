@@ -1,16 +1,57 @@
 pub mod audio;
 pub mod display;
+pub mod flash;
+pub mod motion;
+pub mod power;
+pub mod recording;
 
+use gamepad_core::{hal::DigitalInput, input::ButtonId};
+use gamepad_protocol::RawInput;
 use microbit_bsp::embassy_nrf::{
     bind_interrupts,
     gpio::{AnyPin, Input, Pull},
-    saadc,
+    peripherals::TWISPI0,
+    saadc, twim,
 };
 
 bind_interrupts!(pub struct Irqs {
     SAADC => saadc::InterruptHandler;
+    SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0 => twim::InterruptHandler<TWISPI0>;
 });
 
-pub fn to_button(pin: AnyPin) -> Input<'static> {
-    Input::new(pin, Pull::Up)
+/// A gamepad button, pulled up so it reads low while pressed
+pub struct ButtonInput {
+    input: Input<'static>,
+    button: ButtonId,
+}
+
+impl ButtonInput {
+    pub fn new(input: Input<'static>, button: ButtonId) -> Self {
+        Self { input, button }
+    }
+
+    fn record(&self, low: bool) {
+        let button = self.button;
+        recording::record(RawInput::Edge { button, low });
+    }
+}
+
+impl DigitalInput for ButtonInput {
+    async fn wait_for_low(&mut self) {
+        self.input.wait_for_low().await;
+        self.record(true);
+    }
+
+    async fn wait_for_high(&mut self) {
+        self.input.wait_for_high().await;
+        self.record(false);
+    }
+
+    fn is_low(&mut self) -> bool {
+        self.input.is_low()
+    }
+}
+
+pub fn to_button(pin: AnyPin, button: ButtonId) -> ButtonInput {
+    ButtonInput::new(Input::new(pin, Pull::Up), button)
 }
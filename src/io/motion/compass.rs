@@ -0,0 +1,152 @@
+use core::f32::consts::PI;
+use gamepad_core::display::Bitmap;
+use libm::{atan2f, cosf, sinf};
+
+use super::MotionSample;
+
+/// Minimum spread (nT) each magnetometer axis must cover before calibration is accepted.
+const CALIBRATION_SPAN: i32 = 60_000;
+
+/// Hard and soft iron correction for the magnetometer
+#[derive(Clone, Copy, defmt::Format)]
+pub struct Calibration {
+    offset: [f32; 3],
+    scale: [f32; 3],
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            offset: [0.0; 3],
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl Calibration {
+    /// Stored as the offsets then the scales, little endian `f32`s
+    pub const SIZE: usize = 24;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        for (i, value) in self.offset.iter().chain(&self.scale).enumerate() {
+            bytes[i * 4..][..4].copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// `None` unless `bytes` is a stored calibration
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
+        let value = |i: usize| f32::from_le_bytes(bytes[i * 4..][..4].try_into().unwrap());
+        Some(Self {
+            offset: core::array::from_fn(value),
+            scale: core::array::from_fn(|i| value(i + 3)),
+        })
+    }
+
+    fn apply(&self, raw: [i32; 3]) -> [f32; 3] {
+        core::array::from_fn(|i| (raw[i] as f32 - self.offset[i]) * self.scale[i])
+    }
+}
+
+/// Collects magnetometer extremes while the user waves the controller in a figure-eight
+pub struct CalibrationRoutine {
+    min: [i32; 3],
+    max: [i32; 3],
+}
+
+impl Default for CalibrationRoutine {
+    fn default() -> Self {
+        Self {
+            min: [i32::MAX; 3],
+            max: [i32::MIN; 3],
+        }
+    }
+}
+
+impl CalibrationRoutine {
+    pub fn add(&mut self, mag: [i32; 3]) {
+        self.min = core::array::from_fn(|i| self.min[i].min(mag[i]));
+        self.max = core::array::from_fn(|i| self.max[i].max(mag[i]));
+    }
+
+    fn span(&self, axis: usize) -> i32 {
+        self.max[axis].saturating_sub(self.min[axis]).max(0)
+    }
+
+    /// Number of LEDs (0..=25) to light, showing how much of the required range has been covered
+    pub fn progress(&self) -> usize {
+        let covered: i32 = (0..3).map(|i| self.span(i).min(CALIBRATION_SPAN)).sum();
+        (covered * 25 / (CALIBRATION_SPAN * 3)) as usize
+    }
+
+    /// Render the progress as a filling 5x5 frame
+    pub fn progress_frame(&self) -> Bitmap {
+        let mut frame = Bitmap::empty();
+        for led in 0..self.progress() {
+            frame.set(led % 5, led / 5);
+        }
+        frame
+    }
+
+    pub fn is_complete(&self) -> bool {
+        (0..3).all(|i| self.span(i) >= CALIBRATION_SPAN)
+    }
+
+    /// Derive the hard iron offset (centre of the extremes) and soft iron scale (axis spread
+    /// normalised to the mean spread)
+    pub fn finish(&self) -> Calibration {
+        let mean = (0..3).map(|i| self.span(i) as f32).sum::<f32>() / 3.0;
+        Calibration {
+            offset: core::array::from_fn(|i| (self.max[i] as f32 + self.min[i] as f32) / 2.0),
+            scale: core::array::from_fn(|i| match self.span(i) {
+                0 => 1.0,
+                span => mean / span as f32,
+            }),
+        }
+    }
+}
+
+/// A tilt compensated compass
+#[derive(Default)]
+pub struct Compass {
+    calibration: Calibration,
+}
+
+impl Compass {
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    /// Magnetic field with the hard and soft iron correction applied
+    pub fn field(&self, raw: [i32; 3]) -> [f32; 3] {
+        self.calibration.apply(raw)
+    }
+
+    /// Heading in whole degrees, 0..=359, clockwise from magnetic north
+    pub fn heading(&self, sample: &MotionSample) -> u16 {
+        let [ax, ay, az] = sample.accel.map(|a| a as f32);
+        let [mx, my, mz] = self.field(sample.mag);
+        // project the magnetic field onto the horizontal plane using the gravity vector
+        let roll = atan2f(ay, az);
+        let pitch = atan2f(-ax, ay * sinf(roll) + az * cosf(roll));
+        let by = mz * sinf(roll) - my * cosf(roll);
+        let bx = mx * cosf(pitch) + my * sinf(pitch) * sinf(roll) + mz * sinf(pitch) * cosf(roll);
+        let degrees = atan2f(-by, bx) * 180.0 / PI;
+        (degrees as i32).rem_euclid(360) as u16
+    }
+}
+
+/// Signed shortest rotation from `reference` to `heading`, in -180..180 degrees
+pub fn heading_delta(heading: u16, reference: u16) -> i16 {
+    (heading as i16 - reference as i16 + 540).rem_euclid(360) - 180
+}
+
+/// Map a heading onto a stick axis (-3..=3) relative to a reference heading,
+/// saturating at a quarter turn either side.
+pub fn heading_to_axis(heading: u16, reference: u16) -> i8 {
+    (heading_delta(heading, reference).clamp(-90, 90) / 30) as i8
+}
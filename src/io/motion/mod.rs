@@ -0,0 +1,69 @@
+pub mod compass;
+
+use defmt::{info, warn};
+use embassy_time::Delay;
+use lsm303agr::{
+    interface::I2cInterface, mode::MagContinuous, AccelMode, AccelOutputDataRate, Lsm303agr,
+    MagMode, MagOutputDataRate,
+};
+use microbit_bsp::embassy_nrf::{
+    peripherals::{P0_08, P0_16, TWISPI0},
+    twim::{self, Twim},
+};
+
+use super::Irqs;
+
+type Lsm303 = Lsm303agr<I2cInterface<Twim<'static, TWISPI0>>, MagContinuous>;
+
+pub type MotionError = lsm303agr::Error<twim::Error>;
+
+/// A single reading of the accelerometer (milli-g) and magnetometer (nano-tesla)
+#[derive(Clone, Copy, defmt::Format)]
+pub struct MotionSample {
+    pub accel: [i32; 3],
+    pub mag: [i32; 3],
+}
+
+/// The on-board LSM303AGR accelerometer and magnetometer
+pub struct MotionSensor {
+    sensor: Lsm303,
+}
+
+impl MotionSensor {
+    /// Bring up the sensor on the internal I2C bus
+    pub fn new(twim: TWISPI0, sda: P0_16, scl: P0_08) -> Result<Self, MotionError> {
+        let i2c = Twim::new(twim, Irqs, sda, scl, twim::Config::default());
+        let mut sensor = Lsm303agr::new_with_i2c(i2c);
+        let mut delay = Delay;
+        sensor.init()?;
+        sensor.set_accel_mode_and_odr(&mut delay, AccelMode::Normal, AccelOutputDataRate::Hz50)?;
+        sensor.set_mag_mode_and_odr(
+            &mut delay,
+            MagMode::HighResolution,
+            MagOutputDataRate::Hz50,
+        )?;
+        let sensor = sensor.into_mag_continuous().map_err(|e| e.error)?;
+        info!("motion sensor online");
+        Ok(Self { sensor })
+    }
+
+    /// Read the latest acceleration and magnetic field, if the bus is healthy
+    pub fn sample(&mut self) -> Option<MotionSample> {
+        let accel = self.sensor.acceleration();
+        let mag = self.sensor.magnetic_field();
+        match (accel, mag) {
+            (Ok(accel), Ok(mag)) => {
+                let (ax, ay, az) = accel.xyz_mg();
+                let (mx, my, mz) = mag.xyz_nt();
+                Some(MotionSample {
+                    accel: [ax, ay, az],
+                    mag: [mx, my, mz],
+                })
+            }
+            _ => {
+                warn!("motion sensor read failed");
+                None
+            }
+        }
+    }
+}
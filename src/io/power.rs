@@ -0,0 +1,26 @@
+//! System OFF, the nRF52833's deepest sleep. Only the GPIO sense logic stays powered, and a
+//! press of A or B resets the chip, so waking up is an ordinary boot.
+
+use defmt::info;
+use microbit_bsp::embassy_nrf::pac;
+
+/// Port 0 pins of buttons A and B
+const WAKE_PINS: [usize; 2] = [14, 23];
+
+/// Turn everything off until A or B is pressed. The display should already be blank, as
+/// pins keep their levels while off.
+pub fn system_off() -> ! {
+    info!("[power] system off");
+    // SAFETY: both buttons are already inputs, this only adds sensing to their configuration,
+    // and nothing else runs once SYSTEMOFF is written
+    let p = unsafe { pac::Peripherals::steal() };
+    for pin in WAKE_PINS {
+        p.P0.pin_cnf[pin].modify(|_, w| w.sense().low());
+    }
+    cortex_m::asm::dsb();
+    p.POWER.systemoff.write(|w| w.systemoff().enter());
+    // a debugger emulates System OFF, so this is only reached while one is attached
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
@@ -0,0 +1,40 @@
+//! The raw input recorder, filled by the button, stick and motion drivers while a central has
+//! asked for it and downloaded through the recording service.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::ThreadModeRawMutex, Mutex};
+use embassy_time::Instant;
+use gamepad_core::recording::Recorder;
+use gamepad_protocol::{RawInput, Sample};
+use heapless::Vec;
+
+/// Samples kept, around ten seconds of stick and accelerometer readings
+const CAPACITY: usize = 1024;
+
+static RECORDER: Mutex<ThreadModeRawMutex, RefCell<Recorder<CAPACITY>>> =
+    Mutex::new(RefCell::new(Recorder::new()));
+
+/// Keep `input` if recording is on
+pub fn record(input: RawInput) {
+    RECORDER.lock(|recorder| recorder.borrow_mut().record(Instant::now(), input));
+}
+
+/// Start recording from empty
+pub fn start() {
+    RECORDER.lock(|recorder| recorder.borrow_mut().start(Instant::now()));
+}
+
+pub fn stop() {
+    RECORDER.lock(|recorder| recorder.borrow_mut().stop());
+}
+
+/// How many samples are held
+pub fn len() -> usize {
+    RECORDER.lock(|recorder| recorder.borrow().len())
+}
+
+/// Up to `M` samples, starting `from` places after the oldest, copied out under one lock
+pub fn chunk<const M: usize>(from: usize) -> Vec<Sample, M> {
+    RECORDER.lock(|recorder| recorder.borrow().chunk(from))
+}
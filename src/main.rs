@@ -2,75 +2,357 @@
 #![no_main]
 
 mod ble;
+mod config;
+mod error;
 mod io;
 
+use core::cell::Cell;
+
 use defmt_rtt as _;
 use panic_probe as _;
 
-use defmt::info;
+use defmt::{info, warn};
 use embassy_executor::Spawner;
-use embassy_time::Duration;
-use microbit_bsp::{display::Brightness, embassy_nrf::gpio::Pin as _, Microbit};
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
+use embassy_time::{Duration, Timer};
+use gamepad_core::{
+    advertising::AdvertisingConfig,
+    config::Key,
+    controller::{perform, Controller, Event, State},
+    error::Recovery,
+    hal::{Display5x5, ToneOutput},
+    hid::{HidMode, KeyboardMouse, MediaRemote},
+    input::{analog_stick_task, buttons_task, ButtonId, GamepadInputs},
+    power::{idle_watch, Activity, ActivitySink, PowerConfig},
+    profile::{Chord, ChordSink},
+    scheduler::ReportScheduler,
+};
+use gamepad_protocol::Setting;
+use microbit_bsp::{embassy_nrf::gpio::Pin as _, Microbit};
+use static_cell::StaticCell;
 
 use crate::{
     ble::{
+        advertiser::Advertised,
+        centrals::{admit_companions, serve_companions},
+        config::LiveSettings,
+        diagnostics::{connection_interval, publish_last_error, reset_link_status, GattLink},
         gatt::gatt_server_task,
-        hid::{buttons_task, GamepadInputs},
-        stick::{analog_stick_task, init_analog_adc},
-        BleServer,
+        hid::{reset_inputs, GattReportSink},
+        hid_device::GattHidOutput,
+        motion::{calibrate, motion_task, CALIBRATION_REQUESTED},
+        mpsl_task,
+        recording::download_task,
+        stick::init_analog_adc,
+        BleCentrals, BleServer, RESTART_STACK,
     },
+    error::Error,
     io::{
-        audio::{AsyncAudio, Tune},
-        display::{AsyncDisplay, DisplayFrame::*},
-        to_button,
+        audio::AsyncAudio,
+        display::AsyncDisplay,
+        motion::{
+            compass::{Calibration, Compass},
+            MotionSensor,
+        },
+        power, to_button, ButtonInput,
     },
 };
 
+/// How long to show an error before trying again
+const ERROR_RECOVERY: Duration = Duration::from_secs(3);
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     info!("Hello World!");
-    let name = "Rust Gamepad";
     let board = Microbit::new(Default::default());
 
     // Spawn Async Embassy Tasks
     let display = AsyncDisplay::new(spawner, board.display);
     let speaker = AsyncAudio::new(spawner, board.pwm0, board.speaker);
-    let (sdc, mpsl) = board
-        .ble
-        .init(board.timer0, board.rng)
-        .expect("BLE stack failed to initialize");
-    let (server, mut advertiser) =
-        BleServer::start_gatt(name, spawner, sdc, mpsl).expect("Failed to start GATT server");
+    // hold A and B while powering on to forget every saved setting, or A alone to move on to
+    // the next HID mode
+    let factory_reset = board.btn_a.is_low() && board.btn_b.is_low();
+    let switch_mode = board.btn_a.is_low() && !factory_reset;
+    if factory_reset {
+        info!("[main] factory reset");
+    }
+    // the config store writes flash in timeslots from the MPSL, so the radio comes up first
+    let Ok((sdc, mpsl)) = board.ble.init(board.timer0, board.rng) else {
+        error::fatal(Error::BleInit, &display).await
+    };
+    spawner.must_spawn(mpsl_task(mpsl));
+    config::init(mpsl, factory_reset).await;
+    let settings = Cell::new(config::settings().await);
+    if switch_mode {
+        let mut switched = settings.get();
+        switched.hid_mode = switched.hid_mode.next();
+        settings.set(switched);
+        info!("[main] switched to {:?}", switched.hid_mode);
+        config::save_setting(&Setting::HidMode(switched.hid_mode)).await;
+        display.scroll(switched.hid_mode.label()).await;
+    }
+    display.set_brightness(settings.get().brightness).await;
+    speaker.set_volume(settings.get().volume).await;
+    let mut controller = Controller::new();
+    perform(controller.start(), &display, &speaker).await;
+
+    let advertised = Advertised {
+        name: settings.get().name,
+        player: 0,
+        mode: settings.get().hid_mode,
+    };
+    let (server, hid_services, mut advertiser, stack) =
+        match BleServer::start_gatt(advertised, spawner, sdc) {
+            Ok(started) => started,
+            Err(e) => error::fatal(e, &display).await,
+        };
+    if let Some(index) = config::load::<u8>(Key::PlayerIndex).await {
+        if let Err(e) = server.set(&server.player.index, &index) {
+            error::record(&e.into());
+        }
+    }
+    let live = LiveSettings {
+        settings: &settings,
+        display: &display,
+        speaker: &speaker,
+        mode: advertised.mode,
+    };
+    if let Err(e) = live.publish(server) {
+        error::record(&e);
+    }
 
     let mut gamepad_buttons = GamepadInputs::new(
-        server,
-        board.btn_a,
-        board.btn_b,
-        to_button(board.p12.degrade()),
-        to_button(board.p13.degrade()),
-        to_button(board.p14.degrade()),
-        to_button(board.p15.degrade()),
+        ButtonInput::new(board.btn_a, ButtonId::A),
+        ButtonInput::new(board.btn_b, ButtonId::B),
+        to_button(board.p12.degrade(), ButtonId::C),
+        to_button(board.p13.degrade(), ButtonId::D),
+        to_button(board.p14.degrade(), ButtonId::E),
+        to_button(board.p15.degrade(), ButtonId::F),
     );
 
     let mut analog_stick = init_analog_adc(board.p1, board.p2, board.saadc);
+    // carry on without motion if the sensor doesn't respond
+    let mut motion_sensor =
+        match MotionSensor::new(board.twispi0, board.i2c_int_sda, board.i2c_int_scl) {
+            Ok(sensor) => Some(sensor),
+            Err(e) => {
+                error::record(&e.into());
+                None
+            }
+        };
+    let mut compass = Compass::default();
+    if let Some(calibration) = config::get(Key::CompassCalibration)
+        .await
+        .and_then(|bytes| Calibration::from_bytes(&bytes))
+    {
+        compass.set_calibration(calibration);
+    }
 
-    display.set_brightness(Brightness::MAX).await;
-    display.scroll("BLE!").await;
+    let power = PowerConfig::default();
+    let activity = Activity::new();
 
-    // Main loop
+    // Main loop, the controller decides what happens next and this carries it out
+    let centrals = {
+        static CENTRALS: StaticCell<BleCentrals> = StaticCell::new();
+        &*CENTRALS.init(BleCentrals::new())
+    };
+    spawner.must_spawn(download_task(server, centrals));
+    // PHY and packet length are asked for once per connection, not on every return to Connected
+    let mut negotiated = false;
+    let mut event = Event::Booted;
     loop {
-        display.display(QuestionMark, Duration::from_secs(2)).await;
-        // advertise for connections
-        if let Ok(conn) = advertiser.advertise().await {
-            let pause = Duration::from_secs(1);
-            speaker.play_tune(Tune::Connect).await;
-            display.display_blocking(Heart, pause).await;
-
-            let gatt = gatt_server_task(server, &conn);
-            let buttons = buttons_task(&mut gamepad_buttons, &conn, &display);
-            let analog = analog_stick_task(server, &conn, &mut analog_stick, &display);
-            embassy_futures::select::select3(gatt, buttons, analog).await;
-            speaker.play_tune(Tune::Disconnect).await;
+        info!("[main] {:?} in {:?}", event, controller.state());
+        if let Err(e) = publish_last_error(server) {
+            error::record(&e);
+        }
+        // only the primary is let go, companions stay until the board sleeps
+        if perform(controller.handle(event), &display, &speaker).await {
+            if let Some(conn) = centrals.primary() {
+                conn.disconnect();
+            }
+            centrals.set_primary(None);
         }
+        let connection = centrals.primary();
+        event = match (controller.state(), connection.as_ref()) {
+            (State::Booting, _) => Event::Booted,
+            (State::Advertising(phase), _) => {
+                centrals.set_primary(None);
+                // as last written to the config service, taken up each time a phase starts
+                let advertising = AdvertisingConfig::from(&settings.get());
+                let timing = advertising.timing(phase);
+                let primary = advertiser.advertise_current(timing, || live.advertised(server));
+                let timeout = Timer::after(timing.timeout);
+                let companions = serve_companions(server, centrals, &live);
+                match select3(primary, timeout, companions).await {
+                    Either3::First(Ok(conn)) => {
+                        centrals.set_primary(Some(conn));
+                        negotiated = false;
+                        if let Err(e) = reset_link_status(server) {
+                            error::record(&e);
+                        }
+                        Event::Connected
+                    }
+                    Either3::First(Err(e)) => Event::Fault(error::record(&Error::Advertising(e))),
+                    Either3::Second(()) => Event::AdvertisingTimeout,
+                    Either3::Third(never) => match never {},
+                }
+            }
+            (State::Connected, Some(conn)) => {
+                if let Err(e) = reset_inputs(server) {
+                    error::record(&e);
+                }
+                let scheduler = ReportScheduler::new(settings.get().batch_rate_hz.into());
+                // as far as the link has settled, the rest comes to the GATT server task
+                scheduler.follow(connection_interval(server));
+                let sink = ActivitySink {
+                    sink: &scheduler,
+                    activity: &activity,
+                };
+                let chord = Chord::new();
+                let chorded = ChordSink {
+                    sink: &sink,
+                    chord: &chord,
+                };
+                let link = GattLink {
+                    server,
+                    conn,
+                    stack,
+                };
+                let companion_timing = AdvertisingConfig::from(&settings.get()).slow;
+                let gatt = async {
+                    let idle = async {
+                        if !negotiated {
+                            link.negotiate().await;
+                            negotiated = true;
+                        }
+                        idle_watch(&activity, &power, &display, &link, &settings).await
+                    };
+                    let companions = select(
+                        serve_companions(server, centrals, &live),
+                        admit_companions(&mut advertiser, centrals, &companion_timing, || {
+                            live.advertised(server)
+                        }),
+                    );
+                    match select3(
+                        gatt_server_task(server, conn, true, Some(&scheduler), &live),
+                        idle,
+                        companions,
+                    )
+                    .await
+                    {
+                        Either3::First(()) => Event::Disconnected,
+                        Either3::Second(()) => Event::Idle,
+                        Either3::Third(Either::First(never) | Either::Second(never)) => {
+                            match never {}
+                        }
+                    }
+                };
+                let inputs = async {
+                    let buttons = buttons_task(&mut gamepad_buttons, &display, &chorded, &settings);
+                    let analog = analog_stick_task(&mut analog_stick, &display, &sink, &settings);
+                    let profiles = live.switch_on_chord(server, &chord);
+                    match select3(buttons, analog, profiles).await {
+                        Either3::First(Err(never)) | Either3::Second(Err(never)) => match never {},
+                        Either3::First(Ok(())) | Either3::Second(Ok(())) => Event::Disconnected,
+                        Either3::Third(never) => match never {},
+                    }
+                };
+                let notifier = GattReportSink { server, centrals };
+                let hid = GattHidOutput {
+                    server,
+                    services: hid_services,
+                    centrals,
+                };
+                let keyboard_mouse = KeyboardMouse::new(&hid, &settings);
+                let remote = MediaRemote::new(&hid);
+                // the same inputs go to the gamepad services or the HID service, for the
+                // whole connection, a shake plays or pauses a media remote and tilting the board
+                // moves the pointer
+                let mode = live.mode;
+                let motion = async {
+                    let remote = (mode == HidMode::MediaRemote).then_some(&remote);
+                    let pointer = (mode == HidMode::KeyboardMouse).then_some(&keyboard_mouse);
+                    match motion_sensor.as_mut() {
+                        Some(sensor) => {
+                            motion_task(server, centrals, sensor, &compass, remote, pointer).await
+                        }
+                        None => core::future::pending().await,
+                    }
+                };
+                let delivery = async {
+                    match mode {
+                        HidMode::Gamepad => scheduler.run(&notifier).await,
+                        HidMode::KeyboardMouse => {
+                            let keys = scheduler.run(&keyboard_mouse);
+                            match select(keys, keyboard_mouse.pointer()).await {
+                                Either::First(result) | Either::Second(result) => result,
+                            }
+                        }
+                        HidMode::MediaRemote => {
+                            let keys = scheduler.run(&remote);
+                            match select(keys, remote.volume()).await {
+                                Either::First(result) | Either::Second(result) => result,
+                            }
+                        }
+                    }
+                };
+                let event = match select4(gatt, inputs, motion, delivery).await {
+                    Either4::First(event) | Either4::Second(event) => event,
+                    Either4::Third(Ok(event)) => event,
+                    Either4::Third(Err(e)) | Either4::Fourth(Err(e)) => {
+                        error::record(&Error::Notify(e));
+                        Event::NotifyFailed
+                    }
+                    Either4::Fourth(Ok(never)) => match never {},
+                };
+                // undo any dimming by the idle watch
+                display.set_brightness(settings.get().brightness).await;
+                event
+            }
+            (State::Calibrating, Some(conn)) => {
+                let event = match motion_sensor.as_mut() {
+                    Some(sensor) => {
+                        let gatt = gatt_server_task(server, conn, true, None, &live);
+                        let routine = calibrate(sensor, &mut compass, &display);
+                        let companions = serve_companions(server, centrals, &live);
+                        match select3(gatt, routine, companions).await {
+                            Either3::First(()) => Event::Disconnected,
+                            Either3::Second(()) => Event::CalibrationFinished,
+                            Either3::Third(never) => match never {},
+                        }
+                    }
+                    None => Event::CalibrationFinished,
+                };
+                // requests while it ran were for this calibration, and the central can read
+                // that it's over
+                CALIBRATION_REQUESTED.reset();
+                if let Err(e) = server.set(&server.heading.calibrate, &false) {
+                    error::record(&e.into());
+                }
+                event
+            }
+            (State::Connected | State::Calibrating, None) => {
+                warn!("[main] lost track of the connection");
+                Event::Disconnected
+            }
+            // waking up is a reset, which starts advertising again from boot
+            (State::Sleeping, _) => {
+                for conn in centrals.clear() {
+                    conn.disconnect();
+                }
+                // pins keep their levels while off, so the matrix has to be blank first
+                display.flushed().await;
+                power::system_off()
+            }
+            (State::Error(_), _) => {
+                Timer::after(ERROR_RECOVERY).await;
+                match controller.recovery() {
+                    Some(Recovery::Reset) => error::reset(),
+                    Some(Recovery::RestartStack) => RESTART_STACK.signal(()),
+                    Some(Recovery::RestartAdvertising) | None => {}
+                }
+                Event::Recovered
+            }
+        };
     }
 }